@@ -1,18 +1,45 @@
-use bevy::{prelude::*, utils::HashMap};
+use std::{fs, path::PathBuf, time::Duration};
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
 use bevy_simple_text_input::TextInputValue;
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    connectors::ConnectionDirection,
+    control_just_pressed,
     text_input::TextInput,
-    ui_box::Hole,
-    utils::BlockType,
-    wasm::{Message, WASMRequest},
+    ui_box::{Disabled, Hole, HoleValue},
+    utils::{BlockType, HoleType, Language, Languages, Scope},
+    wasm::{download_file, Message, WASMRequest},
     ErrorEvent, GameSets,
 };
 
 #[derive(Debug, Event, Default)]
 pub struct UpdateAst;
 
+/// Sent once the `UpdateAst` events have stopped for `RegenerateDebounce::duration`, this is
+/// when the code actually gets generated
+#[derive(Debug, Event, Default)]
+pub struct RegenerateCode;
+
+#[derive(Debug, Resource)]
+pub struct RegenerateDebounce {
+    pub duration: Duration,
+    timer: Option<Timer>,
+}
+
+impl Default for RegenerateDebounce {
+    fn default() -> Self {
+        Self {
+            duration: Duration::from_millis(150),
+            timer: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct BlockData {
     block_type: BlockType,
@@ -20,6 +47,25 @@ pub struct BlockData {
     position: usize,
 }
 
+impl BlockData {
+    /// The data of a hole that holds a value instead of a block
+    pub fn value(position: usize, value: String) -> Self {
+        Self {
+            block_type: BlockType::default(),
+            data_type: BlockDataType::Value(value),
+            position,
+        }
+    }
+
+    /// Gives the position and the value of the hole if it holds a value
+    pub fn get_value(&self) -> Option<(usize, &str)> {
+        match &self.data_type {
+            BlockDataType::Value(value) => Some((self.position, value.as_str())),
+            BlockDataType::Hole(_) => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum BlockDataType {
     Hole(Entity),
@@ -29,33 +75,86 @@ pub enum BlockDataType {
 #[derive(Debug, Resource, Default)]
 pub struct BlockDataMap {
     pub map: HashMap<Entity, Vec<BlockData>>,
+    /// Values that replace the `{{name}}` placeholders of a block, e.g. the name of a function
+    pub named: HashMap<Entity, Vec<(String, String)>>,
 }
 
 impl BlockDataMap {
-    fn expand_holes(&self, block_entity: Entity, block_type: BlockType) -> Result<String, String> {
-        let Some(data) = self.map.get(&block_entity) else {
-            info!("Block {block_type} doesn't have an entry in the template string");
-            return Ok(block_type.get_template());
-        };
-        let mut data = data.clone();
+    /// Removes the argument from the holes of its owner block
+    pub fn remove_arg(&mut self, owner: Entity, arg: Entity) {
+        if let Some(data) = self.map.get_mut(&owner) {
+            data.retain(|data| data.data_type != BlockDataType::Hole(arg));
+        }
+    }
+
+    fn expand_named(&self, block_entity: Entity, mut template_string: String) -> String {
+        for (name, value) in self.named.get(&block_entity).into_iter().flatten() {
+            template_string = template_string.replace(format!("{{{{{name}}}}}").as_str(), value);
+        }
+        template_string
+    }
 
-        // Make sure that the block is always sorted when we want to get the holes
-        data.sort_by(|data1, data2| data1.position.cmp(&data2.position));
+    /// Fills the holes of the block, the holes that are empty and have no default value are
+    /// replaced with the missing marker of the language and reported in `missing`
+    fn expand_holes(
+        &self,
+        block_entity: Entity,
+        block_type: BlockType,
+        language: &Language,
+        missing: &mut Vec<String>,
+    ) -> Result<String, String> {
+        // INFO: The text of a comment is only for the diagram, so the empty template isn't filled
+        if block_type.is_comment() {
+            return Ok(String::new());
+        }
+        let block_type = language.resolve_block(&block_type)?;
+        let data = self.map.get(&block_entity).cloned().unwrap_or_default();
 
         let mut value: Vec<String> = Vec::with_capacity(block_type.get_holes());
 
-        for (index, data) in data.iter().cloned().enumerate() {
+        for (index, hole_type) in block_type.holes.iter().enumerate() {
+            // INFO: An empty text box counts as an empty hole
+            let Some(data) = data.iter().cloned().find(|data| {
+                data.position == index && data.data_type != BlockDataType::Value(String::new())
+            }) else {
+                let default = language.default_value(hole_type).unwrap_or_else(|| {
+                    missing.push(format!(
+                        "Hole {} of {block_type} is empty, put a block in it",
+                        index + 1
+                    ));
+                    language.missing.clone()
+                });
+                value.push(default);
+                continue;
+            };
             match data.data_type {
                 BlockDataType::Value(val) => {
-                    if block_type.holes[index].valid_input(val.as_str()) {
-                        value.push(val)
+                    if hole_type == &HoleType::Variable && language.is_reserved(&val) {
+                        return Err(format!(
+                            "'{val}' is a reserved word in {} and can't be used as a variable name",
+                            block_type.language
+                        ));
+                    }
+                    if language.valid_input(hole_type, val.as_str()) {
+                        match hole_type {
+                            HoleType::String => value.push(language.escape_string(&val)),
+                            HoleType::List(inner) => value.push(language.format_list(inner, &val)),
+                            HoleType::Variable => value.push(val),
+                            _ => value.push(language.format_literal(&val)),
+                        }
                     } else {
                         return Err(format!("Couldn't get value for {block_type}"));
                     }
                 }
                 BlockDataType::Hole(entity) => {
-                    let val = self.expand_holes(entity, data.block_type)?;
-                    value.push(val);
+                    let is_element = !matches!(data.block_type.value, HoleType::List(_));
+                    let val = self.expand_holes(entity, data.block_type, language, missing)?;
+                    // INFO: A single element in a list hole is a list with one item
+                    if matches!(hole_type, HoleType::List(_)) && is_element {
+                        value.push(format!("[{val}]"));
+                    } else {
+                        value.push(val);
+                    }
                 }
             }
         }
@@ -67,58 +166,309 @@ impl BlockDataMap {
                 .replacen(format!("{{{{{index}}}}}").as_str(), value.as_str(), 1)
                 .clone_into(&mut template_string);
         }
-        Ok(template_string)
+        Ok(self.expand_named(block_entity, template_string))
+    }
+}
+
+/// The parse order of the connection that continues the flow after a block, every other
+/// connection is a branch that is expanded inside of the block's template
+pub const FLOW_ORDER: usize = ConnectionDirection::Bottom.get_parse_order();
+
+/// The generated code and the holes and branches that were empty when it was generated
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GeneratedCode {
+    pub code: String,
+    pub missing: Vec<String>,
+}
+
+impl GeneratedCode {
+    /// Puts all the missing slots in one message
+    pub fn diagnostic(&self) -> Option<String> {
+        if self.missing.is_empty() {
+            None
+        } else {
+            Some(format!(
+                "The code has empty slots:\n{}",
+                self.missing.join("\n")
+            ))
+        }
+    }
+}
+
+/// Replaces every `{{...}}` token that is left in the code with the `marker`
+fn replace_leftover_tokens(code: &str, marker: &str, missing: &mut Vec<String>) -> String {
+    let mut result = String::with_capacity(code.len());
+    let mut rest = code;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        let token = &rest[start..start + end + 2];
+        missing.push(format!("The template token {token} was not filled in"));
+        result.push_str(&rest[..start]);
+        result.push_str(marker);
+        rest = &rest[start + end + 2..];
     }
+    result.push_str(rest);
+    result
 }
 
 #[derive(Resource, Debug, Default)]
 pub struct Ast {
-    pub map: HashMap<Entity, [Option<(Entity, BlockType)>; 3]>,
-    // pub map: HashMap<Entity, Vec<Entity>>,
+    /// The connections of every block indexed by the parse order of their direction
+    pub map: HashMap<Entity, Vec<Option<(Entity, BlockType)>>>,
+    /// The blocks that are skipped when the code is generated
+    pub disabled: HashSet<Entity>,
 }
 
 impl Ast {
+    /// Generates the code of the whole program starting from the start block
+    pub fn generate(
+        &self,
+        start_entity: Entity,
+        start_block: &BlockType,
+        block_data_map: &BlockDataMap,
+        language: &Language,
+    ) -> Result<GeneratedCode, String> {
+        let mut missing = Vec::new();
+        let code = self.traverse_branch(
+            start_entity,
+            start_block,
+            block_data_map,
+            language,
+            0,
+            &mut HashSet::default(),
+            &mut missing,
+        )?;
+        let code = replace_leftover_tokens(&code, &language.missing, &mut missing);
+        Ok(GeneratedCode { code, missing })
+    }
+
+    /// Generates the code for the branch starting at `entity`.
+    ///
+    /// `depth` is the indentation level of the block, it is changed by blocks that open or close
+    /// a scope in the flow and is only visible in languages that have an `indent` set.
+    ///
+    /// `visiting` holds the entities on the current path from the root, so that a line that loops
+    /// back to one of its ancestors is reported as an error instead of recursing forever.
+    ///
+    /// The holes and branches that are empty are added to `missing`
+    #[allow(clippy::too_many_arguments)]
     fn traverse_branch(
         &self,
         entity: Entity,
         block_type: &BlockType,
         block_data_map: &BlockDataMap,
+        language: &Language,
+        depth: usize,
+        visiting: &mut HashSet<Entity>,
+        missing: &mut Vec<String>,
     ) -> Result<String, String> {
+        if !visiting.insert(entity) {
+            return Err(format!(
+                "Block {block_type} connects back to itself, the flow can't contain a cycle"
+            ));
+        }
+        let result = self.expand_branches(
+            entity,
+            block_type,
+            block_data_map,
+            language,
+            depth,
+            visiting,
+            missing,
+        );
+        visiting.remove(&entity);
+        result
+    }
+
+    /// Builds the tree of the program starting from the start block. A line that loops back to
+    /// one of its ancestors is an error like when the code is generated
+    pub fn to_tree(
+        &self,
+        start_entity: Entity,
+        start_block: &BlockType,
+        block_data_map: &BlockDataMap,
+    ) -> Result<AstNode, String> {
+        self.tree_node(
+            start_entity,
+            start_block,
+            block_data_map,
+            &mut HashSet::default(),
+        )
+    }
+
+    fn tree_node(
+        &self,
+        entity: Entity,
+        block_type: &BlockType,
+        block_data_map: &BlockDataMap,
+        visiting: &mut HashSet<Entity>,
+    ) -> Result<AstNode, String> {
+        if !visiting.insert(entity) {
+            return Err(format!(
+                "Block {block_type} connects back to itself, the flow can't contain a cycle"
+            ));
+        }
+        let data = block_data_map.map.get(&entity).cloned().unwrap_or_default();
+        let mut holes = Vec::with_capacity(block_type.get_holes());
+        for index in 0..block_type.get_holes() {
+            let hole = match data.iter().find(|data| data.position == index) {
+                Some(BlockData {
+                    data_type: BlockDataType::Value(value),
+                    ..
+                }) => AstHole::Value(value.clone()),
+                Some(BlockData {
+                    data_type: BlockDataType::Hole(arg),
+                    block_type: arg_type,
+                    ..
+                }) => AstHole::Block(self.tree_node(*arg, arg_type, block_data_map, visiting)?),
+                None => AstHole::Empty,
+            };
+            holes.push(hole);
+        }
+
+        let empty = Vec::new();
+        let connections = self.map.get(&entity).unwrap_or(&empty);
+        let mut branches = vec![None; block_type.get_branches()];
+        // INFO: Like in the code, a branch is put at the place of its connector among the block's
+        for (order, branch) in connections
+            .iter()
+            .enumerate()
+            .filter(|&(order, _)| order != FLOW_ORDER)
+        {
+            let (Some((branch_entity, branch_block_type)), Some(index)) =
+                (branch, block_type.get_branch_index(order))
+            else {
+                continue;
+            };
+            branches[index] = Some(self.tree_node(
+                *branch_entity,
+                branch_block_type,
+                block_data_map,
+                visiting,
+            )?);
+        }
+        let next = match connections.get(FLOW_ORDER).cloned().flatten() {
+            Some((next_entity, next_block_type)) => Some(Box::new(self.tree_node(
+                next_entity,
+                &next_block_type,
+                block_data_map,
+                visiting,
+            )?)),
+            None => None,
+        };
+        visiting.remove(&entity);
+
+        Ok(AstNode {
+            name: block_type.name.clone(),
+            disabled: self.disabled.contains(&entity),
+            holes,
+            branches,
+            next,
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn expand_branches(
+        &self,
+        entity: Entity,
+        block_type: &BlockType,
+        block_data_map: &BlockDataMap,
+        language: &Language,
+        depth: usize,
+        visiting: &mut HashSet<Entity>,
+        missing: &mut Vec<String>,
+    ) -> Result<String, String> {
+        // INFO: A disabled block is skipped with its holes and branches, the flow goes on after it
+        if self.disabled.contains(&entity) {
+            return match self
+                .map
+                .get(&entity)
+                .and_then(|branches| branches.get(FLOW_ORDER).cloned().flatten())
+            {
+                Some((branch_entity, branch_block_type)) => self.traverse_branch(
+                    branch_entity,
+                    &branch_block_type,
+                    block_data_map,
+                    language,
+                    depth,
+                    visiting,
+                    missing,
+                ),
+                None => Ok(String::new()),
+            };
+        }
+        let block_type = &language.resolve_block(block_type)?;
+        let (depth, next_depth) = match block_type.scope {
+            Scope::Open => (depth, depth + 1),
+            Scope::Close => (depth.saturating_sub(1), depth.saturating_sub(1)),
+            Scope::None => (depth, depth),
+        };
+
         // Expand the holes inside the block
-        let mut full_string = block_data_map.expand_holes(entity, block_type.clone())?;
+        let mut full_string =
+            block_data_map.expand_holes(entity, block_type.clone(), language, missing)?;
 
         let hole = block_type.get_holes();
-        let Some(branches) = self.map.get(&entity) else {
-            return Ok(full_string);
-        };
+        let empty = Vec::new();
+        let branches = self.map.get(&entity).unwrap_or(&empty);
 
-        // Expand the left and right branches
-        for (index, branch) in branches
-            .get(0..=1)
-            .unwrap()
+        // Expand the branches in their parse order
+        for (order, branch) in branches
             .iter()
-            .filter(|x| x.is_some())
             .enumerate()
+            .filter(|&(order, _)| order != FLOW_ORDER)
         {
-            match branch.to_owned() {
-                Some((branch_entity, branch_block_type)) => {
-                    let string =
-                        self.traverse_branch(branch_entity, &branch_block_type, block_data_map)?;
-                    full_string = full_string.replacen(
-                        format!("{{{{{}}}}}", hole + index + 1).as_str(),
-                        string.as_str(),
-                        1,
-                    );
-                }
-                None => unreachable!("This should not be reachable"),
+            // INFO: A branch fills the placeholder of its place among the block's own connectors
+            let (Some((branch_entity, branch_block_type)), Some(index)) =
+                (branch, block_type.get_branch_index(order))
+            else {
+                continue;
+            };
+            // INFO: The branch is generated from the start of the line and indented once,
+            // the rest of the indentation comes from indenting this block
+            let string = self.traverse_branch(
+                *branch_entity,
+                branch_block_type,
+                block_data_map,
+                language,
+                0,
+                visiting,
+                missing,
+            )?;
+            full_string = full_string.replacen(
+                format!("{{{{{}}}}}", hole + index + 1).as_str(),
+                language.indent_code(&string, 1).as_str(),
+                1,
+            );
+        }
+        for index in 0..block_type.get_branches() {
+            let token = format!("{{{{{}}}}}", hole + index + 1);
+            if full_string.contains(&token) {
+                missing.push(format!(
+                    "Branch {} of {block_type} is empty, connect a block to it",
+                    index + 1
+                ));
+                // INFO: The marker is indented like a branch so the block stays valid code
+                full_string =
+                    full_string.replacen(&token, &language.indent_code(&language.missing, 1), 1);
             }
         }
+        let full_string = language.indent_code(&full_string, depth);
 
         // Expand the flow branch
-        match branches.last().and_then(ToOwned::to_owned) {
+        match branches.get(FLOW_ORDER).cloned().flatten() {
             Some((branch_entity, branch_block_type)) => {
-                let string =
-                    self.traverse_branch(branch_entity, &branch_block_type, block_data_map)?;
+                let string = self.traverse_branch(
+                    branch_entity,
+                    &branch_block_type,
+                    block_data_map,
+                    language,
+                    next_depth,
+                    visiting,
+                    missing,
+                )?;
                 if full_string.is_empty() {
                     Ok(string)
                 } else {
@@ -130,6 +480,28 @@ impl Ast {
     }
 }
 
+/// A block of the program and everything that is connected to it, made to be serialized for
+/// tools outside of the app
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AstNode {
+    pub name: String,
+    pub disabled: bool,
+    /// What is in each hole of the block in the order of the holes
+    pub holes: Vec<AstHole>,
+    /// The branches that are inside of the block in their parse order, `None` when nothing is
+    /// connected to one
+    pub branches: Vec<Option<AstNode>>,
+    /// The block that comes after this one in the flow
+    pub next: Option<Box<AstNode>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum AstHole {
+    Empty,
+    Value(String),
+    Block(AstNode),
+}
+
 // TODO: Make specialized events for adding a child to a parent
 // and removing a child from a parent
 
@@ -145,6 +517,23 @@ pub struct RemoveFromAst {
     pub child: Entity,
 }
 
+/// Where the generated code is exported to. The extension is replaced by the one of the language
+#[derive(Debug, Resource, Clone)]
+pub struct ExportConfig {
+    pub path: PathBuf,
+    /// The name of the language the code is generated in, the active language when it is `None`
+    pub target: Option<String>,
+}
+
+impl Default for ExportConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("program"),
+            target: None,
+        }
+    }
+}
+
 pub struct ASTPlugin;
 
 impl ASTPlugin {
@@ -156,6 +545,9 @@ impl ASTPlugin {
         for AddToAst { parent, child } in reader.read().map(ToOwned::to_owned) {
             if let Some((parent, order)) = parent {
                 let value = ast.map.entry(parent).or_default();
+                if value.len() <= order {
+                    value.resize(order + 1, None);
+                }
                 value[order] = Some(child)
             } else {
                 ast.map.entry(child.0).or_default();
@@ -164,23 +556,66 @@ impl ASTPlugin {
         }
     }
 
+    fn sync_disabled(
+        disabled: Query<Entity, Added<Disabled>>,
+        mut enabled: RemovedComponents<Disabled>,
+        mut ast: ResMut<Ast>,
+        mut update_writer: EventWriter<UpdateAst>,
+    ) {
+        let mut changed = false;
+        for entity in &disabled {
+            changed |= ast.disabled.insert(entity);
+        }
+        for entity in enabled.read() {
+            changed |= ast.disabled.remove(&entity);
+        }
+        if changed {
+            update_writer.send(UpdateAst);
+        }
+    }
+
     fn handle_remove_from_ast(mut reader: EventReader<RemoveFromAst>, mut ast: ResMut<Ast>) {
         for event in reader.read() {
             if let Some((parent, order)) = event.parent {
-                let value = ast.map.entry(parent).or_default();
-                value[order] = None;
+                if let Some(value) = ast
+                    .map
+                    .get_mut(&parent)
+                    .and_then(|value| value.get_mut(order))
+                {
+                    *value = None;
+                }
             } else {
                 ast.map.remove_entry(&event.child);
             }
         }
     }
 
-    fn print_ast(
+    fn debounce_update_ast(
+        mut update_reader: EventReader<UpdateAst>,
+        mut debounce: ResMut<RegenerateDebounce>,
+        time: Res<Time>,
+        mut regenerate_writer: EventWriter<RegenerateCode>,
+    ) {
+        // INFO: Every update restarts the timer so typing in a block only regenerates once
+        if update_reader.read().last().is_some() {
+            debounce.timer = Some(Timer::new(debounce.duration, TimerMode::Once));
+        }
+        let Some(timer) = debounce.timer.as_mut() else {
+            return;
+        };
+        if timer.tick(time.delta()).finished() {
+            debounce.timer = None;
+            regenerate_writer.send_default();
+        }
+    }
+
+    pub(crate) fn print_ast(
         ast: Res<Ast>,
         block_data_map: Res<BlockDataMap>,
+        languages: Res<Languages>,
         block_type: Query<(Entity, &BlockType)>,
         mut error_writer: EventWriter<ErrorEvent>,
-        mut update_reader: EventReader<UpdateAst>,
+        mut update_reader: EventReader<RegenerateCode>,
         mut wasm_writer: EventWriter<WASMRequest>,
     ) {
         if update_reader.read().last().is_some() {
@@ -191,8 +626,12 @@ impl ASTPlugin {
                 info!("There is no start block in the world");
                 return;
             };
-            let code = match ast.traverse_branch(start_entity, start_block, block_data_map.as_ref())
-            {
+            let code = match ast.generate(
+                start_entity,
+                start_block,
+                block_data_map.as_ref(),
+                languages.active(),
+            ) {
                 Ok(code) => code,
                 Err(error) => {
                     error_writer.send(ErrorEvent(error));
@@ -200,22 +639,103 @@ impl ASTPlugin {
                 }
             };
             info!("====== Outputed Code ======");
-            info!("{code}");
+            info!("{}", code.code);
+            // INFO: The program is usually incomplete while it is being built, so the empty
+            // slots are only reported when the code is run or exported
+            if let Some(diagnostic) = code.diagnostic() {
+                info!("{diagnostic}");
+            }
+
+            wasm_writer.send(WASMRequest(Message::Code(code.code)));
+        }
+    }
+
+    fn export_code(
+        ast: Res<Ast>,
+        block_data_map: Res<BlockDataMap>,
+        languages: Res<Languages>,
+        block_type: Query<(Entity, &BlockType)>,
+        config: Res<ExportConfig>,
+        mut error_writer: EventWriter<ErrorEvent>,
+    ) {
+        let Some((start_entity, start_block)) = block_type
+            .iter()
+            .find(|(_, block_type)| block_type.name == "Start")
+        else {
+            error_writer.send(ErrorEvent("There is no start block to export".into()));
+            return;
+        };
+        let language = match &config.target {
+            Some(target) => match languages.get(target) {
+                Some(language) => language,
+                None => {
+                    error_writer.send(ErrorEvent(format!(
+                        "Can't export to {target} because the language isn't loaded"
+                    )));
+                    return;
+                }
+            },
+            None => languages.active(),
+        };
+        let code = match ast.generate(start_entity, start_block, block_data_map.as_ref(), language)
+        {
+            Ok(code) => {
+                if let Some(diagnostic) = code.diagnostic() {
+                    error_writer.send(ErrorEvent(diagnostic));
+                }
+                code.code
+            }
+            Err(error) => {
+                error_writer.send(ErrorEvent(error));
+                return;
+            }
+        };
 
-            wasm_writer.send(WASMRequest(Message::Code(code)));
+        let path = config.path.with_extension(&language.extension);
+        if cfg!(target_family = "wasm") {
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+            if let Err(error) = download_file(&file_name, &code) {
+                error_writer.send(ErrorEvent::take_js_error(error));
+            }
+        } else if let Err(error) = fs::write(&path, code) {
+            error_writer.send(ErrorEvent(format!(
+                "Couldn't export the code to {}: {error}",
+                path.display()
+            )));
+        } else {
+            info!("Exported the code to {}", path.display());
         }
     }
 
-    fn get_block_data_hashmap(
+    pub(crate) fn get_block_data_hashmap(
         holes: Query<(Entity, &Hole)>,
         children: Query<&Children>,
         block_type: Query<(Entity, &BlockType)>,
         text_input: Query<(&TextInput, &TextInputValue)>,
+        hole_values: Query<&HoleValue>,
         mut block_map: ResMut<BlockDataMap>,
         mut update_reader: EventReader<UpdateAst>,
+        changed_holes: Query<(), (With<Hole>, Or<(Added<Hole>, Changed<Children>)>)>,
+        mut removed_holes: RemovedComponents<Hole>,
+        changed_values: Query<
+            (),
+            Or<(
+                Changed<HoleValue>,
+                (With<TextInput>, Changed<TextInputValue>),
+            )>,
+        >,
     ) {
-        if update_reader.read().last().is_some() {
+        // INFO: The map is only rebuilt on the frames where something that goes in it changed
+        let updated = update_reader.read().last().is_some();
+        let removed = removed_holes.read().last().is_some();
+        if updated || removed || !changed_holes.is_empty() || !changed_values.is_empty() {
             let mut hashmap: HashMap<Entity, Vec<BlockData>> = HashMap::default();
+            for hole_value in &hole_values {
+                hashmap
+                    .entry(hole_value.owner)
+                    .or_default()
+                    .push(BlockData::value(hole_value.order, hole_value.value.clone()));
+            }
             for (hole_entity, hole) in &holes {
                 let Some((child_entity, child_block)) =
                     children.get(hole_entity).ok().and_then(|children| {
@@ -273,6 +793,9 @@ impl Plugin for ASTPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app.init_resource::<Ast>()
             .init_resource::<BlockDataMap>()
+            .init_resource::<ExportConfig>()
+            .init_resource::<RegenerateDebounce>()
+            .add_event::<RegenerateCode>()
             .add_event::<AddToAst>()
             .add_event::<RemoveFromAst>()
             .add_event::<UpdateAst>()
@@ -282,10 +805,313 @@ impl Plugin for ASTPlugin {
                     Self::get_block_data_hashmap,
                     Self::handle_add_to_ast,
                     Self::handle_remove_from_ast,
+                    Self::sync_disabled,
+                    Self::debounce_update_ast,
                     Self::print_ast,
                 )
                     .chain()
                     .in_set(GameSets::Running),
+            )
+            .add_systems(
+                Update,
+                Self::export_code
+                    .run_if(control_just_pressed(KeyCode::KeyE))
+                    .in_set(GameSets::Running),
             );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(language: &Language, name: &str) -> BlockType {
+        language
+            .get_block(name)
+            .unwrap_or_else(|| panic!("The {name} block should exist"))
+    }
+
+    /// Connects `to` to the connector of `from` that is parsed at `order`
+    fn connect(ast: &mut Ast, from: Entity, order: usize, to: Entity, block_type: &BlockType) {
+        ast.map
+            .entry(from)
+            .or_insert_with(|| vec![None; FLOW_ORDER + 1])[order] = Some((to, block_type.clone()));
+    }
+
+    #[test]
+    fn reports_the_empty_condition_of_an_if() {
+        let language = Language::new();
+        let generated = Ast::default()
+            .generate(
+                Entity::from_raw(1),
+                &block(&language, "If"),
+                &BlockDataMap::default(),
+                &language,
+            )
+            .expect("An empty If should still generate");
+
+        assert!(generated
+            .missing
+            .contains(&"Hole 1 of If is empty, put a block in it".to_owned()));
+        assert!(generated.code.contains(&language.missing));
+        assert!(!generated.code.contains("{{"));
+    }
+
+    #[test]
+    fn marks_the_empty_slots_with_valid_python() {
+        let language = Language::python();
+        let generated = Ast::default()
+            .generate(
+                Entity::from_raw(1),
+                &block(&language, "If"),
+                &BlockDataMap::default(),
+                &language,
+            )
+            .expect("An empty If should still generate");
+        assert_eq!(generated.code, "if ...:\n    ...\nelse:\n    ...");
+    }
+
+    #[test]
+    fn indents_the_body_of_an_if_in_python() {
+        let language = Language::python();
+        let [if_entity, print_entity] = [0, 1].map(Entity::from_raw);
+        let mut ast = Ast::default();
+        connect(
+            &mut ast,
+            if_entity,
+            ConnectionDirection::Left.get_parse_order(),
+            print_entity,
+            &block(&language, "Print"),
+        );
+        let mut data = BlockDataMap::default();
+        data.map
+            .insert(if_entity, vec![BlockData::value(0, "true".into())]);
+        data.map
+            .insert(print_entity, vec![BlockData::value(0, "1".into())]);
+
+        let generated = ast
+            .generate(if_entity, &block(&language, "If"), &data, &language)
+            .expect("The If should generate");
+        assert_eq!(generated.code, "if True:\n    print(1)\nelse:\n    ...");
+    }
+
+    #[test]
+    fn refuses_a_flow_that_loops_back() {
+        let language = Language::new();
+        let print = block(&language, "Print");
+        let [a, b] = [0, 1].map(Entity::from_raw);
+        let mut ast = Ast::default();
+        connect(&mut ast, a, FLOW_ORDER, b, &print);
+        connect(&mut ast, b, FLOW_ORDER, a, &print);
+
+        let result = ast.generate(a, &print, &BlockDataMap::default(), &language);
+        assert!(result.is_err_and(|error| error.contains("cycle")));
+        assert!(ast.to_tree(a, &print, &BlockDataMap::default()).is_err());
+    }
+
+    #[test]
+    fn skips_a_comment_in_the_flow() {
+        let language = Language::new();
+        let start = block(&language, "Start");
+        let print = block(&language, "Print");
+        let [start_entity, comment_entity, print_entity] = [0, 1, 2].map(Entity::from_raw);
+        let mut data = BlockDataMap::default();
+        data.map
+            .insert(comment_entity, vec![BlockData::value(0, "a note".into())]);
+
+        let mut with_comment = Ast::default();
+        connect(
+            &mut with_comment,
+            start_entity,
+            FLOW_ORDER,
+            comment_entity,
+            &block(&language, "Comment"),
+        );
+        connect(
+            &mut with_comment,
+            comment_entity,
+            FLOW_ORDER,
+            print_entity,
+            &print,
+        );
+        let mut without_comment = Ast::default();
+        connect(
+            &mut without_comment,
+            start_entity,
+            FLOW_ORDER,
+            print_entity,
+            &print,
+        );
+
+        let generate = |ast: &Ast| {
+            ast.generate(start_entity, &start, &data, &language)
+                .expect("The flow should generate")
+        };
+        let generated = generate(&with_comment);
+        assert_eq!(generated, generate(&without_comment));
+        assert!(!generated.code.contains("a note"));
+    }
+
+    #[test]
+    fn skips_a_disabled_block() {
+        let language = Language::new();
+        let start = block(&language, "Start");
+        let print = block(&language, "Print");
+        let [start_entity, disabled_entity, print_entity] = [0, 1, 2].map(Entity::from_raw);
+
+        let mut with_disabled = Ast::default();
+        connect(
+            &mut with_disabled,
+            start_entity,
+            FLOW_ORDER,
+            disabled_entity,
+            &print,
+        );
+        connect(
+            &mut with_disabled,
+            disabled_entity,
+            FLOW_ORDER,
+            print_entity,
+            &print,
+        );
+        with_disabled.disabled.insert(disabled_entity);
+        let mut without_disabled = Ast::default();
+        connect(
+            &mut without_disabled,
+            start_entity,
+            FLOW_ORDER,
+            print_entity,
+            &print,
+        );
+
+        let generate = |ast: &Ast| {
+            ast.generate(start_entity, &start, &BlockDataMap::default(), &language)
+                .expect("The flow should generate")
+        };
+        assert_eq!(generate(&with_disabled), generate(&without_disabled));
+    }
+
+    #[test]
+    fn builds_a_tree_with_the_empty_holes_and_branches() {
+        let language = Language::new();
+        let [start_entity, if_entity, print_entity] = [0, 1, 2].map(Entity::from_raw);
+        let mut ast = Ast::default();
+        connect(
+            &mut ast,
+            start_entity,
+            FLOW_ORDER,
+            if_entity,
+            &block(&language, "If"),
+        );
+        connect(
+            &mut ast,
+            if_entity,
+            FLOW_ORDER,
+            print_entity,
+            &block(&language, "Print"),
+        );
+        let mut data = BlockDataMap::default();
+        data.map
+            .insert(print_entity, vec![BlockData::value(0, "done".into())]);
+
+        let tree = ast
+            .to_tree(start_entity, &block(&language, "Start"), &data)
+            .expect("The tree should build");
+        assert_eq!(tree.name, "Start");
+        let if_node = tree.next.expect("The If should follow the Start");
+        assert_eq!(if_node.holes, vec![AstHole::Empty]);
+        assert!(if_node.branches.iter().all(Option::is_none));
+        let print_node = if_node.next.expect("The Print should follow the If");
+        assert_eq!(print_node.holes, vec![AstHole::Value("done".into())]);
+        assert!(print_node.next.is_none());
+    }
+
+    #[test]
+    fn puts_the_body_of_a_function_in_its_only_branch() {
+        let language = Language::new();
+        let [function_entity, print_entity] = [0, 1].map(Entity::from_raw);
+        let mut ast = Ast::default();
+        connect(
+            &mut ast,
+            function_entity,
+            ConnectionDirection::Right.get_parse_order(),
+            print_entity,
+            &block(&language, "Print"),
+        );
+
+        let tree = ast
+            .to_tree(
+                function_entity,
+                &block(&language, "Function"),
+                &BlockDataMap::default(),
+            )
+            .expect("The tree should build");
+        assert_eq!(tree.branches.len(), 1);
+        assert_eq!(
+            tree.branches[0].as_ref().map(|branch| branch.name.as_str()),
+            Some("Print")
+        );
+    }
+
+    #[test]
+    fn generates_the_blocks_of_one_language_in_another() {
+        let javascript = Language::new();
+        let python = Language::python();
+        let start = block(&javascript, "Start");
+        let print = block(&javascript, "Print");
+        let [start_entity, print_entity] = [0, 1].map(Entity::from_raw);
+        let mut ast = Ast::default();
+        connect(&mut ast, start_entity, FLOW_ORDER, print_entity, &print);
+        let mut data = BlockDataMap::default();
+        data.map
+            .insert(print_entity, vec![BlockData::value(0, "1".into())]);
+
+        let generated = ast
+            .generate(start_entity, &start, &data, &python)
+            .expect("Python has the same blocks");
+        assert!(generated.code.contains("print(1)"));
+
+        let mut missing = print.clone();
+        missing.name = "Not a block".into();
+        connect(&mut ast, start_entity, FLOW_ORDER, print_entity, &missing);
+        assert!(ast.generate(start_entity, &start, &data, &python).is_err());
+    }
+
+    #[test]
+    fn generates_the_body_of_a_function() {
+        let language = Language::python();
+        let [start_entity, function_entity, print_entity] = [0, 1, 2].map(Entity::from_raw);
+        let mut ast = Ast::default();
+        connect(
+            &mut ast,
+            start_entity,
+            FLOW_ORDER,
+            function_entity,
+            &block(&language, "Function"),
+        );
+        // INFO: The body comes out of the right connector, the only branch of a Function
+        connect(
+            &mut ast,
+            function_entity,
+            ConnectionDirection::Right.get_parse_order(),
+            print_entity,
+            &block(&language, "Print"),
+        );
+        let mut data = BlockDataMap::default();
+        data.map
+            .insert(print_entity, vec![BlockData::value(0, "1".into())]);
+        data.named.insert(
+            function_entity,
+            vec![
+                ("name".into(), "run".into()),
+                ("args".into(), String::new()),
+            ],
+        );
+
+        let generated = ast
+            .generate(start_entity, &block(&language, "Start"), &data, &language)
+            .expect("The function should generate");
+        assert!(generated.missing.is_empty(), "{:?}", generated.missing);
+        assert!(generated.code.contains("def run():\n    print(1)"));
+    }
+}
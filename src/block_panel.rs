@@ -0,0 +1,218 @@
+use bevy::{prelude::*, ui::FocusPolicy};
+
+use crate::{
+    control_just_pressed,
+    focus::InteractionFocusBundle,
+    theme::{FixedBackground, Theme},
+    ui_box::{BackgroundBox, BlockBundle, SpawnUIBox},
+    utils::{ConceptType, Languages},
+    GameSets,
+};
+
+const CONCEPT_TYPES: [(ConceptType, &str); 3] = [
+    (ConceptType::ControlFlow, "Control Flow"),
+    (ConceptType::Input, "Input"),
+    (ConceptType::Output, "Output"),
+];
+
+/// The panel that lists the blocks of the active language
+#[derive(Debug, Component)]
+pub struct BlockPanel;
+
+/// Spawns the block with this name when pressed
+#[derive(Debug, Component, Clone)]
+pub struct BlockPanelEntry {
+    pub name: String,
+}
+
+#[derive(Resource, Default, Debug, Clone, Copy)]
+struct IsBlockPanelVisible(bool);
+
+#[derive(Bundle)]
+pub struct BlockPanelBundle {
+    node: NodeBundle,
+    marker: BlockPanel,
+    fixed_background: FixedBackground,
+}
+
+impl BlockPanelBundle {
+    fn new() -> Self {
+        Self {
+            node: NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(8.),
+                    top: Val::Px(48.),
+                    flex_direction: FlexDirection::Column,
+                    row_gap: Val::Px(2.),
+                    padding: UiRect::all(Val::Px(8.)),
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::rgba(0., 0., 0., 0.8)),
+                // INFO: Clicks between the entries shouldn't reach the background
+                focus_policy: FocusPolicy::Block,
+                visibility: Visibility::Hidden,
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            marker: BlockPanel,
+            fixed_background: FixedBackground,
+        }
+    }
+}
+
+#[derive(Bundle)]
+pub struct BlockPanelEntryBundle {
+    button: ButtonBundle,
+    entry: BlockPanelEntry,
+}
+
+impl BlockPanelEntryBundle {
+    fn new(name: String) -> Self {
+        Self {
+            button: ButtonBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Row,
+                    align_items: AlignItems::Center,
+                    column_gap: Val::Px(6.),
+                    padding: UiRect::axes(Val::Px(4.), Val::Px(2.)),
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::NONE),
+                ..default()
+            },
+            entry: BlockPanelEntry { name },
+        }
+    }
+}
+
+/// A side panel with every block of the language grouped by their concept, it is toggled with
+/// Ctrl+B
+pub struct BlockPanelPlugin;
+
+impl BlockPanelPlugin {
+    fn spawn_block_panel(mut commands: Commands) {
+        commands.spawn(BlockPanelBundle::new());
+    }
+
+    fn toggle_block_panel(
+        mut is_visible: ResMut<IsBlockPanelVisible>,
+        mut panel: Query<&mut Visibility, With<BlockPanel>>,
+    ) {
+        is_visible.0 = !is_visible.0;
+        for mut visibility in &mut panel {
+            *visibility = if is_visible.0 {
+                Visibility::Visible
+            } else {
+                Visibility::Hidden
+            };
+        }
+    }
+
+    /// Fills the panel with the blocks of the active language
+    fn fill_block_panel(
+        languages: Res<Languages>,
+        panel: Query<Entity, With<BlockPanel>>,
+        mut commands: Commands,
+    ) {
+        let Ok(panel) = panel.get_single() else {
+            return;
+        };
+        let blocks = &languages.active().blocks;
+        let text_style = TextStyle {
+            color: Color::WHITE,
+            font_size: 15.,
+            ..default()
+        };
+
+        let mut panel = commands.entity(panel);
+        panel.despawn_descendants();
+        panel.with_children(|parent| {
+            for (concept_type, title) in CONCEPT_TYPES {
+                let mut concept_blocks = blocks
+                    .iter()
+                    .filter(|block| block.concept_type == concept_type)
+                    .peekable();
+                if concept_blocks.peek().is_none() {
+                    continue;
+                }
+                parent.spawn(TextBundle::from_section(
+                    title,
+                    TextStyle {
+                        font_size: 17.,
+                        ..text_style.clone()
+                    },
+                ));
+                for block in concept_blocks {
+                    parent
+                        .spawn(BlockPanelEntryBundle::new(block.name.clone()))
+                        .with_children(|parent| {
+                            parent.spawn(NodeBundle {
+                                style: Style {
+                                    width: Val::Px(12.),
+                                    height: Val::Px(12.),
+                                    ..default()
+                                },
+                                background_color: BackgroundColor(
+                                    Theme::LIGHT.concept_color(&concept_type),
+                                ),
+                                ..default()
+                            });
+                            parent.spawn(TextBundle::from_section(
+                                block.name.clone(),
+                                text_style.clone(),
+                            ));
+                        });
+                }
+            }
+        });
+    }
+
+    fn handle_entry_press(
+        entries: Query<(&Interaction, &BlockPanelEntry), Changed<Interaction>>,
+        languages: Res<Languages>,
+        background: Query<&Node, With<BackgroundBox>>,
+        mut writer: EventWriter<SpawnUIBox>,
+    ) {
+        for (_, entry) in entries
+            .iter()
+            .filter(|(interaction, _)| matches!(interaction, Interaction::Pressed))
+        {
+            let Some(block_type) = languages.active().get_block(&entry.name) else {
+                continue;
+            };
+            let Ok(background) = background.get_single() else {
+                continue;
+            };
+            let coordinates = background.size() / 2.;
+            writer.send(SpawnUIBox {
+                bundle: BlockBundle::new(
+                    coordinates.x,
+                    coordinates.y,
+                    40.,
+                    40.,
+                    InteractionFocusBundle::default(),
+                    block_type,
+                ),
+                marker: None,
+                text: None,
+            });
+        }
+    }
+}
+
+impl Plugin for BlockPanelPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<IsBlockPanelVisible>()
+            .add_systems(Startup, Self::spawn_block_panel)
+            .add_systems(
+                Update,
+                (
+                    Self::toggle_block_panel.run_if(control_just_pressed(KeyCode::KeyB)),
+                    Self::fill_block_panel.run_if(resource_changed::<Languages>),
+                    Self::handle_entry_press,
+                )
+                    .in_set(GameSets::Running),
+            );
+    }
+}
@@ -0,0 +1,256 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{
+    ast::{AddToAst, BlockData},
+    connectors::{ConnectionDirection, SpawnConnector},
+    focus::InteractionFocusBundle,
+    ui_box::{BackgroundBox, BlockBundle, UIBoxPlugin},
+    ui_line::{LineBundle, UiLine},
+    utils::{get_relative_direction, BlockType, Languages, Position, Size},
+    ErrorEvent,
+};
+
+/// What a block is spawned with besides its type and its position
+#[derive(Debug, Clone)]
+pub struct BlockOptions {
+    size: Vec2,
+    text: Option<String>,
+    values: Vec<BlockData>,
+}
+
+impl Default for BlockOptions {
+    fn default() -> Self {
+        Self {
+            size: Vec2::splat(40.),
+            text: None,
+            values: Vec::new(),
+        }
+    }
+}
+
+impl BlockOptions {
+    /// The smallest size of the block, it grows to fit its text and holes
+    pub fn with_size(mut self, size: Vec2) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// The text of a block that is typed in, like the Text and Variable blocks
+    pub fn with_text(mut self, text: impl Into<String>) -> Self {
+        self.text = Some(text.into());
+        self
+    }
+
+    /// The value of the hole at `order`, it is only used by the holes that take a value without
+    /// a block in them like a number or a bool
+    pub fn with_value(mut self, order: usize, value: impl Into<String>) -> Self {
+        self.values.push(BlockData::value(order, value.into()));
+        self
+    }
+}
+
+/// Builds a program from code instead of from the keyboard and the mouse.
+///
+/// Everything is spawned through the commands of the system that uses it, so the entities
+/// that are given back only exist in the world once those commands are applied (at the end of
+/// the schedule or on an `apply_deferred`). The connectors of a block are spawned in
+/// `PostUpdate`, so use it in `Startup` or `Update`. Blocks have to be spawned before they are
+/// connected, which is the case when both are done by the same system
+#[derive(SystemParam)]
+pub struct BlockSpawner<'w, 's> {
+    commands: Commands<'w, 's>,
+    background: Query<'w, 's, Entity, With<BackgroundBox>>,
+    languages: Res<'w, Languages>,
+    add_ast_writer: EventWriter<'w, AddToAst>,
+    connector_writer: EventWriter<'w, SpawnConnector>,
+}
+
+impl BlockSpawner<'_, '_> {
+    /// Spawns the block with this name from the language on the canvas at the position, given
+    /// in the coordinates of the canvas, and gives back its entity
+    pub fn spawn_block(
+        &mut self,
+        language: &str,
+        name: &str,
+        position: Vec2,
+    ) -> Result<Entity, String> {
+        self.spawn_block_with(language, name, position, BlockOptions::default())
+    }
+
+    /// Like [`Self::spawn_block`] but with the size, text and hole values of the options
+    pub fn spawn_block_with(
+        &mut self,
+        language: &str,
+        name: &str,
+        position: Vec2,
+        options: BlockOptions,
+    ) -> Result<Entity, String> {
+        let language = self
+            .languages
+            .get(language)
+            .ok_or_else(|| format!("The language '{language}' isn't loaded"))?;
+        let block_type = language
+            .get_block(name)
+            .ok_or_else(|| format!("'{}' has no block called '{name}'", language.name))?;
+        let background = self
+            .background
+            .get_single()
+            .map_err(|_| "There is no canvas to spawn the block on".to_string())?;
+
+        let bundle = BlockBundle::new(
+            0.,
+            0.,
+            options.size.x,
+            options.size.y,
+            InteractionFocusBundle::default(),
+            block_type.clone(),
+        )
+        .with_position(position);
+        let mut entity = Entity::PLACEHOLDER;
+        self.commands.entity(background).with_children(|parent| {
+            entity = UIBoxPlugin::spawn_block(
+                parent,
+                bundle,
+                options.text,
+                &options.values,
+                language,
+                &mut |_, _| false,
+            )
+            .id();
+        });
+
+        self.add_ast_writer.send(AddToAst {
+            parent: None,
+            child: (entity, block_type.clone()),
+        });
+        UIBoxPlugin::send_connectors(&mut self.connector_writer, entity, &block_type.connectors);
+        Ok(entity)
+    }
+
+    /// Draws a line out of the side of `from` in to `to` and puts `to` after `from` in the
+    /// program. The line is given back straight away but it is only drawn once both blocks are
+    /// in the world, if one of them isn't an error is shown and the line is despawned
+    pub fn connect(&mut self, from: Entity, direction: ConnectionDirection, to: Entity) -> Entity {
+        let line = self.commands.spawn_empty().id();
+        self.commands.add(move |world: &mut World| {
+            let block = |entity| {
+                Some((
+                    world.get::<Position>(entity)?,
+                    world.get::<Size>(entity)?,
+                    world.get::<BlockType>(entity)?,
+                ))
+            };
+            let (Some((from_pos, from_size, _)), Some((to_pos, to_size, to_type))) =
+                (block(from), block(to))
+            else {
+                world.despawn(line);
+                world.send_event(ErrorEvent(format!(
+                    "Couldn't connect {from:?} to {to:?} because one of them isn't a block"
+                )));
+                return;
+            };
+            let to_type = to_type.clone();
+            let to_direction = get_relative_direction((to_pos, to_size), (from_pos, from_size));
+
+            world.entity_mut(line).insert(LineBundle::connected(UiLine {
+                from,
+                from_direction: direction,
+                to,
+                to_direction,
+            }));
+            world.send_event(AddToAst {
+                parent: Some((from, direction.get_parse_order())),
+                child: (to, to_type),
+            });
+        });
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy_simple_text_input::TextInputValue;
+
+    use super::*;
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.insert_resource(Languages::new());
+        world.init_resource::<Events<AddToAst>>();
+        world.init_resource::<Events<SpawnConnector>>();
+        world.init_resource::<Events<ErrorEvent>>();
+        world.spawn(BackgroundBox);
+        world
+    }
+
+    #[test]
+    fn spawns_and_connects_blocks() {
+        let mut world = world();
+        let language = world.resource::<Languages>().active().name.clone();
+        let (first, second, line) = world.run_system_once(move |mut spawner: BlockSpawner| {
+            let first = spawner
+                .spawn_block(&language, "Print", Vec2::ZERO)
+                .expect("Print should exist");
+            let second = spawner
+                .spawn_block(&language, "Print", Vec2::new(0., 100.))
+                .expect("Print should exist");
+            let line = spawner.connect(first, ConnectionDirection::Bottom, second);
+            (first, second, line)
+        });
+
+        assert_eq!(
+            world.get::<Position>(second).map(|position| position.0),
+            Some(Vec2::new(0., 100.))
+        );
+        assert_eq!(
+            world.get::<UiLine>(line).copied(),
+            Some(UiLine {
+                from: first,
+                from_direction: ConnectionDirection::Bottom,
+                to: second,
+                to_direction: ConnectionDirection::Top,
+            })
+        );
+        assert_eq!(world.resource::<Events<AddToAst>>().len(), 3);
+    }
+
+    #[test]
+    fn spawns_a_block_with_its_size_and_text() {
+        let mut world = world();
+        let language = world.resource::<Languages>().active().name.clone();
+        let text = world.run_system_once(move |mut spawner: BlockSpawner| {
+            spawner
+                .spawn_block_with(
+                    &language,
+                    "Text",
+                    Vec2::ZERO,
+                    BlockOptions::default()
+                        .with_size(Vec2::new(120., 40.))
+                        .with_text("hello"),
+                )
+                .expect("Text should exist")
+        });
+
+        assert_eq!(
+            world.get::<Size>(text).map(|size| size.0),
+            Some(Vec2::new(120., 40.))
+        );
+        let values = world
+            .query::<&TextInputValue>()
+            .iter(&world)
+            .map(|value| value.0.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec!["hello".to_owned()]);
+    }
+
+    #[test]
+    fn refuses_a_block_that_the_language_does_not_have() {
+        let mut world = world();
+        let language = world.resource::<Languages>().active().name.clone();
+        let result = world.run_system_once(move |mut spawner: BlockSpawner| {
+            spawner.spawn_block(&language, "Not a block", Vec2::ZERO)
+        });
+        assert!(result.is_err());
+    }
+}
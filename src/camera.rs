@@ -1,27 +1,70 @@
 use bevy::{
+    ecs::system::SystemParam,
     input::{
         common_conditions::{input_just_pressed, input_pressed},
         keyboard::KeyboardInput,
-        mouse::MouseWheel,
+        mouse::{MouseScrollUnit, MouseWheel},
     },
     prelude::*,
     render::camera::ScalingMode,
+    window::PrimaryWindow,
 };
 
 use crate::{
     focus::ActiveEntity,
-    ui_box::{BackgroundBox, UIBox},
-    utils::Position,
+    translate_vec_to_world,
+    ui_box::{BackgroundBox, Block, UIBox},
+    utils::{Position, Size},
     GameSets,
 };
 
 #[derive(Debug, Clone, Copy, Component)]
 pub struct MyCameraComponent;
 
+/// The size and zoom of the canvas, used to draw the UI coordinates in the world
+#[derive(SystemParam)]
+pub struct CanvasView<'w, 's> {
+    background: Query<'w, 's, &'static Node, With<BackgroundBox>>,
+    ui_scale: Res<'w, UiScale>,
+}
+
+impl CanvasView<'_, '_> {
+    /// The size of the background in the coordinates of the UI
+    pub fn size(&self) -> Vec2 {
+        self.background
+            .get_single()
+            .map(Node::size)
+            .unwrap_or_default()
+    }
+
+    pub fn to_world(&self, vector: Vec2) -> Vec2 {
+        translate_vec_to_world(vector, self.size(), self.ui_scale.0)
+    }
+
+    /// The cursor is in the coordinates of the window which the `UiScale` doesn't change
+    pub fn cursor_to_world(&self, cursor: Vec2) -> Vec2 {
+        self.to_world(cursor / self.ui_scale.0)
+    }
+}
+
 pub struct CameraPlugin;
 
+const MIN_ZOOM: f32 = 0.25;
+const MAX_ZOOM: f32 = 4.0;
+/// How much one line of scrolling multiplies the zoom by
+const ZOOM_STEP: f32 = 1.1;
+/// How many times the `PanSpeed` one line of scrolling pans the canvas by
+const WHEEL_PAN_STEPS: f32 = 3.;
+
+/// How far the canvas is panned by one press of an arrow key or one line of scrolling
 #[derive(Debug, Clone, Copy, Resource, Default)]
-pub struct PanSpeed(f32);
+pub struct PanSpeed(pub f32);
+
+/// Whether the canvas is being panned with the middle mouse button
+#[derive(Debug, Clone, Copy, Resource, Default)]
+pub struct MousePan {
+    pub panning: bool,
+}
 
 impl CameraPlugin {
     fn spawn_camera(mut commands: Commands) {
@@ -59,15 +102,153 @@ impl CameraPlugin {
         }
     }
 
-    fn zoom_camera(mut ui_scale: ResMut<UiScale>) {
-        ui_scale.0 += 0.25;
+    /// The lines that the wheel was scrolled by since the last frame
+    fn scroll_lines(wheel_events: &mut EventReader<MouseWheel>) -> f32 {
+        wheel_events
+            .read()
+            .map(|event| match event.unit {
+                MouseScrollUnit::Line => event.y,
+                MouseScrollUnit::Pixel => event.y / 100.,
+            })
+            .sum()
+    }
+
+    /// The wheel pans the canvas sideways with Shift and up and down with Ctrl, without either
+    /// it zooms
+    fn wheel_pan_direction(keyboard: &ButtonInput<KeyCode>) -> Option<Vec2> {
+        if keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+            Some(Vec2::X)
+        } else if keyboard.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+            Some(Vec2::Y)
+        } else {
+            None
+        }
+    }
+
+    fn wheel_pan_camera(
+        mut wheel_events: EventReader<MouseWheel>,
+        keyboard: Res<ButtonInput<KeyCode>>,
+        pan_speed: Res<PanSpeed>,
+        mut boxes: Query<&mut Position, With<UIBox>>,
+    ) {
+        let scroll = Self::scroll_lines(&mut wheel_events);
+        let Some(direction) = Self::wheel_pan_direction(&keyboard).filter(|_| scroll != 0.) else {
+            return;
+        };
+        // INFO: Scrolling up shows what is to the left or above, so the blocks move the other way
+        let offset = direction * scroll * pan_speed.0 * WHEEL_PAN_STEPS;
+        for mut position in &mut boxes {
+            position.0 += offset;
+        }
+    }
+
+    fn zoom_camera(
+        mut wheel_events: EventReader<MouseWheel>,
+        keyboard: Res<ButtonInput<KeyCode>>,
+        mut ui_scale: ResMut<UiScale>,
+        windows: Query<&Window, With<PrimaryWindow>>,
+        mut boxes: Query<&mut Position, With<UIBox>>,
+    ) {
+        let scroll = Self::scroll_lines(&mut wheel_events);
+        if scroll == 0. || Self::wheel_pan_direction(&keyboard).is_some() {
+            return;
+        }
+
+        let old_scale = ui_scale.0;
+        let new_scale = (old_scale * ZOOM_STEP.powf(scroll)).clamp(MIN_ZOOM, MAX_ZOOM);
+        if new_scale == old_scale {
+            return;
+        }
+        ui_scale.0 = new_scale;
+
+        // INFO: Positions are scaled by the UiScale so we shift them to keep the point under the
+        // cursor in the same place on the screen
+        let Some(cursor) = windows
+            .get_single()
+            .ok()
+            .and_then(|window| window.cursor_position())
+        else {
+            return;
+        };
+        let offset = cursor / new_scale - cursor / old_scale;
+        for mut position in &mut boxes {
+            position.0 += offset;
+        }
+    }
+
+    fn start_mouse_pan(blocks: Query<&Interaction, With<Block>>, mut mouse_pan: ResMut<MousePan>) {
+        // INFO: We don't pan when the middle mouse button is pressed on a block
+        mouse_pan.panning = !blocks
+            .iter()
+            .any(|interaction| matches!(interaction, Interaction::Hovered | Interaction::Pressed));
+    }
+
+    fn mouse_pan_camera(
+        mouse_pan: Res<MousePan>,
+        ui_scale: Res<UiScale>,
+        mut cursor_motion: EventReader<CursorMoved>,
+        mut boxes: Query<&mut Position, With<UIBox>>,
+    ) {
+        let delta: Vec2 = cursor_motion
+            .read()
+            .map(|motion| motion.delta.unwrap_or_default())
+            .sum();
+        if !mouse_pan.panning || delta == Vec2::ZERO {
+            return;
+        }
+        for mut position in &mut boxes {
+            position.0 += delta / ui_scale.0;
+        }
+    }
+
+    /// Resets the zoom and moves every box so that the blocks are centered on the screen
+    fn reset_camera(
+        mut ui_scale: ResMut<UiScale>,
+        windows: Query<&Window, With<PrimaryWindow>>,
+        mut boxes: Query<(&mut Position, Option<&Size>, Has<Block>), With<UIBox>>,
+    ) {
+        ui_scale.0 = 1.;
+        let bounds = boxes
+            .iter()
+            .filter(|&(_, _, is_block)| is_block)
+            .filter_map(|(position, size, _)| Some((position.0, position.0 + size?.0)))
+            .reduce(|(min1, max1), (min2, max2)| (min1.min(min2), max1.max(max2)));
+
+        let Some((min, max)) = bounds else {
+            for (mut position, _, _) in &mut boxes {
+                position.0 = Vec2::ZERO;
+            }
+            return;
+        };
+        let Ok(window) = windows.get_single() else {
+            return;
+        };
+        let offset = Vec2::new(window.width(), window.height()) / 2. - (min + max) / 2.;
+        for (mut position, _, _) in &mut boxes {
+            position.0 += offset;
+        }
     }
 }
 
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(PanSpeed(10.0))
+            .init_resource::<MousePan>()
             .add_systems(Startup, Self::spawn_camera)
-            .add_systems(Update, Self::move_camera.in_set(GameSets::Running));
+            .add_systems(
+                Update,
+                (
+                    Self::move_camera,
+                    Self::zoom_camera,
+                    Self::wheel_pan_camera,
+                    Self::reset_camera.run_if(input_just_pressed(KeyCode::Home)),
+                    (
+                        Self::start_mouse_pan.run_if(input_just_pressed(MouseButton::Middle)),
+                        Self::mouse_pan_camera.run_if(input_pressed(MouseButton::Middle)),
+                    )
+                        .chain(),
+                )
+                    .in_set(GameSets::Running),
+            );
     }
 }
@@ -0,0 +1,316 @@
+use std::sync::{Arc, Mutex};
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+    window::PrimaryWindow,
+};
+use bevy_simple_text_input::TextInputValue;
+
+use crate::{
+    ast::{BlockData, UpdateAst},
+    control_just_pressed,
+    focus::{ActiveEntity, InteractionFocusBundle, Selection},
+    text_input::TextInput,
+    ui_box::{
+        Arg, Block, BlockBundle, ColorOverride, Disabled, Hole, HoleValue, SpawnArg, SpawnUIBox,
+        UIBoxPlugin,
+    },
+    utils::{BlockType, Position, Size},
+    ErrorEvent, GameSets, GameState, Marker, State,
+};
+
+/// Where the pasted blocks go when the cursor is not on the window
+const PASTE_OFFSET: Vec2 = Vec2::new(20., 20.);
+
+/// The text read from the clipboard, on the web it only arrives some time after it was asked for
+#[derive(Debug, Resource, Default)]
+pub struct PastedText(pub Arc<Mutex<Option<String>>>);
+
+/// The blocks that were pasted, their args are put back in their holes once they are spawned
+#[derive(Debug, Resource, Default)]
+struct PendingPaste {
+    state: Option<GameState>,
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn write_text(text: &str) -> Result<(), String> {
+    arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.set_text(text))
+        .map_err(|error| error.to_string())
+}
+
+#[cfg(target_family = "wasm")]
+fn write_text(text: &str) -> Result<(), String> {
+    crate::wasm::write_clipboard(text).map_err(|error| ErrorEvent::take_js_error(error).0)
+}
+
+#[cfg(not(target_family = "wasm"))]
+fn read_text(pasted: &PastedText) -> Result<(), String> {
+    let text = arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.get_text())
+        .map_err(|error| error.to_string())?;
+    if let Ok(mut pasted) = pasted.0.lock() {
+        *pasted = Some(text);
+    }
+    Ok(())
+}
+
+#[cfg(target_family = "wasm")]
+fn read_text(pasted: &PastedText) -> Result<(), String> {
+    crate::wasm::read_clipboard(pasted.0.clone())
+        .map_err(|error| ErrorEvent::take_js_error(error).0)
+}
+
+/// Copies the selected blocks with their args to the clipboard and pastes them back at the cursor
+pub struct ClipboardPlugin;
+
+impl ClipboardPlugin {
+    fn copy_blocks(
+        active: Res<ActiveEntity>,
+        selection: Res<Selection>,
+        blocks: Query<
+            (
+                Entity,
+                &Position,
+                &Size,
+                &BlockType,
+                Option<&Arg>,
+                Option<&ColorOverride>,
+                Has<Disabled>,
+            ),
+            With<Block>,
+        >,
+        holes: Query<&Hole>,
+        hole_values: Query<&HoleValue>,
+        text_inputs: Query<(&TextInput, &TextInputValue)>,
+        mut error_writer: EventWriter<ErrorEvent>,
+    ) {
+        let mut copied: HashSet<Entity> = if selection.entities.is_empty() {
+            active.entity.into_iter().collect()
+        } else {
+            selection.entities.clone()
+        };
+        copied.retain(|&entity| blocks.contains(entity));
+        if copied.is_empty() {
+            return;
+        }
+
+        // INFO: The args of the copied blocks are copied with them
+        loop {
+            let args = blocks
+                .iter()
+                .filter(|(entity, ..)| !copied.contains(entity))
+                .filter_map(|(entity, _, _, _, arg, ..)| {
+                    let hole = holes.get(arg?.owner).ok()?;
+                    copied.contains(&hole.owner).then_some(entity)
+                })
+                .collect::<Vec<_>>();
+            if args.is_empty() {
+                break;
+            }
+            copied.extend(args);
+        }
+
+        let map = blocks
+            .iter_many(&copied)
+            .map(
+                |(entity, &position, &size, block_type, arg, color_override, disabled)| {
+                    let hole = arg
+                        .and_then(|arg| holes.get(arg.owner).ok())
+                        .filter(|hole| copied.contains(&hole.owner));
+                    let value = text_inputs
+                        .iter()
+                        .find(|(text_input, _)| text_input.owner == entity)
+                        .map(|(_, value)| value.0.clone());
+                    let state = State {
+                        parent: hole.map(|hole| hole.owner),
+                        order: hole.map(|hole| hole.order),
+                        connections: Vec::new(),
+                        holes: hole_values
+                            .iter()
+                            .filter(|hole_value| hole_value.owner == entity)
+                            .map(|hole_value| {
+                                BlockData::value(hole_value.order, hole_value.value.clone())
+                            })
+                            .collect(),
+                        block_type: block_type.clone(),
+                        position,
+                        size,
+                        value,
+                        color: color_override.map(|color| color.0),
+                        disabled,
+                        // INFO: The inputs of a pasted function are set up before it could be filled in
+                        function: None,
+                    };
+                    (entity, state)
+                },
+            )
+            .collect();
+        let state = GameState { map, ..default() };
+
+        let result = serde_json::to_string(&state)
+            .map_err(|error| error.to_string())
+            .and_then(|text| write_text(&text));
+        if let Err(error) = result {
+            error_writer.send(ErrorEvent(format!("Couldn't copy the blocks: {error}")));
+        }
+    }
+
+    fn request_paste(pasted: Res<PastedText>, mut error_writer: EventWriter<ErrorEvent>) {
+        if let Err(error) = read_text(&pasted) {
+            error_writer.send(ErrorEvent(format!("Couldn't paste the blocks: {error}")));
+        }
+    }
+
+    fn handle_pasted_text(
+        pasted: Res<PastedText>,
+        windows: Query<&Window, With<PrimaryWindow>>,
+        ui_scale: Res<UiScale>,
+        mut pending: ResMut<PendingPaste>,
+        mut writer: EventWriter<SpawnUIBox>,
+        mut error_writer: EventWriter<ErrorEvent>,
+    ) {
+        let Some(text) = pasted.0.lock().ok().and_then(|mut text| text.take()) else {
+            return;
+        };
+        let Ok(state) = serde_json::from_str::<GameState>(&text) else {
+            error_writer.send(ErrorEvent("There are no blocks on the clipboard".into()));
+            return;
+        };
+
+        // INFO: The top left corner of the pasted blocks is put at the cursor
+        let top_left = state
+            .map
+            .values()
+            .filter(|block| block.parent.is_none())
+            .map(|block| block.position.0)
+            .reduce(Vec2::min);
+        let cursor = windows
+            .get_single()
+            .ok()
+            .and_then(|window| window.cursor_position())
+            .map(|cursor| cursor / ui_scale.0);
+        let offset = match (top_left, cursor) {
+            (Some(top_left), Some(cursor)) => cursor - top_left,
+            _ => PASTE_OFFSET,
+        };
+
+        for (&entity, block) in &state.map {
+            let position = block.position.0 + offset;
+            writer.send(SpawnUIBox {
+                bundle: BlockBundle::new(
+                    position.x,
+                    position.y,
+                    block.size.0.x,
+                    block.size.0.y,
+                    InteractionFocusBundle::default(),
+                    block.block_type.clone(),
+                ),
+                marker: Some(Marker(entity)),
+                text: block.value.clone(),
+            });
+        }
+        pending.state = Some(state);
+    }
+
+    fn spawn_pasted_args(
+        mut pending: ResMut<PendingPaste>,
+        markers: Query<(Entity, &Marker)>,
+        holes: Query<(Entity, &Hole)>,
+        mut hole_values: Query<(&mut HoleValue, &Children, Option<&mut TextInputValue>)>,
+        mut texts: Query<&mut Text>,
+        mut arg_writer: EventWriter<SpawnArg>,
+        mut update_writer: EventWriter<UpdateAst>,
+        mut commands: Commands,
+    ) {
+        // INFO: The pasted blocks are only in the world the frame after they were sent
+        if markers.is_empty() {
+            return;
+        }
+        let Some(state) = pending.state.take() else {
+            return;
+        };
+
+        let new_entities: HashMap<Entity, Entity> = markers
+            .iter()
+            .map(|(entity, &Marker(old_entity))| (old_entity, entity))
+            .collect();
+        for (old_entity, block) in &state.map {
+            let (Some(&arg), Some(&parent), Some(order)) = (
+                new_entities.get(old_entity),
+                block.parent.and_then(|parent| new_entities.get(&parent)),
+                block.order,
+            ) else {
+                continue;
+            };
+            if let Some((hole, _)) = holes
+                .iter()
+                .find(|(_, hole)| hole.owner == parent && hole.order == order)
+            {
+                arg_writer.send(SpawnArg { arg, parent: hole });
+            }
+        }
+
+        // INFO: The holes that were toggled or picked get their value back
+        for (mut hole_value, children, text_value) in &mut hole_values {
+            let value = state
+                .map
+                .iter()
+                .find(|(old_entity, _)| new_entities.get(old_entity) == Some(&hole_value.owner))
+                .and_then(|(_, block)| {
+                    block
+                        .holes
+                        .iter()
+                        .filter_map(BlockData::get_value)
+                        .find(|&(position, _)| position == hole_value.order)
+                });
+            match (value, text_value) {
+                // INFO: A number hole is a text box, its hole value follows what is typed in it
+                (Some((_, value)), Some(mut text_value)) => value.clone_into(&mut text_value.0),
+                (Some((_, value)), None) => {
+                    UIBoxPlugin::set_hole_value(&mut hole_value, children, &mut texts, value);
+                }
+                (None, _) => {}
+            }
+        }
+        update_writer.send_default();
+
+        for (entity, Marker(old_entity)) in &markers {
+            let mut entity_commands = commands.entity(entity);
+            entity_commands.remove::<Marker>();
+            let Some(block) = state.map.get(old_entity) else {
+                continue;
+            };
+            if let Some(color) = block.color {
+                entity_commands.insert(ColorOverride(color));
+            }
+            if block.disabled {
+                entity_commands.insert(Disabled);
+            }
+        }
+    }
+}
+
+impl Plugin for ClipboardPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PastedText>()
+            .init_resource::<PendingPaste>()
+            .add_systems(
+                Update,
+                (
+                    Self::copy_blocks.run_if(control_just_pressed(KeyCode::KeyC)),
+                    (
+                        Self::request_paste.run_if(control_just_pressed(KeyCode::KeyV)),
+                        Self::handle_pasted_text,
+                    )
+                        .chain()
+                        .before(UIBoxPlugin::handle_spawn_ui_box),
+                    Self::spawn_pasted_args
+                        .run_if(|pending: Res<PendingPaste>| pending.state.is_some())
+                        .before(UIBoxPlugin::handle_spawn_active_arg),
+                )
+                    .in_set(GameSets::Running),
+            );
+    }
+}
@@ -0,0 +1,222 @@
+use bevy::prelude::*;
+
+use crate::{
+    ast::{ASTPlugin, Ast, BlockDataMap, RegenerateCode},
+    theme::FixedBackground,
+    utils::{BlockType, Languages},
+    wasm::{Command, Message, WASMRequest},
+    ErrorEvent, GameSets,
+};
+
+const NO_START_BLOCK: &str = "Add a Start block to see the generated code";
+
+/// Replaces the code in the panel, this is used when the server sends back code
+#[derive(Debug, Event, Clone)]
+pub struct ShowCode(pub String);
+
+/// The text that shows the generated code
+#[derive(Debug, Component)]
+pub struct CodePanelText;
+
+#[derive(Bundle)]
+pub struct CodePanelBundle {
+    node: NodeBundle,
+    fixed_background: FixedBackground,
+}
+
+impl CodePanelBundle {
+    fn new() -> Self {
+        Self {
+            node: NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(0.),
+                    bottom: Val::Px(0.),
+                    max_width: Val::Percent(40.),
+                    padding: UiRect::all(Val::Px(8.)),
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::rgba(0., 0., 0., 0.8)),
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            fixed_background: FixedBackground,
+        }
+    }
+}
+
+/// Sends the generated code to be run when pressed
+#[derive(Debug, Component)]
+pub struct RunButton;
+
+#[derive(Bundle)]
+pub struct RunButtonBundle {
+    button: ButtonBundle,
+    marker: RunButton,
+}
+
+impl RunButtonBundle {
+    fn new() -> Self {
+        Self {
+            button: ButtonBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(8.),
+                    top: Val::Px(8.),
+                    padding: UiRect::axes(Val::Px(12.), Val::Px(4.)),
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::DARK_GREEN),
+                z_index: ZIndex::Global(10),
+                ..default()
+            },
+            marker: RunButton,
+        }
+    }
+}
+
+/// A panel in the corner of the screen with the code of the program, it is regenerated every
+/// time the ast changes
+pub struct CodePanelPlugin;
+
+impl CodePanelPlugin {
+    fn spawn_code_panel(mut commands: Commands) {
+        commands
+            .spawn(CodePanelBundle::new())
+            .with_children(|parent| {
+                parent.spawn((
+                    TextBundle::from_section(
+                        NO_START_BLOCK,
+                        TextStyle {
+                            color: Color::WHITE,
+                            font_size: 15.,
+                            ..default()
+                        },
+                    ),
+                    CodePanelText,
+                ));
+            });
+    }
+
+    fn spawn_run_button(mut commands: Commands) {
+        commands
+            .spawn(RunButtonBundle::new())
+            .with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    "Run",
+                    TextStyle {
+                        color: Color::WHITE,
+                        font_size: 20.,
+                        ..default()
+                    },
+                ));
+            });
+    }
+
+    fn handle_run_button(
+        buttons: Query<&Interaction, (Changed<Interaction>, With<RunButton>)>,
+        ast: Res<Ast>,
+        block_data_map: Res<BlockDataMap>,
+        languages: Res<Languages>,
+        block_type: Query<(Entity, &BlockType)>,
+        mut error_writer: EventWriter<ErrorEvent>,
+        mut wasm_writer: EventWriter<WASMRequest>,
+    ) {
+        if !buttons
+            .iter()
+            .any(|interaction| matches!(interaction, Interaction::Pressed))
+        {
+            return;
+        }
+        let Some((start_entity, start_block)) = block_type
+            .iter()
+            .find(|(_, block_type)| block_type.name == "Start")
+        else {
+            error_writer.send(ErrorEvent("There is no start block to run".into()));
+            return;
+        };
+        match ast.generate(
+            start_entity,
+            start_block,
+            block_data_map.as_ref(),
+            languages.active(),
+        ) {
+            Ok(code) => {
+                if let Some(diagnostic) = code.diagnostic() {
+                    error_writer.send(ErrorEvent(diagnostic));
+                    return;
+                }
+                wasm_writer.send(WASMRequest(Message::Code(code.code)));
+                wasm_writer.send(WASMRequest(Message::Command(Command::RunCode)));
+            }
+            Err(error) => {
+                error_writer.send(ErrorEvent(error));
+            }
+        }
+    }
+
+    fn update_code_panel(
+        ast: Res<Ast>,
+        block_data_map: Res<BlockDataMap>,
+        languages: Res<Languages>,
+        block_type: Query<(Entity, &BlockType)>,
+        mut text: Query<&mut Text, With<CodePanelText>>,
+        mut update_reader: EventReader<RegenerateCode>,
+    ) {
+        if update_reader.read().last().is_none() {
+            return;
+        }
+        let Ok(mut text) = text.get_single_mut() else {
+            return;
+        };
+
+        let code = match block_type
+            .iter()
+            .find(|(_, block_type)| block_type.name == "Start")
+        {
+            Some((start_entity, start_block)) => ast
+                .generate(
+                    start_entity,
+                    start_block,
+                    block_data_map.as_ref(),
+                    languages.active(),
+                )
+                .map_or_else(
+                    |error| format!("Couldn't generate the code: {error}"),
+                    |code| code.code,
+                ),
+            None => NO_START_BLOCK.into(),
+        };
+        text.sections[0].value = code;
+    }
+
+    fn handle_show_code(
+        mut reader: EventReader<ShowCode>,
+        mut text: Query<&mut Text, With<CodePanelText>>,
+    ) {
+        let Some(ShowCode(code)) = reader.read().last() else {
+            return;
+        };
+        if let Ok(mut text) = text.get_single_mut() {
+            text.sections[0].value.clone_from(code);
+        }
+    }
+}
+
+impl Plugin for CodePanelPlugin {
+    fn build(&self, app: &mut App) {
+        // INFO: The run button sends requests even when there is no backend to handle them
+        app.add_event::<ShowCode>()
+            .add_event::<WASMRequest>()
+            .add_systems(Startup, (Self::spawn_code_panel, Self::spawn_run_button))
+            .add_systems(
+                Update,
+                (
+                    Self::update_code_panel.after(ASTPlugin::print_ast),
+                    Self::handle_run_button,
+                    Self::handle_show_code.after(Self::update_code_panel),
+                )
+                    .in_set(GameSets::Running),
+            );
+    }
+}
@@ -0,0 +1,122 @@
+use bevy::{
+    math::bounding::{Aabb2d, IntersectsVolume},
+    prelude::*,
+    utils::HashMap,
+};
+
+use crate::{
+    utils::{get_aabb2d, Position, Size},
+    GameSets,
+};
+
+/// How big the cells of the `SpatialGrid` are
+const GRID_CELL_SIZE: f32 = 100.;
+
+/// Marks an entity whose `Position` and `Size` should be checked for collisions
+#[derive(Debug, Component, Clone, Copy, Default)]
+pub struct Collision;
+
+/// Fired when two entities with a `Collision` overlap, `a` is always an entity that moved
+#[derive(Debug, Event, Clone, Copy)]
+pub struct OnCollide {
+    pub a: Entity,
+    pub b: Entity,
+}
+
+/// The colliding entities put in the cells of a grid that their bounds overlap, so that a moving
+/// entity is only checked against the entities that are near it
+#[derive(Debug, Resource, Default)]
+pub struct SpatialGrid {
+    cells: HashMap<IVec2, Vec<Entity>>,
+    /// The order the entities were added in, the candidates are given back in this order
+    order: HashMap<Entity, usize>,
+}
+
+impl SpatialGrid {
+    fn cells_of(aabb: &Aabb2d) -> impl Iterator<Item = IVec2> {
+        let min = (aabb.min / GRID_CELL_SIZE).floor().as_ivec2();
+        let max = (aabb.max / GRID_CELL_SIZE).floor().as_ivec2();
+        (min.x..=max.x).flat_map(move |x| (min.y..=max.y).map(move |y| IVec2::new(x, y)))
+    }
+
+    fn insert(&mut self, entity: Entity, aabb: &Aabb2d) {
+        let order = self.order.len();
+        self.order.insert(entity, order);
+        for cell in Self::cells_of(aabb) {
+            self.cells.entry(cell).or_default().push(entity);
+        }
+    }
+
+    /// The entities that share a cell with the bounds
+    pub fn candidates(&self, aabb: &Aabb2d) -> Vec<Entity> {
+        let mut candidates = Self::cells_of(aabb)
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|entity| self.order.get(entity));
+        candidates.dedup();
+        candidates
+    }
+}
+
+pub struct CollisionPlugin;
+
+impl CollisionPlugin {
+    /// Puts the colliding entities back in the grid when any of them moved, was resized or removed
+    fn update_spatial_grid(
+        query: Query<(Entity, &Position, &Size), With<Collision>>,
+        changed: Query<(), (With<Collision>, Or<(Changed<Position>, Changed<Size>)>)>,
+        mut removed: RemovedComponents<Collision>,
+        mut grid: ResMut<SpatialGrid>,
+    ) {
+        if changed.is_empty() && removed.read().last().is_none() {
+            return;
+        }
+        *grid = SpatialGrid::default();
+        for (entity, position, size) in &query {
+            grid.insert(entity, &get_aabb2d(position, size));
+        }
+    }
+
+    /// Checks the entities that moved this frame against the entities in the same cells
+    pub(crate) fn handle_collision(
+        query: Query<(Entity, Ref<Position>, &Size), With<Collision>>,
+        grid: Res<SpatialGrid>,
+        mut writer: EventWriter<OnCollide>,
+    ) {
+        for (entity, position, size) in query
+            .iter()
+            .filter(|(_, position, _)| position.is_changed())
+        {
+            let aabb = get_aabb2d(&position, size);
+            for other in grid.candidates(&aabb) {
+                if other == entity {
+                    continue;
+                }
+                let Ok((_, other_position, other_size)) = query.get(other) else {
+                    continue;
+                };
+                if aabb.intersects(&get_aabb2d(&other_position, other_size)) {
+                    writer.send(OnCollide {
+                        a: entity,
+                        b: other,
+                    });
+                }
+            }
+        }
+    }
+}
+
+impl Plugin for CollisionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<OnCollide>()
+            .init_resource::<SpatialGrid>()
+            .add_systems(
+                Update,
+                (Self::update_spatial_grid, Self::handle_collision)
+                    .chain()
+                    .in_set(GameSets::Running),
+            );
+    }
+}
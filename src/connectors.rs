@@ -1,10 +1,15 @@
-use bevy::{math::bounding::BoundingVolume, prelude::*};
+use bevy::{
+    math::bounding::{BoundingVolume, IntersectsVolume},
+    prelude::*,
+};
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    focus::{ActiveEntity, DragEntity, DragState, Draggable, FocusColor, InteractionFocusBundle},
+    collision::{Collision, CollisionPlugin, OnCollide, SpatialGrid},
+    focus::{DragEntity, DragState, Draggable, FocusColor, InteractionFocusBundle},
+    theme::Theme,
     ui_box::Block,
-    ui_line::{ConnectLine, TempConnectLine},
+    ui_line::{ActivelyDrawingLine, TempConnectLine, UiLine},
     utils::{get_aabb2d, get_relative_direction, Position, Size},
     GameSets,
 };
@@ -63,6 +68,17 @@ impl ConnectionDirection {
         }
     }
 
+    /// The color of the connector and of the lines that start from it, based on the role of the
+    /// connection
+    pub const fn get_color(&self) -> Color {
+        match self.get_parse_order() {
+            0 => Color::BLUE,
+            1 => Color::RED,
+            2 => Color::GOLD,
+            _ => Color::BLACK,
+        }
+    }
+
     pub const fn get_vec(&self) -> Vec2 {
         Vec2::new(self.get_left(), self.get_top())
     }
@@ -101,11 +117,17 @@ pub struct ConnectorBundle {
     position: Position,
     focus_bundle: InteractionFocusBundle,
     draggable: Draggable,
+    collision: Collision,
+    outline: Outline,
 }
 
+/// How far outside of the edge of a block a dragged connector still connects to it
+const CONNECTOR_SNAP_DISTANCE: f32 = 20.;
+
 #[derive(Debug, Resource, Default)]
 pub struct CollidedRect {
     pub entity: Option<Entity>,
+    pub direction: ConnectionDirection,
 }
 
 impl ConnectorBundle {
@@ -113,17 +135,15 @@ impl ConnectorBundle {
         let top = connector.direction.get_top();
         let left = connector.direction.get_left();
 
-        let color = match connector.direction {
-            ConnectionDirection::Left => Color::BLUE,
-            ConnectionDirection::Right => Color::RED,
-            _ => Color::BLACK,
-        };
+        let color = connector.direction.get_color();
 
         Self {
             focus_bundle: InteractionFocusBundle::new(Color::RED, Color::GREEN, color),
             connector,
             position,
             draggable: Draggable,
+            collision: Collision,
+            outline: Outline::new(Val::Px(2.), Val::Px(1.), Color::NONE),
             label: crate::EntityLabel("Connector".into()),
             size: Size(Vec2::new(radius, radius)),
             node: NodeBundle {
@@ -137,7 +157,7 @@ impl ConnectorBundle {
                 },
                 transform: Transform::default()
                     .with_translation(Vec2::new(-radius, -radius).extend(0.) / 2.),
-                background_color: BackgroundColor(Color::BLACK),
+                background_color: BackgroundColor(Theme::LIGHT.connector),
                 focus_policy: bevy::ui::FocusPolicy::Block,
                 ..default()
             },
@@ -150,6 +170,7 @@ pub struct Connector {
     pub fixture: Entity,
     pub direction: ConnectionDirection,
     // pub connection_type: ConnectionType,
+    /// Whether a line already starts from this connector
     pub connected: bool,
 }
 
@@ -252,40 +273,47 @@ impl ConnectorPlugin {
     ) {
         for &TempConnectLine(collided_entity) in reader.read() {
             collided_rect.entity = collided_entity.map(|(e, _)| e);
+            collided_rect.direction = collided_entity.map(|(_, d)| d).unwrap_or_default();
         }
     }
 
     fn check_collision(
-        connectors: Query<(&Position, &Size, &Connector), (With<Connector>, Changed<Position>)>,
+        connectors: Query<(Entity, &Position, &Size, &Connector), Changed<Position>>,
         positions: Query<(Entity, &Position, &Size), With<Block>>,
+        mut collisions: EventReader<OnCollide>,
         collided_rect: Res<CollidedRect>,
+        grid: Res<SpatialGrid>,
         mut writer: EventWriter<TempConnectLine>,
     ) {
-        for (connector_pos, connector_size, connector) in &connectors {
+        let collisions = collisions.read().copied().collect::<Vec<_>>();
+        for (connector_entity, connector_pos, connector_size, connector) in &connectors {
             let connector_aabb = get_aabb2d(connector_pos, connector_size);
 
-            let mut collided_with = positions.iter().filter_map(|(entity, pos, size)| {
-                if entity == connector.fixture {
-                    return None;
-                }
-                let target_aab = get_aabb2d(pos, size);
-                if target_aab.contains(&connector_aabb) {
-                    let direction =
-                        get_relative_direction((pos, size), (connector_pos, connector_size));
-                    Some((entity, direction))
-                } else {
-                    None
-                }
-            });
+            // INFO: The blocks the connector overlaps come first, then the blocks whose edge is
+            // close enough to snap to, only the blocks near the connector are checked
+            let nearby =
+                grid.candidates(&connector_aabb.grow(Vec2::splat(CONNECTOR_SNAP_DISTANCE)));
+            let collided_with = collisions
+                .iter()
+                .filter(|collision| collision.a == connector_entity)
+                .map(|collision| collision.b)
+                .chain(nearby)
+                .filter(|&entity| entity != connector.fixture)
+                .find_map(|entity| {
+                    let (_, pos, size) = positions.get(entity).ok()?;
+                    let target_aabb =
+                        get_aabb2d(pos, size).grow(Vec2::splat(CONNECTOR_SNAP_DISTANCE));
+                    target_aabb.intersects(&connector_aabb).then(|| {
+                        let direction =
+                            get_relative_direction((pos, size), (connector_pos, connector_size));
+                        (entity, direction)
+                    })
+                });
 
             //? This could in theory product two boxes but we don't care
-            if let Some(collided_with) = collided_with.next() {
-                if collided_rect.entity.is_none()
-                    || collided_rect
-                        .entity
-                        .is_some_and(|entity| entity != collided_with.0)
-                {
-                    writer.send(TempConnectLine(Some(collided_with)));
+            if let Some((entity, direction)) = collided_with {
+                if collided_rect.entity != Some(entity) || collided_rect.direction != direction {
+                    writer.send(TempConnectLine(Some((entity, direction))));
                 }
             } else {
                 writer.send_default();
@@ -293,21 +321,55 @@ impl ConnectorPlugin {
         }
     }
 
-    fn hide_connector(
-        mut reader: EventReader<ConnectLine>,
-        mut connectors: Query<&mut Visibility, With<Connector>>,
-        active: Res<ActiveEntity>,
+    /// A connector is connected while a line that isn't being drawn starts from it, a connected
+    /// connector can't be dragged into another line
+    fn update_connected(
+        lines: Query<(Entity, &UiLine)>,
+        changed_lines: Query<(), Changed<UiLine>>,
+        mut removed_lines: RemovedComponents<UiLine>,
+        active_drawing: Res<ActivelyDrawingLine>,
+        mut connectors: Query<(Entity, &mut Connector)>,
+        mut commands: Commands,
+    ) {
+        let removed = removed_lines.read().last().is_some();
+        if !removed && changed_lines.is_empty() && !active_drawing.is_changed() {
+            return;
+        }
+        for (entity, mut connector) in &mut connectors {
+            let connected = lines.iter().any(|(line_entity, line)| {
+                active_drawing.entity != Some(line_entity)
+                    && line.from == connector.fixture
+                    && line.from_direction == connector.direction
+            });
+            if connector.connected == connected {
+                continue;
+            }
+            connector.connected = connected;
+            if connected {
+                commands.entity(entity).remove::<Draggable>();
+            } else {
+                commands.entity(entity).insert(Draggable);
+            }
+        }
+    }
+
+    /// The outline of a connector shows if a line is being drawn from it or if it is connected
+    fn update_outline(
+        mut connectors: Query<(Entity, &Connector, &mut Outline)>,
+        drag: Res<DragEntity>,
+        theme: Res<Theme>,
     ) {
-        for ConnectLine(_) in reader.read() {
-            let Ok(mut visibility) = connectors.get_mut(
-                active
-                    .entity
-                    .expect("Expected the active element to be a connector"),
-            ) else {
-                return;
+        for (entity, connector, mut outline) in &mut connectors {
+            let color = if drag.entity == Some(entity) {
+                theme.highlight
+            } else if connector.connected {
+                theme.border
+            } else {
+                Color::NONE
             };
-
-            *visibility = Visibility::Hidden;
+            if outline.color != color {
+                outline.color = color;
+            }
         }
     }
 }
@@ -322,9 +384,12 @@ impl Plugin for ConnectorPlugin {
                     Self::handle_color_change,
                     Self::move_connector_according_to_mouse,
                     Self::translate_position,
-                    Self::check_collision.run_if(in_state(DragState::Started)),
+                    Self::check_collision
+                        .run_if(in_state(DragState::Started))
+                        .after(CollisionPlugin::handle_collision),
                     Self::set_connect_line,
-                    Self::hide_connector,
+                    Self::update_connected,
+                    Self::update_outline,
                 )
                     .in_set(GameSets::Running),
             )
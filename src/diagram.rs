@@ -0,0 +1,247 @@
+use std::fs;
+
+use bevy::{
+    input::common_conditions::input_just_pressed, prelude::*,
+    render::view::screenshot::ScreenshotManager, window::PrimaryWindow,
+};
+
+use crate::{
+    ast::ExportConfig,
+    control_just_pressed,
+    focus::Focus,
+    text_input::{CompletionList, SearchBox, SearchResultList},
+    theme::Theme,
+    tooltip::TooltipNode,
+    ui_box::{BackgroundBox, Block, ErrorList},
+    ui_line::{Segment, UiLine},
+    utils::BlockType,
+    wasm::download_file,
+    ErrorEvent, GameSets,
+};
+
+/// How far the drawing is from the edges of the svg
+const SVG_PADDING: f32 = 20.;
+const SVG_FONT_SIZE: f32 = 14.;
+
+fn svg_color(color: Color) -> String {
+    let [r, g, b, _] = color.as_rgba_u8();
+    format!("#{r:02x}{g:02x}{b:02x}")
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The overlays that were hidden for the screenshot and the visibility they get back
+#[derive(Debug, Resource, Default)]
+struct HiddenForScreenshot(Vec<(Entity, Visibility)>);
+
+/// Draws the blocks and the lines between them as an svg document. The rects are the top left
+/// corner and size of the blocks and the lines are the points of their segments, all in the
+/// coordinates of the background
+fn make_svg(
+    blocks: &[(Vec2, Vec2, String, Color)],
+    lines: &[(Vec<Vec2>, Color)],
+    theme: &Theme,
+) -> String {
+    let points = blocks
+        .iter()
+        .flat_map(|&(position, size, ..)| [position, position + size])
+        .chain(lines.iter().flat_map(|(points, _)| points.iter().copied()));
+    let (min, max) = points.fold(
+        (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+        |(min, max), point| (min.min(point), max.max(point)),
+    );
+    let (min, max) = if min.cmpgt(max).any() {
+        (Vec2::ZERO, Vec2::ZERO)
+    } else {
+        (min - SVG_PADDING, max + SVG_PADDING)
+    };
+    let size = max - min;
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"{} {} {} {}\">\n",
+        size.x, size.y, min.x, min.y, size.x, size.y
+    );
+    svg += &format!(
+        "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n",
+        min.x,
+        min.y,
+        size.x,
+        size.y,
+        svg_color(theme.background)
+    );
+
+    // INFO: Every line has its own arrowhead marker so that it has the color of the line
+    svg += "<defs>\n";
+    for (index, (_, color)) in lines.iter().enumerate() {
+        svg += &format!(
+            "<marker id=\"arrow-{index}\" viewBox=\"0 0 10 10\" refX=\"10\" refY=\"5\" markerWidth=\"8\" markerHeight=\"8\" orient=\"auto\"><path d=\"M 0 0 L 10 5 L 0 10 z\" fill=\"{}\"/></marker>\n",
+            svg_color(*color)
+        );
+    }
+    svg += "</defs>\n";
+
+    for (position, size, title, color) in blocks {
+        svg += &format!(
+            "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" stroke=\"{}\"/>\n",
+            position.x,
+            position.y,
+            size.x,
+            size.y,
+            svg_color(*color),
+            svg_color(theme.border)
+        );
+        svg += &format!(
+            "<text x=\"{}\" y=\"{}\" font-family=\"sans-serif\" font-size=\"{SVG_FONT_SIZE}\" fill=\"{}\">{}</text>\n",
+            position.x + 4.,
+            position.y + SVG_FONT_SIZE + 2.,
+            svg_color(theme.text),
+            escape_xml(title)
+        );
+    }
+
+    for (index, (points, color)) in lines.iter().enumerate() {
+        let points = points
+            .iter()
+            .map(|point| format!("{},{}", point.x, point.y))
+            .collect::<Vec<_>>()
+            .join(" ");
+        svg += &format!(
+            "<polyline points=\"{points}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\" marker-end=\"url(#arrow-{index})\"/>\n",
+            svg_color(*color)
+        );
+    }
+
+    svg += "</svg>\n";
+    svg
+}
+
+/// Exports the blocks and the lines on the canvas as an svg with Ctrl+I and takes a png
+/// screenshot of the window with F12
+pub struct DiagramPlugin;
+
+impl DiagramPlugin {
+    fn export_svg(
+        blocks: Query<(&Node, &GlobalTransform, &BlockType, &BackgroundColor), With<Block>>,
+        lines: Query<(&Focus, &Children), With<UiLine>>,
+        segments: Query<&Segment>,
+        background: Query<(&Node, &GlobalTransform), With<BackgroundBox>>,
+        theme: Res<Theme>,
+        config: Res<ExportConfig>,
+        mut error_writer: EventWriter<ErrorEvent>,
+    ) {
+        let Ok((background_node, background_transform)) = background.get_single() else {
+            return;
+        };
+        // INFO: The segments are in the coordinates of the background while the nodes are laid
+        // out on the window, so the blocks are moved to the background
+        let origin = background_transform.translation().truncate() - background_node.size() / 2.;
+        let blocks = blocks
+            .iter()
+            .map(|(node, transform, block_type, background_color)| {
+                let size = node.size();
+                let position = transform.translation().truncate() - size / 2. - origin;
+                (position, size, block_type.name.clone(), background_color.0)
+            })
+            .collect::<Vec<_>>();
+        let lines = lines
+            .iter()
+            .filter_map(|(focus, children)| {
+                let mut line_segments = segments.iter_many(children).peekable();
+                let first = line_segments.peek()?.from;
+                let points = std::iter::once(first)
+                    .chain(line_segments.map(|segment| segment.to))
+                    .collect();
+                Some((points, focus.inactive))
+            })
+            .collect::<Vec<_>>();
+        let svg = make_svg(&blocks, &lines, &theme);
+
+        let path = config.path.with_extension("svg");
+        if cfg!(target_family = "wasm") {
+            let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+            if let Err(error) = download_file(&file_name, &svg) {
+                error_writer.send(ErrorEvent::take_js_error(error));
+            }
+        } else if let Err(error) = fs::write(&path, svg) {
+            error_writer.send(ErrorEvent(format!(
+                "Couldn't export the diagram to {}: {error}",
+                path.display()
+            )));
+        } else {
+            info!("Exported the diagram to {}", path.display());
+        }
+    }
+
+    /// Hides the overlays that aren't part of the diagram and asks for a screenshot of the frame
+    fn take_screenshot(
+        window: Query<Entity, With<PrimaryWindow>>,
+        mut screenshot_manager: ResMut<ScreenshotManager>,
+        mut overlays: Query<
+            (Entity, &mut Visibility),
+            Or<(
+                With<ErrorList>,
+                With<SearchBox>,
+                With<SearchResultList>,
+                With<CompletionList>,
+                With<TooltipNode>,
+            )>,
+        >,
+        config: Res<ExportConfig>,
+        mut hidden: ResMut<HiddenForScreenshot>,
+        mut error_writer: EventWriter<ErrorEvent>,
+    ) {
+        let Ok(window) = window.get_single() else {
+            return;
+        };
+        // INFO: On the web bevy downloads the screenshot instead of writing it to the disk
+        let path = config.path.with_extension("png");
+        if screenshot_manager
+            .save_screenshot_to_disk(window, &path)
+            .is_err()
+        {
+            error_writer.send(ErrorEvent("A screenshot is already being taken".into()));
+            return;
+        }
+        info!("Saving a screenshot to {}", path.display());
+
+        for (entity, mut visibility) in &mut overlays {
+            hidden.0.push((entity, *visibility));
+            *visibility = Visibility::Hidden;
+        }
+    }
+
+    /// The frame with the hidden overlays was already sent to be rendered so they are shown again
+    fn restore_overlays(
+        mut hidden: ResMut<HiddenForScreenshot>,
+        mut overlays: Query<&mut Visibility>,
+    ) {
+        for (entity, visibility) in hidden.0.drain(..) {
+            if let Ok(mut current) = overlays.get_mut(entity) {
+                *current = visibility;
+            }
+        }
+    }
+}
+
+impl Plugin for DiagramPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HiddenForScreenshot>().add_systems(
+            Update,
+            (
+                Self::export_svg.run_if(control_just_pressed(KeyCode::KeyI)),
+                (
+                    Self::restore_overlays
+                        .run_if(|hidden: Res<HiddenForScreenshot>| !hidden.0.is_empty()),
+                    Self::take_screenshot.run_if(input_just_pressed(KeyCode::F12)),
+                )
+                    .chain(),
+            )
+                .in_set(GameSets::Running),
+        );
+    }
+}
@@ -2,10 +2,17 @@ use bevy::{
     input::common_conditions::{input_just_pressed, input_just_released},
     prelude::*,
     ui::FocusPolicy,
+    utils::HashSet,
+    window::PrimaryWindow,
 };
 
 use crate::{
-    ui_box::BackgroundBox, ui_line::Segment, utils::point_line_collision, DeleteEvent, GameSets,
+    box_pos_collision,
+    camera::CanvasView,
+    ui_box::{Arg, BackgroundBox, Block},
+    ui_line::Segment,
+    utils::{get_aabb2d, point_line_collision, Position, Size},
+    DeleteEvent, GameSets,
 };
 
 #[derive(Component, Clone, Copy, Debug, Default)]
@@ -26,6 +33,21 @@ pub struct ActiveEntity {
     pub entity: Option<Entity>,
 }
 
+/// The blocks that are selected together, shift clicking a block adds or removes it
+#[derive(Debug, Resource, Default)]
+pub struct Selection {
+    pub entities: HashSet<Entity>,
+    /// The selected block that was clicked without shift, the selection shrinks to it when it is
+    /// released without being dragged
+    pub collapse_to: Option<Entity>,
+}
+
+/// The cursor position where a box select was started on the background
+#[derive(Debug, Resource, Default)]
+pub struct BoxSelect {
+    pub start: Option<Vec2>,
+}
+
 #[derive(Debug, Resource, Default)]
 pub struct HoverEntity {
     pub entity: Option<Entity>,
@@ -149,33 +171,150 @@ impl FocusPlugin {
         }
     }
 
+    /// Tab focuses the next block from top to bottom and left to right, Shift+Tab the previous one
+    fn cycle_focus(
+        active: Res<ActiveEntity>,
+        keyboard: Res<ButtonInput<KeyCode>>,
+        blocks: Query<(Entity, &Position), (With<Block>, Without<Arg>)>,
+        mut select_writer: EventWriter<SelectEvent>,
+    ) {
+        let mut blocks = blocks.iter().collect::<Vec<_>>();
+        if blocks.is_empty() {
+            return;
+        }
+        blocks.sort_by(|(_, a), (_, b)| a.0.y.total_cmp(&b.0.y).then(a.0.x.total_cmp(&b.0.x)));
+
+        let backwards = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+        let current = active
+            .entity
+            .and_then(|active| blocks.iter().position(|&(entity, _)| entity == active));
+        let next = match (current, backwards) {
+            (Some(index), false) => (index + 1) % blocks.len(),
+            (Some(index), true) => index.checked_sub(1).unwrap_or(blocks.len() - 1),
+            (None, false) => 0,
+            (None, true) => blocks.len() - 1,
+        };
+        select_writer.send(SelectEvent(Some(blocks[next].0)));
+    }
+
     fn handle_select_event(
         old_selected: Res<ActiveEntity>,
-        mut color: Query<(&mut FocusColor, &Focus)>,
+        mut selection: ResMut<Selection>,
+        keyboard: Res<ButtonInput<KeyCode>>,
+        blocks: Query<(), With<Block>>,
+        mut color: Query<(&mut FocusColor, &Focus), Without<LineFocus>>,
         mut reader: EventReader<SelectEvent>,
     ) {
+        // INFO: Shift+Tab moves the focus back, it shouldn't add to the selection
+        let shift = keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight])
+            && !keyboard.pressed(KeyCode::Tab);
         for &SelectEvent(entity) in reader.read() {
-            if let Some(select_box_entity) = entity {
-                let (mut focus_color, focus) = color
-                    .get_mut(select_box_entity)
-                    .expect("Expected selcted UI box to be in the world tree");
-                focus_color.0 = focus.active;
+            let block = entity.filter(|&entity| blocks.contains(entity));
+            let mut deselected = Vec::new();
+            selection.collapse_to = None;
+
+            match block {
+                // INFO: Shift click toggles the block and keeps the rest of the selection
+                Some(block) if shift => {
+                    if let Some(old_entity) = old_selected.entity {
+                        if blocks.contains(old_entity) {
+                            selection.entities.insert(old_entity);
+                        } else {
+                            deselected.push(old_entity);
+                        }
+                    }
+                    if !selection.entities.insert(block) {
+                        selection.entities.remove(&block);
+                        deselected.push(block);
+                    }
+                }
+                // INFO: Clicking a block that is already selected keeps the selection so that it
+                // can be dragged as a group, it is only collapsed once the click is released
+                Some(block) if selection.entities.contains(&block) => {
+                    selection.collapse_to = Some(block);
+                }
+                _ => {
+                    deselected.extend(selection.entities.drain());
+                    deselected.extend(old_selected.entity);
+                    selection.entities.extend(block);
+                }
+            }
+
+            for old_entity in deselected {
+                if let Ok((mut focus_color, focus)) = color.get_mut(old_entity) {
+                    focus_color.0 = focus.inactive;
+                }
             }
 
-            if let Some(old_entity) = old_selected
-                .entity
-                .filter(|&old_entity| !entity.is_some_and(|new| new == old_entity))
+            if let Some(select_box_entity) =
+                entity.filter(|entity| block.is_none() || selection.entities.contains(entity))
             {
-                let (mut focus_color, focus) = color
-                    .get_mut(old_entity)
-                    .expect("Expected the old box to be in the world tree");
+                if let Ok((mut focus_color, focus)) = color.get_mut(select_box_entity) {
+                    focus_color.0 = focus.active;
+                }
+            }
+        }
+    }
+
+    /// Leaves only the clicked block selected when it was released without being dragged
+    fn collapse_selection(
+        mut selection: ResMut<Selection>,
+        drag: Res<DragEntity>,
+        transforms: Query<&GlobalTransform>,
+        mut color: Query<(&mut FocusColor, &Focus), Without<LineFocus>>,
+    ) {
+        let Some(block) = selection.collapse_to.take() else {
+            return;
+        };
+        let dragged = drag.entity == Some(block)
+            && drag
+                .drag_start
+                .zip(transforms.get(block).ok())
+                .is_some_and(|(start, transform)| transform.translation().xy() != start);
+        if dragged {
+            return;
+        }
+        let deselected = selection
+            .entities
+            .iter()
+            .copied()
+            .filter(|&entity| entity != block)
+            .collect::<Vec<_>>();
+        for entity in deselected {
+            selection.entities.remove(&entity);
+            if let Ok((mut focus_color, focus)) = color.get_mut(entity) {
                 focus_color.0 = focus.inactive;
             }
         }
     }
 
+    /// Gives the selected line its active color and the line that was selected before its
+    /// inactive color again
+    fn handle_select_line(
+        mut selected_line: Local<Option<Entity>>,
+        mut lines: Query<(&mut FocusColor, &Focus), With<LineFocus>>,
+        mut reader: EventReader<SelectEvent>,
+    ) {
+        // INFO: Clicking a line also presses the background, the line is selected last
+        let Some(&SelectEvent(entity)) = reader.read().last() else {
+            return;
+        };
+        let line = entity.filter(|&entity| lines.contains(entity));
+        if *selected_line == line {
+            return;
+        }
+        if let Some(Ok((mut focus_color, focus))) = selected_line.map(|old| lines.get_mut(old)) {
+            focus_color.0 = focus.inactive;
+        }
+        if let Some(Ok((mut focus_color, focus))) = line.map(|line| lines.get_mut(line)) {
+            focus_color.0 = focus.active;
+        }
+        *selected_line = line;
+    }
+
     fn handle_hover_event(
         selected_box: Res<ActiveEntity>,
+        selection: Res<Selection>,
         // old_hover_box: Res<HoverEntity>,
         mut color: Query<(Entity, &mut FocusColor, &Focus)>,
         mut reader: EventReader<HoverEvent>,
@@ -185,6 +324,7 @@ impl FocusPlugin {
                 if selected_box
                     .entity
                     .is_some_and(|selcted_entity| selcted_entity == entity)
+                    || selection.entities.contains(&entity)
                 {
                     continue;
                 }
@@ -248,10 +388,14 @@ impl FocusPlugin {
         for new_state in transitions.read() {
             match new_state.after {
                 DragState::Started => {
-                    if let Some(entity) = active.entity {
-                        if hover.get(entity).expect("Should never fail") == &Interaction::Pressed {
-                            drag.entity = Some(entity);
-                        }
+                    // INFO: A grabbed line end starts the drag of its connector without it being
+                    // pressed
+                    if let Some(entity) = active.entity.filter(|&entity| {
+                        hover
+                            .get(entity)
+                            .is_ok_and(|interaction| interaction == &Interaction::Pressed)
+                    }) {
+                        drag.entity = Some(entity);
                     }
                 }
                 DragState::Ended => {
@@ -268,31 +412,140 @@ impl FocusPlugin {
         mut hover: ResMut<HoverEntity>,
         mut drag: ResMut<DragEntity>,
         mut next_state: ResMut<NextState<DragState>>,
+        mut selection: ResMut<Selection>,
         background: Query<Entity, With<BackgroundBox>>, // query: Query<&Focus>,
     ) {
-        for &DeleteEvent(_) in reader.read() {
-            let background = background.single();
-            active.entity = Some(background);
+        for &DeleteEvent(deleted) in reader.read() {
+            selection.entities.remove(&deleted);
+            active.entity = background.get_single().ok();
             hover.entity = None;
             drag.entity = None;
             next_state.set(DragState::Ended);
         }
     }
 
+    fn start_box_select(
+        active: Res<ActiveEntity>,
+        background: Query<(), With<BackgroundBox>>,
+        windows: Query<&Window, With<PrimaryWindow>>,
+        mut box_select: ResMut<BoxSelect>,
+    ) {
+        if !active
+            .entity
+            .is_some_and(|entity| background.contains(entity))
+        {
+            return;
+        }
+        box_select.start = windows
+            .get_single()
+            .ok()
+            .and_then(|window| window.cursor_position());
+    }
+
+    fn draw_box_select(
+        box_select: Res<BoxSelect>,
+        windows: Query<&Window, With<PrimaryWindow>>,
+        view: CanvasView,
+        mut gizmos: Gizmos,
+    ) {
+        let Some(start) = box_select.start else {
+            return;
+        };
+        let Some(end) = windows
+            .get_single()
+            .ok()
+            .and_then(|window| window.cursor_position())
+        else {
+            return;
+        };
+        let start = view.cursor_to_world(start);
+        let end = view.cursor_to_world(end);
+        gizmos.rect_2d((start + end) / 2., 0., (end - start).abs(), Color::BLUE);
+    }
+
+    fn end_box_select(
+        windows: Query<&Window, With<PrimaryWindow>>,
+        ui_scale: Res<UiScale>,
+        mut box_select: ResMut<BoxSelect>,
+        mut selection: ResMut<Selection>,
+        mut blocks: Query<(Entity, &Position, &Size, &mut FocusColor, &Focus), With<Block>>,
+    ) {
+        let Some(start) = box_select.start.take() else {
+            return;
+        };
+        let Some(end) = windows
+            .get_single()
+            .ok()
+            .and_then(|window| window.cursor_position())
+        else {
+            return;
+        };
+        // INFO: The cursor is in the coordinates of the window, the blocks are in the ones of the UI
+        let (start, end) = (start / ui_scale.0, end / ui_scale.0);
+        let rect = (start.min(end), (end - start).abs());
+
+        for (entity, pos, size, mut focus_color, focus) in &mut blocks {
+            let aabb = get_aabb2d(pos, size);
+            if box_pos_collision(aabb.min, rect) && box_pos_collision(aabb.max, rect) {
+                selection.entities.insert(entity);
+                focus_color.0 = focus.active;
+            }
+        }
+    }
+
     fn handle_focus_line(
         query: Query<&Segment>,
+        view: CanvasView,
         mut cursor_motion: EventReader<CursorMoved>,
         mut hover_writer: EventWriter<HoverEvent>,
     ) {
-        for segments in &query {
-            for motion in cursor_motion.read() {
-                if point_line_collision((segments.from, segments.to), motion.position, Some(1.)) {
-                    info!("Hovering on {:?}", segments.owner);
-                    hover_writer.send(HoverEvent(Some(segments.owner)));
-                }
-            }
+        let Some(cursor) = cursor_motion.read().last().map(|motion| motion.position) else {
+            return;
+        };
+        if let Some(segment) = Self::get_segment_at(cursor, &view, &query) {
+            hover_writer.send(HoverEvent(Some(segment.owner)));
+        }
+    }
+
+    /// Selects the line under the cursor when the background was clicked
+    fn select_line(
+        query: Query<&Segment>,
+        view: CanvasView,
+        interactions: Query<&Interaction, (With<Focus>, Without<BackgroundBox>)>,
+        windows: Query<&Window, With<PrimaryWindow>>,
+        mut select_writer: EventWriter<SelectEvent>,
+    ) {
+        if interactions
+            .iter()
+            .any(|interaction| matches!(interaction, Interaction::Pressed))
+        {
+            return;
+        }
+        let Some(cursor) = windows
+            .get_single()
+            .ok()
+            .and_then(|window| window.cursor_position())
+        else {
+            return;
+        };
+        if let Some(segment) = Self::get_segment_at(cursor, &view, &query) {
+            select_writer.send(SelectEvent(Some(segment.owner)));
         }
     }
+
+    fn get_segment_at<'a>(
+        cursor: Vec2,
+        view: &CanvasView,
+        segments: &'a Query<&Segment>,
+    ) -> Option<&'a Segment> {
+        // INFO: The lines are drawn in world coordinates so the hit test is done in them as well
+        let cursor = view.cursor_to_world(cursor);
+        segments.iter().find(|segment| {
+            let from = view.to_world(segment.from);
+            let to = view.to_world(segment.to);
+            point_line_collision((from, to), cursor, Some(5.))
+        })
+    }
 }
 
 impl Plugin for FocusPlugin {
@@ -301,6 +554,8 @@ impl Plugin for FocusPlugin {
             .add_event::<SelectEvent>()
             .init_state::<DragState>()
             .init_resource::<ActiveEntity>()
+            .init_resource::<Selection>()
+            .init_resource::<BoxSelect>()
             .init_resource::<DragEntity>()
             .init_resource::<HoverEntity>()
             .add_systems(
@@ -309,14 +564,24 @@ impl Plugin for FocusPlugin {
                     (
                         (
                             Self::handle_interaction,
+                            Self::select_line.run_if(
+                                in_state(DragState::Ended)
+                                    .and_then(input_just_pressed(MouseButton::Left)),
+                            ),
                             Self::handle_focus_line.run_if(in_state(DragState::Ended)),
                             Self::handle_hover_event,
                             Self::set_hover,
+                            Self::cycle_focus.run_if(input_just_pressed(KeyCode::Tab)),
                             Self::handle_select_event,
+                            Self::handle_select_line,
                             Self::set_active,
                             Self::start_drag_state.run_if(input_just_pressed(MouseButton::Left)),
+                            Self::start_box_select.run_if(input_just_pressed(MouseButton::Left)),
                         )
                             .chain(),
+                        Self::draw_box_select,
+                        Self::end_box_select.run_if(input_just_released(MouseButton::Left)),
+                        Self::collapse_selection.run_if(input_just_released(MouseButton::Left)),
                         // .run_if(in_state(DragState::Ended)),
                         Self::end_drag_state.run_if(
                             in_state(DragState::Started)
@@ -1,20 +1,28 @@
-use bevy::prelude::*;
-use bevy_simple_text_input::TextInputBundle;
+use bevy::{prelude::*, ui::FocusPolicy, utils::HashSet};
+use bevy_simple_text_input::{TextInputBundle, TextInputValue};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    focus::InteractionFocusBundle,
-    text_input::CustomTextInputBundle,
-    ui_box::{Hole, HoleContainerBundle, UIBox},
+    ast::{ASTPlugin, BlockDataMap, UpdateAst},
+    text_input::{CustomTextInputBundle, TextInput},
+    theme::Theme,
+    utils::{BlockType, HoleType, Languages},
+    ErrorEvent, GameSets,
 };
 
 #[derive(Debug, Component)]
 pub struct FunctionComponent;
 
-#[derive(Debug, Component)]
-pub struct FunctionNameComponent;
+/// The name and the arguments of a function as they are saved, a function that is spawned with
+/// it starts with its inputs filled in
+#[derive(Debug, Component, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FunctionData {
+    pub name: String,
+    pub args: Vec<String>,
+}
 
 #[derive(Debug, Component)]
-pub struct FunctionReturnComponent;
+pub struct FunctionNameComponent;
 
 #[derive(Debug, Component)]
 pub struct FunctionArgContainerComponent;
@@ -25,65 +33,282 @@ pub struct FunctionArgComponent;
 #[derive(Debug, Component)]
 pub struct FunctionBodyComponent;
 
+#[derive(Debug, Component, Clone, Copy)]
+pub struct AddFunctionArgButton {
+    function: Entity,
+    container: Entity,
+}
+
+fn function_text_input(placeholder: &str) -> TextInputBundle {
+    TextInputBundle::default()
+        .with_placeholder(
+            placeholder,
+            Some(TextStyle {
+                color: Color::GRAY,
+                font_size: 15.,
+                ..default()
+            }),
+        )
+        .with_text_style(TextStyle {
+            color: Theme::LIGHT.text,
+            font_size: 15.,
+            ..default()
+        })
+}
+
+#[derive(Bundle)]
+pub struct FunctionNameBundle {
+    text_input_bundle: CustomTextInputBundle,
+    marker: FunctionNameComponent,
+}
+
+impl FunctionNameBundle {
+    fn new(function_entity: Entity, name: &str) -> Self {
+        Self {
+            text_input_bundle: CustomTextInputBundle::new(
+                function_text_input("name").with_value(name),
+                function_entity,
+            ),
+            marker: FunctionNameComponent,
+        }
+    }
+}
+
 #[derive(Bundle)]
-pub struct FunctionBundle {
-    marker: (FunctionComponent, UIBox),
+pub struct FunctionArgContainerBundle {
     node: NodeBundle,
-    focus: InteractionFocusBundle,
+    marker: FunctionArgContainerComponent,
 }
 
-impl FunctionBundle {
+impl FunctionArgContainerBundle {
     fn new() -> Self {
         Self {
             node: NodeBundle {
                 style: Style {
-                    flex_direction: FlexDirection::Column,
-                    min_width: Val::Px(50.),
-                    min_height: Val::Px(100.),
+                    flex_direction: FlexDirection::Row,
+                    column_gap: Val::Px(4.),
+                    align_items: AlignItems::Center,
                     ..default()
                 },
-
+                focus_policy: FocusPolicy::Pass,
                 ..default()
             },
-            marker: (FunctionComponent, UIBox),
-            focus: InteractionFocusBundle::default(),
+            marker: FunctionArgContainerComponent,
         }
     }
 }
 
 #[derive(Bundle)]
-pub struct FunctionNameBundle {
+pub struct FunctionArgBundle {
     text_input_bundle: CustomTextInputBundle,
-    marker: FunctionNameComponent,
+    marker: FunctionArgComponent,
 }
 
-impl FunctionNameBundle {
-    fn new(function_entity: Entity) -> Self {
+impl FunctionArgBundle {
+    fn new(function_entity: Entity, arg: &str) -> Self {
         Self {
             text_input_bundle: CustomTextInputBundle::new(
-                TextInputBundle::default(),
+                function_text_input("arg").with_value(arg),
                 function_entity,
             ),
-            marker: FunctionNameComponent,
+            marker: FunctionArgComponent,
         }
     }
 }
 
-// pub struct FunctionArgBundle {
-//     hole_bundle: HoleContainerBundle,
-//     marker:
-// }
-//
-// impl FunctionArgBundle {
-//     fn new()
-// }
+#[derive(Bundle)]
+pub struct AddFunctionArgBundle {
+    button: ButtonBundle,
+    marker: AddFunctionArgButton,
+}
 
+impl AddFunctionArgBundle {
+    fn new(function: Entity, container: Entity) -> Self {
+        Self {
+            button: ButtonBundle {
+                style: Style {
+                    padding: UiRect::horizontal(Val::Px(4.)),
+                    border: UiRect::all(Val::Px(1.)),
+                    align_self: AlignSelf::FlexStart,
+                    ..default()
+                },
+                border_color: BorderColor(Theme::LIGHT.border),
+                background_color: BackgroundColor(Color::NONE),
+                ..default()
+            },
+            marker: AddFunctionArgButton {
+                function,
+                container,
+            },
+        }
+    }
+}
+
+#[derive(Bundle)]
+pub struct FunctionBodyBundle {
+    text: TextBundle,
+    marker: FunctionBodyComponent,
+}
+
+impl FunctionBodyBundle {
+    fn new() -> Self {
+        Self {
+            text: TextBundle::from_section(
+                "body",
+                TextStyle {
+                    color: Theme::LIGHT.text,
+                    font_size: 15.,
+                    ..default()
+                },
+            )
+            .with_style(Style {
+                align_self: AlignSelf::FlexEnd,
+                ..default()
+            }),
+            marker: FunctionBodyComponent,
+        }
+    }
+}
+
+/// This is a plugin for the function block. The function block is a normal block from the
+/// language that is called `Function`, this plugin adds the name, arguments and body to it.
+///
+/// The body of the function is the flow connected to its right connector and the name and
+/// arguments replace the `{{name}}` and `{{args}}` placeholders of the template
 pub struct FunctionPlugin;
 
-impl FunctionPlugin {}
+impl FunctionPlugin {
+    fn setup_function_block(
+        mut commands: Commands,
+        blocks: Query<(Entity, &BlockType, Option<&FunctionData>), Added<BlockType>>,
+    ) {
+        for (function, _, data) in blocks
+            .iter()
+            .filter(|(_, block_type, _)| block_type.name == "Function")
+        {
+            let data = data.cloned().unwrap_or_default();
+            commands
+                .entity(function)
+                .insert(FunctionComponent)
+                .with_children(|parent| {
+                    parent.spawn(FunctionNameBundle::new(function, &data.name));
+                    let container = parent
+                        .spawn(FunctionArgContainerBundle::new())
+                        .with_children(|parent| {
+                            for arg in &data.args {
+                                parent.spawn(FunctionArgBundle::new(function, arg));
+                            }
+                        })
+                        .id();
+                    parent
+                        .spawn(AddFunctionArgBundle::new(function, container))
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section(
+                                "+",
+                                TextStyle {
+                                    color: Theme::LIGHT.text,
+                                    font_size: 15.,
+                                    ..default()
+                                },
+                            ));
+                        });
+                    parent.spawn(FunctionBodyBundle::new());
+                });
+        }
+    }
+
+    fn handle_add_arg(
+        buttons: Query<(&Interaction, &AddFunctionArgButton), Changed<Interaction>>,
+        mut commands: Commands,
+    ) {
+        for (_, button) in buttons
+            .iter()
+            .filter(|(interaction, _)| matches!(interaction, Interaction::Pressed))
+        {
+            let Some(mut container) = commands.get_entity(button.container) else {
+                error!("The argument container of the function doesn't exist");
+                continue;
+            };
+            container.with_children(|parent| {
+                parent.spawn(FunctionArgBundle::new(button.function, ""));
+            });
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn get_function_data(
+        names: Query<(&TextInput, &TextInputValue), With<FunctionNameComponent>>,
+        arg_containers: Query<(&Parent, Option<&Children>), With<FunctionArgContainerComponent>>,
+        args: Query<&TextInputValue, With<FunctionArgComponent>>,
+        languages: Res<Languages>,
+        mut invalid_names: Local<HashSet<Entity>>,
+        mut block_map: ResMut<BlockDataMap>,
+        mut commands: Commands,
+        mut error_writer: EventWriter<ErrorEvent>,
+        mut update_reader: EventReader<UpdateAst>,
+    ) {
+        if update_reader.read().last().is_none() {
+            return;
+        }
+        block_map.named.clear();
+
+        for (text_input, name) in &names {
+            let function = text_input.owner;
+            // INFO: Empty names are not reported since every function starts without a name. A
+            // name is only reported when it becomes invalid, not on every key that is typed
+            let invalid = !name.0.is_empty()
+                && !languages
+                    .active()
+                    .valid_input(&HoleType::Variable, name.0.as_str());
+            if !invalid {
+                invalid_names.remove(&function);
+            } else if invalid_names.insert(function) {
+                error_writer.send(ErrorEvent(format!(
+                    "'{}' is not a valid function name",
+                    name.0
+                )));
+            }
+
+            // INFO: The arguments are in the order they were added to the container
+            let args = arg_containers
+                .iter()
+                .find(|(parent, _)| parent.get() == function)
+                .and_then(|(_, children)| children)
+                .into_iter()
+                .flatten()
+                .filter_map(|&child| args.get(child).ok())
+                .map(|arg| arg.0.clone())
+                .filter(|arg| !arg.is_empty())
+                .collect::<Vec<_>>();
+            block_map.named.insert(
+                function,
+                vec![
+                    ("name".into(), name.0.clone()),
+                    ("args".into(), args.join(", ")),
+                ],
+            );
+            if let Some(mut function) = commands.get_entity(function) {
+                function.insert(FunctionData {
+                    name: name.0.clone(),
+                    args,
+                });
+            }
+        }
+    }
+}
 
 impl Plugin for FunctionPlugin {
     fn build(&self, app: &mut App) {
-        todo!()
+        app.add_systems(
+            Update,
+            (
+                Self::setup_function_block,
+                Self::handle_add_arg,
+                Self::get_function_data
+                    .after(ASTPlugin::get_block_data_hashmap)
+                    .before(ASTPlugin::print_ast),
+            )
+                .in_set(GameSets::Running),
+        );
     }
 }
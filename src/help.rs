@@ -0,0 +1,127 @@
+use bevy::{input::common_conditions::input_just_pressed, prelude::*, ui::FocusPolicy};
+
+use crate::{theme::FixedBackground, GameSets};
+
+/// Every key binding with what it does, in the order that they are shown in the help
+const KEY_BINDINGS: &[(&str, &str)] = &[
+    ("F1", "Show or hide this help"),
+    (
+        "S / D / C / T / V / B",
+        "Spawn a Declaration / If / Comparitor / Text / Variable / Print",
+    ),
+    ("/", "Search for a block to spawn"),
+    ("Tab", "Focus the next block"),
+    (
+        "Arrows",
+        "Move the active block, or the canvas when nothing is active",
+    ),
+    (
+        "Shift / Ctrl + Arrows",
+        "Move the active block by a small / large step",
+    ),
+    (
+        "Alt + Arrows / C / M",
+        "Align the selected blocks to a side or a center",
+    ),
+    ("Alt + H / V", "Spread the selected blocks out evenly"),
+    ("Delete / Backspace", "Delete the active block or line"),
+    ("Ctrl + C / V", "Copy / paste the selected blocks"),
+    ("Ctrl + D", "Duplicate the active block"),
+    ("Ctrl + /", "Disable or enable the active block"),
+    ("Ctrl + K", "Change the color of the active block"),
+    ("Ctrl + U", "Disconnect the active block"),
+    ("Ctrl + F", "Fade every block that isn't of a concept"),
+    ("Ctrl + G", "Turn snapping to the grid on or off"),
+    ("Ctrl + B", "Show or hide the block panel"),
+    ("Ctrl + T / P", "Change the theme / palette"),
+    ("Ctrl + E", "Export the generated code"),
+    (
+        "Ctrl + I / F12",
+        "Export the diagram as an svg / take a screenshot",
+    ),
+    ("Ctrl + S / O", "Save / open the project"),
+    ("Ctrl + L", "Import a program from its tree"),
+    ("Ctrl + N", "Start a new project"),
+    ("Mouse wheel", "Zoom"),
+    (
+        "Shift / Ctrl + Mouse wheel",
+        "Pan the canvas sideways / up and down",
+    ),
+    ("Middle mouse drag", "Pan the canvas"),
+    ("Home", "Reset the zoom and the pan"),
+    ("H", "Log the type of the active block"),
+];
+
+/// The overlay that lists the key bindings
+#[derive(Debug, Component)]
+pub struct HelpOverlay;
+
+#[derive(Bundle)]
+pub struct HelpOverlayBundle {
+    node: NodeBundle,
+    marker: HelpOverlay,
+    fixed_background: FixedBackground,
+}
+
+impl HelpOverlayBundle {
+    fn new() -> Self {
+        Self {
+            node: NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(20.),
+                    top: Val::Px(20.),
+                    padding: UiRect::all(Val::Px(12.)),
+                    display: Display::Grid,
+                    grid_template_columns: vec![GridTrack::auto(), GridTrack::auto()],
+                    column_gap: Val::Px(16.),
+                    row_gap: Val::Px(2.),
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::rgba(0., 0., 0., 0.85)),
+                // INFO: The help is only shown on top of the canvas, it doesn't stop using it
+                focus_policy: FocusPolicy::Pass,
+                z_index: ZIndex::Global(30),
+                ..default()
+            },
+            marker: HelpOverlay,
+            fixed_background: FixedBackground,
+        }
+    }
+}
+
+/// Shows the key bindings on top of the canvas with F1
+pub struct HelpPlugin;
+
+impl HelpPlugin {
+    fn toggle_help(overlay: Query<Entity, With<HelpOverlay>>, mut commands: Commands) {
+        if let Ok(overlay) = overlay.get_single() {
+            commands.entity(overlay).despawn_recursive();
+            return;
+        }
+        let style = |color| TextStyle {
+            color,
+            font_size: 14.,
+            ..default()
+        };
+        commands
+            .spawn(HelpOverlayBundle::new())
+            .with_children(|parent| {
+                for &(keys, description) in KEY_BINDINGS {
+                    parent.spawn(TextBundle::from_section(keys, style(Color::GOLD)));
+                    parent.spawn(TextBundle::from_section(description, style(Color::WHITE)));
+                }
+            });
+    }
+}
+
+impl Plugin for HelpPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            Update,
+            Self::toggle_help
+                .run_if(input_just_pressed(KeyCode::F1))
+                .in_set(GameSets::Running),
+        );
+    }
+}
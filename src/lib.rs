@@ -1,53 +1,88 @@
 #![allow(clippy::type_complexity)]
 
-// TODO:
-// - Fix focus to allow focusing on lines
-// - Fix `ui_box` to allow the dragging outside of arguments
-
 mod ast;
+mod block_panel;
+mod builder;
 mod camera;
+mod clipboard;
+mod code_panel;
+mod collision;
 mod connectors;
+mod diagram;
 mod focus;
-// mod function;
+mod function;
+mod help;
+#[cfg(all(not(target_family = "wasm"), feature = "native-backend"))]
+mod native;
 mod text_input;
+mod theme;
+mod tooltip;
 mod ui_box;
 mod ui_line;
 mod utils;
 mod wasm;
 
-use std::time::Duration;
+use std::{fs, time::Duration};
 
 use bevy::{app::PluginGroupBuilder, prelude::*, utils::HashMap, window::PresentMode};
 use serde::{Deserialize, Serialize};
 use ui_line::UiLinePlugin;
 use wasm::WASMRequest;
 
+use bevy_simple_text_input::TextInputValue;
+
 use crate::{
-    ast::BlockData,
+    ast::{AddToAst, BlockData, UpdateAst, FLOW_ORDER},
+    block_panel::BlockPanelPlugin,
     camera::CameraPlugin,
-    focus::FocusPlugin,
-    text_input::CustomTextInputPlugin,
-    ui_box::{BackgroundBox, ErrorBox, ErrorBoxBundle, UIBoxPlugin},
-    ui_line::UiLine,
-    utils::{BlockType, Position, Size},
+    clipboard::ClipboardPlugin,
+    code_panel::CodePanelPlugin,
+    collision::CollisionPlugin,
+    connectors::SpawnConnector,
+    diagram::DiagramPlugin,
+    focus::{ActiveEntity, FocusPlugin, InteractionFocusBundle, Selection},
+    function::{FunctionArgComponent, FunctionData, FunctionNameComponent, FunctionPlugin},
+    help::HelpPlugin,
+    text_input::{CustomTextInputPlugin, TextInput},
+    theme::{Theme, ThemePlugin},
+    tooltip::TooltipPlugin,
+    ui_box::{
+        Arg, BackgroundBox, Block, BlockBundle, CloseErrorBundle, CloseErrorButton, ColorOverride,
+        Disabled, ErrorBoxBundle, ErrorList, Hole, HoleValue, UIBoxPlugin,
+    },
+    ui_line::{LineBundle, UiLine},
+    utils::{get_relative_direction, HoleType, Position, Size},
+    wasm::download_file,
 };
 use ast::ASTPlugin;
 use connectors::ConnectorPlugin;
 
+pub use ast::{Ast, AstHole, AstNode, BlockDataMap, ExportConfig};
+pub use builder::{BlockOptions, BlockSpawner};
+pub use camera::PanSpeed;
+pub use connectors::ConnectionDirection;
+pub use theme::FontConfig;
+pub use ui_box::DeleteConfirmConfig;
+pub use utils::{BlockType, Language, Languages};
+pub use wasm::WsConfig;
+
 pub const WINDOW_HEIGHT: f32 = 600.;
 pub const WINDOW_WIDTH: f32 = 600.;
 
-pub const WHITE: Color = Color::rgb(255., 255., 255.);
 pub const RED: Color = Color::rgb(255., 0., 0.);
 
-// pub fn input_just_pressed_with_modifier<T>(
-//     input: T,
-// ) -> impl FnMut(Res<ButtonInput<T>>) -> bool + Clone
-// where
-//     T: Copy + Eq + std::hash::Hash + Send + Sync + 'static,
-// {
-//     move |inputs: Res<ButtonInput<T>>| inputs.get_just_pressed(KeyCode::ShiftLeft)
-// }
+/// Ctrl+N has to be pressed again within this time for the canvas to be cleared
+const NEW_PROJECT_CONFIRM_TIME: Duration = Duration::from_secs(3);
+
+/// Run condition that is true when the key was just pressed while a control key is held down
+pub fn control_just_pressed(
+    input: KeyCode,
+) -> impl FnMut(Res<ButtonInput<KeyCode>>) -> bool + Clone {
+    move |inputs: Res<ButtonInput<KeyCode>>| {
+        inputs.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight])
+            && inputs.just_pressed(input)
+    }
+}
 
 pub fn get_default_plugins() -> PluginGroupBuilder {
     DefaultPlugins.set(WindowPlugin {
@@ -67,14 +102,17 @@ pub fn get_default_plugins() -> PluginGroupBuilder {
     })
 }
 
-pub fn set_background_color(mut clear_color: ResMut<ClearColor>) {
-    clear_color.0 = WHITE;
+pub fn set_background_color(mut clear_color: ResMut<ClearColor>, theme: Res<Theme>) {
+    clear_color.0 = theme.background;
 }
 
-pub fn translate_vec_to_world(mut vector: Vec2, window_height: f32, window_width: f32) -> Vec2 {
-    vector.x -= window_width / 2.;
-    vector.y = window_height / 2. - vector.y;
-    vector
+/// Moves a point from the coordinates of the UI to the ones of the world. The UI is drawn
+/// `scale` times bigger by the `UiScale`
+pub fn translate_vec_to_world(vector: Vec2, background_size: Vec2, scale: f32) -> Vec2 {
+    Vec2::new(
+        vector.x - background_size.x / 2.,
+        background_size.y / 2. - vector.y,
+    ) * scale
 }
 
 pub fn box_pos_collision(subject: Vec2, (target_pos, target_size): (Vec2, Vec2)) -> bool {
@@ -107,28 +145,279 @@ pub struct DeleteEvent(pub Entity);
 #[derive(Debug, Event, Clone)]
 pub struct ErrorEvent(pub String);
 
+/// An error that came from the server, it is shown like the others but isn't sent back to it
+#[derive(Debug, Event, Clone)]
+pub struct ServerErrorEvent(pub String);
+
 #[derive(Debug, Component, Clone)]
 pub struct ErrorTimer(Timer);
 
+/// Where the project is saved to and loaded from, on the web it is downloaded instead
+const SAVE_PATH: &str = "state.json";
+/// Where the tree of a program is imported from with Ctrl+L
+const TREE_PATH: &str = "program.json";
+/// Where the first block of an imported program is put and how far apart its blocks are laid out
+const TREE_ORIGIN: Vec2 = Vec2::new(50., 50.);
+const TREE_FLOW_STEP: f32 = 80.;
+const TREE_BRANCH_STEP: f32 = 250.;
+/// The version of the format of the save, saves of older versions are migrated when loaded
+const SAVE_VERSION: u32 = 2;
+
+/// Clears the canvas and starts again with only the start block
+#[derive(Debug, Event, Clone, Copy, Default)]
+pub struct NewProject;
+
+/// Replaces the project with this state, sent when a save is loaded or a program is imported
+#[derive(Debug, Event, Clone)]
+pub struct LoadGameState(pub GameState);
+
+/// When Ctrl+N was first pressed, it has to be pressed again to confirm
+#[derive(Debug, Resource, Default)]
+pub struct NewProjectConfirm {
+    requested_at: Option<Duration>,
+}
+
+/// How long an error message stays on screen and how many can be shown at once
+#[derive(Debug, Resource, Clone, Copy)]
+pub struct ErrorConfig {
+    pub timeout: Duration,
+    pub max_visible: usize,
+}
+
+impl Default for ErrorConfig {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(4),
+            max_visible: 5,
+        }
+    }
+}
+
 #[derive(Debug, Component, Clone, Copy)]
 pub struct Marker(pub Entity);
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct State {
     parent: Option<Entity>,
     order: Option<usize>,
-    connections: [Option<(Entity, BlockType)>; 3],
+    connections: Vec<Option<(Entity, BlockType)>>,
     holes: Vec<BlockData>,
     block_type: BlockType,
     position: Position,
     size: Size,
     value: Option<String>,
+    #[serde(default)]
+    color: Option<Color>,
+    #[serde(default)]
+    disabled: bool,
+    #[serde(default)]
+    function: Option<FunctionData>,
+}
+
+/// The zoom of the canvas when the project was saved. The canvas is panned by moving the
+/// blocks, so the pan is already in their positions
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ViewState {
+    scale: f32,
+}
+
+impl Default for ViewState {
+    fn default() -> Self {
+        Self { scale: 1. }
+    }
 }
 
-#[derive(Resource, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[derive(Resource, Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 pub struct GameState {
     map: HashMap<Entity, State>,
     lines: Vec<UiLine>,
+    // INFO: Saves from before the view was stored open with the default view
+    #[serde(default)]
+    view: ViewState,
+}
+
+/// What is written to the save file, the version says how the state has to be migrated
+#[derive(Debug, Serialize, Deserialize)]
+struct SaveFile<T> {
+    version: u32,
+    state: T,
+}
+
+impl GameState {
+    fn to_save(&self) -> Result<String, String> {
+        serde_json::to_string(&SaveFile {
+            version: SAVE_VERSION,
+            state: self,
+        })
+        .map_err(|error| error.to_string())
+    }
+
+    /// Builds the state of a program from its tree, see [`Ast::to_tree`]. The flow goes down from
+    /// the first block and the branches of a block are laid out to the right of it
+    pub fn from_tree(tree: &AstNode, language: &Language) -> Result<Self, String> {
+        let mut state = Self::default();
+        state.add_tree_node(tree, language, None, TREE_ORIGIN, &mut 0)?;
+        Ok(state)
+    }
+
+    /// Adds the block of the node with its args, branches and the flow after it, gives back
+    /// its entity and the lowest point that the blocks of the node reach
+    fn add_tree_node(
+        &mut self,
+        node: &AstNode,
+        language: &Language,
+        parent: Option<(Entity, usize)>,
+        position: Vec2,
+        next_id: &mut u32,
+    ) -> Result<(Entity, f32), String> {
+        let block_type = language
+            .get_block(&node.name)
+            .ok_or_else(|| format!("'{}' has no block called '{}'", language.name, node.name))?;
+        let entity = Entity::from_raw(*next_id);
+        *next_id += 1;
+        let size = Size(Vec2::new(40., 40.));
+
+        let mut value = None;
+        let mut holes = Vec::new();
+        for (order, (hole, hole_type)) in node.holes.iter().zip(&block_type.holes).enumerate() {
+            match hole {
+                AstHole::Empty => {}
+                AstHole::Value(text) if block_type.has_text() => value = Some(text.clone()),
+                AstHole::Value(text)
+                    if matches!(
+                        hole_type,
+                        HoleType::Bool | HoleType::Number | HoleType::Comparitor
+                    ) =>
+                {
+                    holes.push(BlockData::value(order, text.clone()));
+                }
+                // INFO: Any other hole only gets a value from the Text block in it
+                AstHole::Value(text) => {
+                    let text_node = AstNode {
+                        name: "Text".into(),
+                        disabled: false,
+                        holes: vec![AstHole::Value(text.clone())],
+                        branches: Vec::new(),
+                        next: None,
+                    };
+                    self.add_tree_node(
+                        &text_node,
+                        language,
+                        Some((entity, order)),
+                        position,
+                        next_id,
+                    )?;
+                }
+                AstHole::Block(arg) => {
+                    self.add_tree_node(arg, language, Some((entity, order)), position, next_id)?;
+                }
+            }
+        }
+        self.map.insert(
+            entity,
+            State {
+                parent: parent.map(|(owner, _)| owner),
+                order: parent.map(|(_, order)| order),
+                connections: Vec::new(),
+                holes,
+                block_type: block_type.clone(),
+                position: Position(position),
+                size,
+                value,
+                color: None,
+                disabled: node.disabled,
+                function: None,
+            },
+        );
+
+        let mut bottom = position.y;
+        let mut children = Vec::new();
+        let branch_directions = block_type.get_branch_directions();
+        for (index, branch) in node.branches.iter().enumerate() {
+            // INFO: The branches are in the parse order of the block's own connectors
+            let (Some(branch), Some(&direction)) = (branch, branch_directions.get(index)) else {
+                continue;
+            };
+            let branch_position = Vec2::new(
+                position.x + TREE_BRANCH_STEP * (index + 1) as f32,
+                position.y + TREE_FLOW_STEP,
+            );
+            let (branch_entity, branch_bottom) =
+                self.add_tree_node(branch, language, None, branch_position, next_id)?;
+            bottom = bottom.max(branch_bottom);
+            children.push((direction, branch_entity, branch_position));
+        }
+        if let Some(next) = &node.next {
+            let next_position = Vec2::new(position.x, bottom + TREE_FLOW_STEP);
+            let (next_entity, next_bottom) =
+                self.add_tree_node(next, language, None, next_position, next_id)?;
+            bottom = next_bottom;
+            children.push((ConnectionDirection::Bottom, next_entity, next_position));
+        }
+
+        let mut connections = vec![None; FLOW_ORDER + 1];
+        for (direction, child, child_position) in children {
+            let order = direction.get_parse_order();
+            if connections.len() <= order {
+                connections.resize(order + 1, None);
+            }
+            connections[order] = Some((child, self.map[&child].block_type.clone()));
+            self.lines.push(UiLine {
+                from: entity,
+                from_direction: direction,
+                to: child,
+                to_direction: get_relative_direction(
+                    (&Position(child_position), &size),
+                    (&Position(position), &size),
+                ),
+            });
+        }
+        if let Some(state) = self.map.get_mut(&entity) {
+            state.connections = connections;
+        }
+        Ok((entity, bottom))
+    }
+
+    /// Reads a save of any version up to the current one. The saves from before the versions
+    /// were added are only the state and count as version 1
+    fn from_save(text: &str) -> Result<Self, String> {
+        let value =
+            serde_json::from_str::<serde_json::Value>(text).map_err(|error| error.to_string())?;
+        let (version, state) =
+            match serde_json::from_value::<SaveFile<serde_json::Value>>(value.clone()) {
+                Ok(SaveFile { version, state }) => (version, state),
+                Err(_) => (1, value),
+            };
+        let state = Self::migrate(version, state)?;
+        serde_json::from_value(state).map_err(|error| error.to_string())
+    }
+
+    /// Upgrades the state one version at a time until it is in the current version
+    fn migrate(
+        mut version: u32,
+        mut state: serde_json::Value,
+    ) -> Result<serde_json::Value, String> {
+        if version == 0 || version > SAVE_VERSION {
+            return Err(format!(
+                "The save is version {version} but only versions 1 to {SAVE_VERSION} can be loaded"
+            ));
+        }
+        while version < SAVE_VERSION {
+            match version {
+                // INFO: Version 2 saves the view of the canvas
+                1 => {
+                    let view = serde_json::to_value(ViewState::default())
+                        .map_err(|error| error.to_string())?;
+                    if let Some(state) = state.as_object_mut() {
+                        state.entry("view").or_insert(view);
+                    }
+                }
+                _ => unreachable!("Every version before the current one has a migration"),
+            }
+            version += 1;
+        }
+        Ok(state)
+    }
 }
 
 impl GamePlugin {
@@ -149,31 +438,124 @@ impl GamePlugin {
 
     fn handle_errors(
         mut reader: EventReader<ErrorEvent>,
+        mut server_reader: EventReader<ServerErrorEvent>,
         mut commands: Commands,
-        prev_error_message: Query<Entity, With<ErrorBox>>,
-        background: Query<Entity, With<BackgroundBox>>,
+        error_list: Query<(Entity, Option<&Children>), With<ErrorList>>,
+        error_config: Res<ErrorConfig>,
         mut wasm_writer: EventWriter<WASMRequest>,
     ) {
-        for event in reader.read().map(ToOwned::to_owned) {
-            wasm_writer.send(WASMRequest(wasm::Message::Error(event.0.clone())));
+        let errors = reader
+            .read()
+            .map(|event| {
+                wasm_writer.send(WASMRequest(wasm::Message::Error(event.0.clone())));
+                event.0.clone()
+            })
+            .chain(server_reader.read().map(|event| event.0.clone()))
+            .collect::<Vec<_>>();
+        if errors.is_empty() {
+            return;
+        }
+        let Ok((list, previous_errors)) = error_list.get_single() else {
+            error!("Couldn't find the error list");
+            return;
+        };
+
+        // INFO: Only the newest `max_visible` errors are kept, the oldest ones are removed first
+        let previous_errors = previous_errors
+            .map(|children| &children[..])
+            .unwrap_or_default();
+        let overflow =
+            (previous_errors.len() + errors.len()).saturating_sub(error_config.max_visible);
+        for &previous in previous_errors.iter().take(overflow) {
+            commands.entity(previous).despawn_recursive();
+        }
+        let skip = overflow.saturating_sub(previous_errors.len());
+
+        let text_style = TextStyle {
+            color: Color::WHITE,
+            ..default()
+        };
+        commands.entity(list).with_children(|parent| {
+            for error in errors.into_iter().skip(skip) {
+                parent
+                    .spawn((
+                        ErrorBoxBundle::new(),
+                        ErrorTimer(Timer::new(error_config.timeout, TimerMode::Once)),
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(error, text_style.clone()));
+                        parent
+                            .spawn(CloseErrorBundle::new())
+                            .with_children(|parent| {
+                                parent.spawn(TextBundle::from_section("×", text_style.clone()));
+                            });
+                    });
+            }
+        });
+    }
 
-            if let Ok(previous) = prev_error_message.get_single() {
-                commands.entity(previous).despawn_recursive();
+    fn handle_close_error(
+        buttons: Query<(&Interaction, &Parent), (Changed<Interaction>, With<CloseErrorButton>)>,
+        mut commands: Commands,
+    ) {
+        for (_, error_box) in buttons
+            .iter()
+            .filter(|(interaction, _)| matches!(interaction, Interaction::Pressed))
+        {
+            if let Some(error_box) = commands.get_entity(error_box.get()) {
+                error_box.despawn_recursive();
             }
+        }
+    }
 
-            let Some(mut command) = commands.get_entity(background.single()) else {
-                error!("There was more than one background entity");
-                continue;
-            };
-            command.with_children(|parent| {
-                parent.spawn((
-                    ErrorBoxBundle::new(event.0),
-                    ErrorTimer(Timer::new(Duration::from_secs(2), TimerMode::Once)),
-                ));
-            });
+    fn request_new_project(
+        mut confirm: ResMut<NewProjectConfirm>,
+        time: Res<Time>,
+        mut new_project_writer: EventWriter<NewProject>,
+        mut error_writer: EventWriter<ErrorEvent>,
+    ) {
+        let now = time.elapsed();
+        if confirm
+            .requested_at
+            .is_some_and(|requested_at| now - requested_at <= NEW_PROJECT_CONFIRM_TIME)
+        {
+            confirm.requested_at = None;
+            new_project_writer.send(NewProject);
+        } else {
+            confirm.requested_at = Some(now);
+            error_writer.send(ErrorEvent("Press Ctrl+N again to clear the canvas".into()));
         }
     }
 
+    fn handle_new_project(
+        mut reader: EventReader<NewProject>,
+        mut commands: Commands,
+        blocks: Query<Entity, (With<Block>, Without<Arg>)>,
+        lines: Query<Entity, With<UiLine>>,
+        background: Query<Entity, With<BackgroundBox>>,
+        mut ast: ResMut<Ast>,
+        mut block_data_map: ResMut<BlockDataMap>,
+        mut game_state: ResMut<GameState>,
+        mut active: ResMut<ActiveEntity>,
+        mut selection: ResMut<Selection>,
+        mut update_writer: EventWriter<UpdateAst>,
+    ) {
+        if reader.read().last().is_none() {
+            return;
+        }
+        // INFO: The args are children of the holes of their block so they go with it
+        for entity in blocks.iter().chain(lines.iter()) {
+            commands.entity(entity).despawn_recursive();
+        }
+        ast.map.clear();
+        block_data_map.map.clear();
+        block_data_map.named.clear();
+        *game_state = GameState::default();
+        active.entity = background.get_single().ok();
+        selection.entities.clear();
+        update_writer.send_default();
+    }
+
     fn poll_timer(
         mut commands: Commands,
         mut query: Query<(Entity, &mut ErrorTimer)>,
@@ -186,191 +568,306 @@ impl GamePlugin {
         }
     }
 
-    //     fn store_state(
-    //         mut game_state: ResMut<GameState>,
-    //         text_value: Query<(&TextInput, &TextInputValue)>,
-    //         block_query: Query<(Entity, &Position, &Size, &BlockType)>,
-    //         arg_query: Query<&Arg>,
-    //         ast: Res<Ast>,
-    //         block_map: Res<BlockDataMap>,
-    //         lines: Query<&UiLine>,
-    //     ) {
-    //         let mut app_state: HashMap<Entity, State> = HashMap::default();
-    //         for (entity, &position, &size, block_type) in &block_query {
-    //             let mut parent = None;
-    //             let mut order = None;
-    //             if let Ok(arg) = arg_query.get(entity) {
-    //                 parent = Some(arg.owner);
-    //                 order = Some(arg.order);
-    //             }
-    //             let connections = ast
-    //                 .map
-    //                 .get(&entity)
-    //                 .map(ToOwned::to_owned)
-    //                 .unwrap_or_default();
-
-    //             let holes = block_map
-    //                 .map
-    //                 .get(&entity)
-    //                 .map(ToOwned::to_owned)
-    //                 .unwrap_or_default();
-
-    //             let value = if block_type.name == "Text" {
-    //                 text_value.iter().find_map(|(text_input, text_value)| {
-    //                     if text_input.owner == entity {
-    //                         Some(text_value.0.clone())
-    //                     } else {
-    //                         None
-    //                     }
-    //                 })
-    //             } else {
-    //                 None
-    //             };
-    //             let state = State {
-    //                 parent,
-    //                 order,
-    //                 connections,
-    //                 holes,
-    //                 block_type: block_type.to_owned(),
-    //                 position,
-    //                 size,
-    //                 value,
-    //             };
-
-    //             app_state.insert(entity, state);
-    //         }
-    //         game_state.set_if_neq(GameState {
-    //             map: app_state,
-    //             lines: lines.into_iter().map(ToOwned::to_owned).collect(),
-    //         });
-    //         let text = serde_json::to_string(game_state.into_inner()).unwrap();
-    //         fs::write("state.json", text).unwrap();
-    //     }
-
-    //     fn load_state(
-    //         mut game_state: ResMut<GameState>,
-    //         mut commands: Commands,
-    //         background: Query<Entity, With<BackgroundBox>>,
-    //         block_children: Query<&Children>,
-    //         block_type: Query<&BlockType>,
-    //     ) {
-    //         let value: GameState =
-    //             serde_json::from_str(fs::read_to_string("state.json").unwrap().as_str()).unwrap();
-    //         game_state.set_if_neq(value);
-
-    //         let background_entity = background.single();
-
-    //         let children = block_children.get(background_entity).unwrap();
-
-    //         for &child in children {
-    //             if block_type.contains(child) {
-    //                 commands.entity(child).despawn_recursive();
-    //             }
-    //         }
-    //     }
-
-    //     fn spawn_entities_from_state(
-    //         game_state: Res<GameState>,
-    //         mut box_writer: EventWriter<SpawnUIBox>,
-    //     ) {
-    //         for (&entity, state) in &game_state.map {
-    //             let spawn_box = SpawnUIBox {
-    //                 bundle: BlockBundle::new(
-    //                     state.position.x(),
-    //                     state.position.y(),
-    //                     state.size.width(),
-    //                     state.size.height(),
-    //                     Default::default(),
-    //                     state.block_type.clone(),
-    //                 ),
-    //                 marker: Some(Marker(entity)),
-    //             };
-    //             box_writer.send(spawn_box);
-    //         }
-    //     }
-
-    //     fn load_entities(
-    //         game_state: Res<GameState>,
-    //         mut arg_writer: EventWriter<SpawnArg>,
-    //         mut ast_writer: EventWriter<AddToAst>,
-    //         markers: Query<(Entity, &Marker)>,
-    //         holes: Query<(Entity, &Hole)>,
-    //         mut text_value: Query<(&TextInput, &mut TextInputValue)>,
-    //         mut commands: Commands,
-    //     ) {
-    //         let markers: HashMap<Entity, Entity> = markers
-    //             .iter()
-    //             .map(|(entity, &Marker(new_entity))| (entity, new_entity))
-    //             .collect();
-
-    //         // Spawn all lines again
-    //         for line in &game_state.lines {
-    //             let new_line = UiLine {
-    //                 from: markers.get(&line.from).unwrap().to_owned(),
-    //                 to_direction: line.to_direction,
-    //                 to: markers.get(&line.to).unwrap().to_owned(),
-    //                 from_direction: line.from_direction,
-    //             };
-
-    //             let block_type = game_state.map.get(&line.to).unwrap().block_type.clone();
-
-    //             ast_writer.send(AddToAst {
-    //                 parent: Some((new_line.from, new_line.from_direction.get_parse_order())),
-    //                 child: (new_line.to, block_type),
-    //             });
-
-    //             commands.spawn(new_line);
-    //         }
-
-    //         // Spawn all args and load all text back into the block
-    //         for (entity, state) in game_state
-    //             .map
-    //             .iter()
-    //             .filter(|(_, state)| state.parent.is_some())
-    //         {
-    //             let &new_parent = state
-    //                 .parent
-    //                 .map(|parent| markers.get(&parent).unwrap())
-    //                 .unwrap();
-
-    //             let &child_entity = markers.get(entity).unwrap();
-
-    //             if state.block_type.name == "Text" {
-    //                 let mut text_input = text_value
-    //                     .iter_mut()
-    //                     .find_map(|(text_input, text_value)| {
-    //                         if text_input.owner == child_entity {
-    //                             Some(text_value)
-    //                         } else {
-    //                             None
-    //                         }
-    //                     })
-    //                     .expect("Couldn't get the text");
-    //                 text_input.0 = state.value.clone().unwrap_or_default();
-    //             }
-
-    //             let mut holes = holes
-    //                 .iter()
-    //                 .filter(|(_, hole)| hole.owner == new_parent)
-    //                 .collect::<Vec<_>>();
-
-    //             holes.sort_by(|(_, x), (_, other)| x.order.cmp(&other.order));
-
-    //             if let Some(order) = state.order {
-    //                 let (hole, _) = holes[order];
-    //                 arg_writer.send(SpawnArg {
-    //                     arg: child_entity,
-    //                     parent: hole,
-    //                 });
-    //             }
-    //         }
-    //     }
+    #[allow(clippy::too_many_arguments)]
+    fn store_state(
+        blocks: Query<
+            (
+                Entity,
+                &Position,
+                &Size,
+                &BlockType,
+                Option<&Arg>,
+                Option<&ColorOverride>,
+                Has<Disabled>,
+                Option<&FunctionData>,
+            ),
+            With<Block>,
+        >,
+        holes: Query<&Hole>,
+        hole_values: Query<&HoleValue>,
+        text_inputs: Query<
+            (&TextInput, &TextInputValue),
+            (
+                Without<HoleValue>,
+                Without<FunctionNameComponent>,
+                Without<FunctionArgComponent>,
+            ),
+        >,
+        ast: Res<Ast>,
+        lines: Query<&UiLine>,
+        ui_scale: Res<UiScale>,
+        mut game_state: ResMut<GameState>,
+        mut error_writer: EventWriter<ErrorEvent>,
+    ) {
+        let map = blocks
+            .iter()
+            .map(
+                |(
+                    entity,
+                    &position,
+                    &size,
+                    block_type,
+                    arg,
+                    color_override,
+                    disabled,
+                    function,
+                )| {
+                    let hole = arg.and_then(|arg| holes.get(arg.owner).ok());
+                    let state = State {
+                        parent: hole.map(|hole| hole.owner),
+                        order: hole.map(|hole| hole.order),
+                        connections: ast.map.get(&entity).cloned().unwrap_or_default(),
+                        holes: hole_values
+                            .iter()
+                            .filter(|hole_value| hole_value.owner == entity)
+                            .map(|hole_value| {
+                                BlockData::value(hole_value.order, hole_value.value.clone())
+                            })
+                            .collect(),
+                        block_type: block_type.clone(),
+                        position,
+                        size,
+                        value: text_inputs
+                            .iter()
+                            .find(|(text_input, _)| text_input.owner == entity)
+                            .map(|(_, value)| value.0.clone()),
+                        color: color_override.map(|color| color.0),
+                        disabled,
+                        function: function.cloned(),
+                    };
+                    (entity, state)
+                },
+            )
+            .collect::<HashMap<_, _>>();
+        // INFO: The line that is being drawn still ends at a connector and isn't saved
+        let lines = lines
+            .iter()
+            .filter(|line| map.contains_key(&line.from) && map.contains_key(&line.to))
+            .copied()
+            .collect();
+        let view = ViewState { scale: ui_scale.0 };
+        game_state.set_if_neq(GameState { map, lines, view });
+
+        let result = game_state.to_save().and_then(|text| {
+            if cfg!(target_family = "wasm") {
+                download_file(SAVE_PATH, &text).map_err(|error| ErrorEvent::take_js_error(error).0)
+            } else {
+                fs::write(SAVE_PATH, text).map_err(|error| error.to_string())
+            }
+        });
+        match result {
+            Ok(()) => info!("Saved the project to {SAVE_PATH}"),
+            Err(error) => {
+                error_writer.send(ErrorEvent(format!("Couldn't save the project: {error}")));
+            }
+        }
+    }
+
+    fn load_state(
+        mut load_writer: EventWriter<LoadGameState>,
+        mut error_writer: EventWriter<ErrorEvent>,
+    ) {
+        let state = fs::read_to_string(SAVE_PATH)
+            .map_err(|error| error.to_string())
+            .and_then(|text| GameState::from_save(&text));
+        match state {
+            Ok(state) => {
+                load_writer.send(LoadGameState(state));
+            }
+            Err(error) => {
+                error_writer.send(ErrorEvent(format!("Couldn't load the project: {error}")));
+            }
+        }
+    }
+
+    fn import_tree(
+        languages: Res<Languages>,
+        mut load_writer: EventWriter<LoadGameState>,
+        mut error_writer: EventWriter<ErrorEvent>,
+    ) {
+        let state = fs::read_to_string(TREE_PATH)
+            .map_err(|error| error.to_string())
+            .and_then(|text| {
+                serde_json::from_str::<AstNode>(&text).map_err(|error| error.to_string())
+            })
+            .and_then(|tree| GameState::from_tree(&tree, languages.active()));
+        match state {
+            Ok(state) => {
+                load_writer.send(LoadGameState(state));
+            }
+            Err(error) => {
+                error_writer.send(ErrorEvent(format!(
+                    "Couldn't import the program from {TREE_PATH}: {error}"
+                )));
+            }
+        }
+    }
+
+    /// Replaces everything on the canvas with the state
+    #[allow(clippy::too_many_arguments)]
+    fn handle_load_game_state(
+        mut reader: EventReader<LoadGameState>,
+        mut game_state: ResMut<GameState>,
+        mut commands: Commands,
+        blocks: Query<Entity, (With<Block>, Without<Arg>)>,
+        lines: Query<Entity, With<UiLine>>,
+        background: Query<Entity, With<BackgroundBox>>,
+        languages: Res<Languages>,
+        mut ui_scale: ResMut<UiScale>,
+        mut ast: ResMut<Ast>,
+        mut add_ast_writer: EventWriter<AddToAst>,
+        mut connector_writer: EventWriter<SpawnConnector>,
+    ) {
+        let Some(LoadGameState(state)) = reader.read().last().cloned() else {
+            return;
+        };
+        let Ok(background) = background.get_single() else {
+            return;
+        };
+
+        for entity in blocks.iter().chain(lines.iter()) {
+            commands.entity(entity).despawn_recursive();
+        }
+        ast.map.clear();
+        ui_scale.0 = state.view.scale;
+
+        let new_entities = Self::spawn_game_state(&mut commands, background, &state, &languages);
+        for (old_entity, block) in &state.map {
+            let Some(&entity) = new_entities.get(old_entity) else {
+                continue;
+            };
+            add_ast_writer.send(AddToAst {
+                parent: None,
+                child: (entity, block.block_type.clone()),
+            });
+            // INFO: The connectors of the args were never spawned so they don't have to be removed
+            if block.parent.is_none() {
+                UIBoxPlugin::send_connectors(
+                    &mut connector_writer,
+                    entity,
+                    &block.block_type.connectors,
+                );
+            }
+        }
+        for line in &state.lines {
+            let (Some(&from), Some(&to), Some(to_block)) = (
+                new_entities.get(&line.from),
+                new_entities.get(&line.to),
+                state.map.get(&line.to),
+            ) else {
+                continue;
+            };
+            let line = UiLine { from, to, ..*line };
+            commands.spawn(LineBundle::connected(line));
+            add_ast_writer.send(AddToAst {
+                parent: Some((from, line.from_direction.get_parse_order())),
+                child: (to, to_block.block_type.clone()),
+            });
+        }
+        *game_state = state;
+    }
+
+    /// Spawns all the blocks of the state in one go and gives back the entities they were
+    /// spawned as by their saved entity. The args are built inside the holes of their block
+    /// instead of being moved in to them once every block is in the world, so the whole
+    /// hierarchy is made by the commands of a single system
+    pub(crate) fn spawn_game_state(
+        commands: &mut Commands,
+        background: Entity,
+        state: &GameState,
+        languages: &Languages,
+    ) -> HashMap<Entity, Entity> {
+        let mut new_entities = HashMap::default();
+        commands.entity(background).with_children(|parent| {
+            for (&old_entity, _) in state.map.iter().filter(|(_, block)| block.parent.is_none()) {
+                Self::spawn_saved_block(
+                    parent,
+                    old_entity,
+                    None,
+                    state,
+                    languages,
+                    &mut new_entities,
+                );
+            }
+        });
+        new_entities
+    }
+
+    fn spawn_saved_block(
+        parent: &mut ChildBuilder,
+        old_entity: Entity,
+        arg: Option<Arg>,
+        state: &GameState,
+        languages: &Languages,
+        new_entities: &mut HashMap<Entity, Entity>,
+    ) {
+        let Some(block) = state.map.get(&old_entity) else {
+            return;
+        };
+        let mut bundle = BlockBundle::new(
+            block.position.0.x,
+            block.position.0.y,
+            block.size.0.x,
+            block.size.0.y,
+            InteractionFocusBundle::default(),
+            block.block_type.clone(),
+        );
+        if arg.is_some() {
+            bundle = bundle.into_arg();
+        }
+        let language = languages
+            .get(&block.block_type.language)
+            .unwrap_or_else(|| languages.active());
+
+        let mut ui_box = UIBoxPlugin::spawn_block(
+            parent,
+            bundle,
+            block.value.clone(),
+            &block.holes,
+            language,
+            &mut |hole_parent, hole| {
+                let Some((&arg_entity, _)) = state.map.iter().find(|(_, arg)| {
+                    arg.parent == Some(old_entity) && arg.order == Some(hole.order)
+                }) else {
+                    return false;
+                };
+                let arg = Arg {
+                    owner: hole_parent.parent_entity(),
+                    order: hole.order,
+                };
+                Self::spawn_saved_block(
+                    hole_parent,
+                    arg_entity,
+                    Some(arg),
+                    state,
+                    languages,
+                    new_entities,
+                );
+                true
+            },
+        );
+        if let Some(arg) = arg {
+            ui_box.insert(arg);
+        }
+        if let Some(color) = block.color {
+            ui_box.insert(ColorOverride(color));
+        }
+        if block.disabled {
+            ui_box.insert(Disabled);
+        }
+        if let Some(function) = &block.function {
+            ui_box.insert(function.clone());
+        }
+        new_entities.insert(old_entity, ui_box.id());
+    }
 }
 
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
         let app = app
             .init_resource::<GameState>()
+            .init_resource::<ErrorConfig>()
+            .init_resource::<NewProjectConfirm>()
             .configure_sets(Update, (GameSets::Despawn, GameSets::Running).chain())
             .add_systems(PreStartup, set_background_color)
             .add_systems(
@@ -383,22 +880,31 @@ impl Plugin for GamePlugin {
                 Update,
                 (
                     Self::handle_delete_block.in_set(GameSets::Despawn),
+                    (
+                        Self::request_new_project.run_if(control_just_pressed(KeyCode::KeyN)),
+                        Self::handle_new_project,
+                        UIBoxPlugin::spawn_initial_box.run_if(on_event::<NewProject>()),
+                    )
+                        .chain()
+                        .in_set(GameSets::Despawn),
                     Self::poll_timer,
-                    // Self::store_state.run_if(input_just_pressed(KeyCode::KeyZ)),
-                    // Self::load_state.run_if(input_just_pressed(KeyCode::KeyL)),
-                    // (
-                    //     Self::spawn_entities_from_state,
-                    //     apply_deferred,
-                    //     Self::load_entities,
-                    //     apply_deferred,
-                    // )
-                    //     .chain()
-                    //     .run_if(input_just_pressed(KeyCode::KeyI)),
+                    Self::handle_close_error,
+                    Self::store_state.run_if(control_just_pressed(KeyCode::KeyS)),
+                    (
+                        Self::load_state.run_if(control_just_pressed(KeyCode::KeyO)),
+                        Self::import_tree.run_if(control_just_pressed(KeyCode::KeyL)),
+                        Self::handle_load_game_state,
+                    )
+                        .chain()
+                        .in_set(GameSets::Despawn),
                 ),
             )
             .add_systems(Last, Self::handle_errors)
             .add_event::<DeleteEvent>()
             .add_event::<ErrorEvent>()
+            .add_event::<ServerErrorEvent>()
+            .add_event::<NewProject>()
+            .add_event::<LoadGameState>()
             // .add_plugins(BoxPlugin)
             .add_plugins(FocusPlugin)
             .add_plugins(UiLinePlugin)
@@ -407,9 +913,291 @@ impl Plugin for GamePlugin {
             .add_plugins(UIBoxPlugin)
             .add_plugins(CustomTextInputPlugin)
             .add_plugins(CameraPlugin)
-            .add_plugins(ConnectorPlugin);
+            .add_plugins(ThemePlugin)
+            .add_plugins(TooltipPlugin)
+            .add_plugins(BlockPanelPlugin)
+            .add_plugins(ClipboardPlugin)
+            .add_plugins(CodePanelPlugin)
+            .add_plugins(CollisionPlugin)
+            .add_plugins(ConnectorPlugin)
+            .add_plugins(DiagramPlugin)
+            .add_plugins(FunctionPlugin)
+            .add_plugins(HelpPlugin);
         if cfg!(target_family = "wasm") {
             app.add_plugins(wasm::WASMPlugin);
         }
+        #[cfg(all(not(target_family = "wasm"), feature = "native-backend"))]
+        app.add_plugins(native::NativeBackendPlugin);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+    use crate::{connectors::ConnectionDirection, ui_box::HoleOrderLabel, utils::Language};
+
+    const BACKGROUND_SIZE: Vec2 = Vec2::new(600., 400.);
+
+    #[test]
+    fn translates_without_zoom() {
+        let world = translate_vec_to_world(Vec2::new(100., 50.), BACKGROUND_SIZE, 1.);
+        assert_eq!(world, Vec2::new(-200., 150.));
+    }
+
+    #[test]
+    fn translates_with_zoom() {
+        let world = translate_vec_to_world(Vec2::new(100., 50.), BACKGROUND_SIZE, 2.);
+        assert_eq!(world, Vec2::new(-400., 300.));
+        // INFO: The center of the background stays in the center of the world
+        let center = translate_vec_to_world(BACKGROUND_SIZE / 2., BACKGROUND_SIZE, 0.5);
+        assert_eq!(center, Vec2::ZERO);
+    }
+
+    /// A program of Print blocks that are connected one after another, each one with a Text
+    /// block in its hole
+    fn generated_state(prints: u32) -> GameState {
+        let language = Language::new();
+        let print = language.get_block("Print").expect("Print should exist");
+        let text = language.get_block("Text").expect("Text should exist");
+        let mut map = HashMap::default();
+        let mut lines = Vec::new();
+        for index in 0..prints {
+            let print_entity = Entity::from_raw(index * 2);
+            let text_entity = Entity::from_raw(index * 2 + 1);
+            let position = Position(Vec2::new(0., index as f32 * 60.));
+            map.insert(
+                print_entity,
+                State {
+                    parent: None,
+                    order: None,
+                    connections: Vec::new(),
+                    holes: Vec::new(),
+                    block_type: print.clone(),
+                    position,
+                    size: Size(Vec2::new(80., 40.)),
+                    value: None,
+                    color: None,
+                    disabled: false,
+                    function: None,
+                },
+            );
+            map.insert(
+                text_entity,
+                State {
+                    parent: Some(print_entity),
+                    order: Some(0),
+                    connections: Vec::new(),
+                    holes: Vec::new(),
+                    block_type: text.clone(),
+                    position,
+                    size: Size(Vec2::new(40., 20.)),
+                    value: Some(format!("line {index}")),
+                    color: None,
+                    disabled: false,
+                    function: None,
+                },
+            );
+            if index > 0 {
+                lines.push(UiLine {
+                    from: Entity::from_raw((index - 1) * 2),
+                    from_direction: ConnectionDirection::Bottom,
+                    to: print_entity,
+                    to_direction: ConnectionDirection::Top,
+                });
+            }
+        }
+        GameState {
+            map,
+            lines,
+            view: ViewState::default(),
+        }
+    }
+
+    #[test]
+    fn loads_a_large_project_in_one_pass() {
+        // INFO: Loading used to send a `SpawnUIBox` for every block and could only move the args
+        // in to their holes once those were flushed, so 200 blocks took 200 `with_children` on
+        // the background, an `apply_deferred` and then a `SpawnArg` per arg that moved each one
+        // again. Now the args are built in their holes and everything is spawned by the commands
+        // of a single system, so the whole project is in the world after one flush
+        let state = generated_state(100);
+        let expected = state.map.len();
+        let args = state
+            .map
+            .iter()
+            .filter_map(|(&entity, block)| Some((entity, block.parent?)))
+            .collect::<Vec<_>>();
+
+        let mut world = World::new();
+        world.insert_resource(Languages::new());
+        let background = world.spawn(BackgroundBox).id();
+        let new_entities =
+            world.run_system_once(move |mut commands: Commands, languages: Res<Languages>| {
+                GamePlugin::spawn_game_state(&mut commands, background, &state, &languages)
+            });
+
+        assert_eq!(new_entities.len(), expected);
+        let blocks = world
+            .query_filtered::<Entity, With<Block>>()
+            .iter(&world)
+            .count();
+        assert_eq!(blocks, expected);
+        // INFO: A paste waits for the blocks with a `Marker`, a load mustn't leave any behind
+        let markers = world.query::<&Marker>().iter(&world).count();
+        assert_eq!(markers, 0);
+        for (old_arg, old_parent) in args {
+            let arg_entity = new_entities[&old_arg];
+            let arg = world
+                .get::<Arg>(arg_entity)
+                .expect("The arg should be in its hole");
+            let hole = world
+                .get::<Hole>(arg.owner)
+                .expect("The arg should be in a hole");
+            assert_eq!(hole.owner, new_entities[&old_parent]);
+            assert_eq!(
+                world.get::<Parent>(arg_entity).map(Parent::get),
+                Some(arg.owner)
+            );
+            // INFO: The hole only has its order label besides the arg, there's no placeholder
+            let children = world
+                .get::<Children>(arg.owner)
+                .expect("The hole should have children");
+            assert_eq!(children.len(), 2);
+            assert!(world.get::<HoleOrderLabel>(children[0]).is_some());
+        }
+    }
+
+    #[test]
+    fn loads_a_save_without_a_view_with_the_default_view() {
+        let state = serde_json::from_str::<GameState>(r#"{"map":{},"lines":[]}"#)
+            .expect("A save without a view should still load");
+        assert_eq!(state.view, ViewState::default());
+    }
+
+    #[test]
+    fn ignores_the_pan_that_older_saves_have() {
+        let state = serde_json::from_str::<GameState>(
+            r#"{"map":{},"lines":[],"view":{"scale":2.0,"pan":[10.0,20.0]}}"#,
+        )
+        .expect("A save with a pan should still load");
+        assert_eq!(state.view, ViewState { scale: 2. });
+    }
+
+    #[test]
+    fn migrates_a_version_1_save() {
+        let state = generated_state(3);
+        let mut v1 = serde_json::to_value(&state).expect("The state should serialize");
+        v1.as_object_mut()
+            .expect("The state should be an object")
+            .remove("view");
+        let text = serde_json::json!({ "version": 1, "state": v1 }).to_string();
+        assert_eq!(GameState::from_save(&text), Ok(state));
+    }
+
+    #[test]
+    fn saves_the_name_and_the_args_of_a_function() {
+        let mut state = generated_state(1);
+        let function = FunctionData {
+            name: "greet".into(),
+            args: vec!["name".into(), "times".into()],
+        };
+        state
+            .map
+            .get_mut(&Entity::from_raw(0))
+            .expect("The first Print should exist")
+            .function = Some(function.clone());
+        let text = state.to_save().expect("The state should save");
+        let loaded = GameState::from_save(&text).expect("The save should load");
+        assert_eq!(loaded.map[&Entity::from_raw(0)].function, Some(function));
+        assert_eq!(loaded.map[&Entity::from_raw(1)].function, None);
+    }
+
+    /// A save from before the saves had a version, it is only the state without a view
+    const LEGACY_SAVE: &str = r#"{
+        "map": {
+            "4294967308": {
+                "parent": null,
+                "order": null,
+                "connections": [null, null, null],
+                "holes": [],
+                "block_type": {
+                    "name": "Text",
+                    "language": "javascript",
+                    "holes": ["Any"],
+                    "connectors": [],
+                    "template_string": "{{1}}",
+                    "in_hole": true,
+                    "value": "Any",
+                    "concept_type": "Input"
+                },
+                "position": [250.0, 250.0],
+                "size": [56.0, 58.0],
+                "value": "dami"
+            }
+        },
+        "lines": []
+    }"#;
+
+    #[test]
+    fn loads_a_save_from_before_the_versions() {
+        let state = GameState::from_save(LEGACY_SAVE).expect("A legacy save should still load");
+        assert_eq!(state.view, ViewState::default());
+        assert_eq!(state.map.len(), 1);
+        let text = state
+            .map
+            .values()
+            .next()
+            .expect("The Text block should be loaded");
+        assert_eq!(text.block_type.name, "Text");
+        assert_eq!(text.value.as_deref(), Some("dami"));
+        assert!(!text.disabled);
+    }
+
+    #[test]
+    fn refuses_a_save_from_a_newer_version() {
+        let text = serde_json::json!({ "version": SAVE_VERSION + 1, "state": {} }).to_string();
+        assert!(GameState::from_save(&text).is_err_and(|error| error.contains("version")));
+    }
+
+    #[test]
+    fn imports_a_program_from_its_tree() {
+        let language = Language::new();
+        let node = |name: &str, holes: Vec<AstHole>| AstNode {
+            name: name.into(),
+            disabled: false,
+            holes,
+            branches: Vec::new(),
+            next: None,
+        };
+        let mut if_node = node("If", vec![AstHole::Value("true".into())]);
+        if_node.branches = vec![
+            Some(node("Print", vec![AstHole::Value("yes".into())])),
+            None,
+        ];
+        if_node.next = Some(Box::new(node("Print", vec![AstHole::Empty])));
+        let mut tree = node("Start", Vec::new());
+        tree.next = Some(Box::new(if_node));
+
+        let state = GameState::from_tree(&tree, &language).expect("The tree should import");
+        // INFO: Start, If, the two Prints and the Text block that holds "yes"
+        assert_eq!(state.map.len(), 5);
+        assert_eq!(state.lines.len(), 3);
+        let text = state
+            .map
+            .values()
+            .find(|block| block.block_type.name == "Text")
+            .expect("The value of the Print hole should be in a Text block");
+        assert_eq!(text.value.as_deref(), Some("yes"));
+        assert_eq!(text.order, Some(0));
+        // INFO: The first branch of an If is the one out of its left connector
+        assert!(state.lines.iter().any(|line| {
+            line.from_direction == ConnectionDirection::Left
+                && state.map[&line.from].block_type.name == "If"
+        }));
+
+        tree.name = "Not a block".into();
+        assert!(GameState::from_tree(&tree, &language).is_err());
     }
 }
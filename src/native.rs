@@ -0,0 +1,166 @@
+use std::{
+    io::ErrorKind,
+    net::TcpStream,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use bevy::prelude::*;
+use tungstenite::{stream::MaybeTlsStream, WebSocket};
+
+use crate::wasm::{
+    ConnectionState, Message, SocketSender, WASMPlugin, WASMRequest, WrappedMessage, WsConfig,
+};
+
+/// The sending half of the channel to the thread that owns the websocket
+#[derive(Debug, Resource)]
+struct NativeSocket {
+    sender: Mutex<Sender<String>>,
+    online: Arc<AtomicBool>,
+}
+
+/// This plugin does what `WASMPlugin` does on the web for the desktop build. The websocket lives
+/// in its own thread and talks to the app with the same `Message`s
+pub struct NativeBackendPlugin;
+
+impl NativeBackendPlugin {
+    fn run_socket(
+        url: String,
+        outgoing: Receiver<String>,
+        incoming: Arc<Mutex<Vec<Message>>>,
+        online: Arc<AtomicBool>,
+    ) {
+        loop {
+            match tungstenite::connect(url.as_str()) {
+                Ok((mut socket, _)) => {
+                    info!("socket opened");
+                    online.store(true, Ordering::Relaxed);
+                    if !Self::handle_socket(&mut socket, &outgoing, &incoming) {
+                        return;
+                    }
+                    online.store(false, Ordering::Relaxed);
+                    info!("socket closed");
+                }
+                Err(error) => error!("{error}"),
+            }
+            thread::sleep(Duration::from_secs(2));
+        }
+    }
+
+    /// Returns false when the app has stopped and the thread should exit
+    fn handle_socket(
+        socket: &mut WebSocket<MaybeTlsStream<TcpStream>>,
+        outgoing: &Receiver<String>,
+        incoming: &Arc<Mutex<Vec<Message>>>,
+    ) -> bool {
+        // INFO: Reading times out so that the thread can also send the outgoing messages
+        if let MaybeTlsStream::Plain(stream) = socket.get_mut() {
+            if let Err(error) = stream.set_read_timeout(Some(Duration::from_millis(50))) {
+                error!("{error}");
+            }
+        }
+        loop {
+            loop {
+                match outgoing.try_recv() {
+                    Ok(message) => {
+                        if let Err(error) = socket.send(tungstenite::Message::Text(message)) {
+                            error!("{error}");
+                            return true;
+                        }
+                    }
+                    Err(std::sync::mpsc::TryRecvError::Empty) => break,
+                    Err(std::sync::mpsc::TryRecvError::Disconnected) => return false,
+                }
+            }
+
+            match socket.read() {
+                Ok(tungstenite::Message::Text(data)) => {
+                    let Ok(message) = serde_json::from_str::<Message>(&data) else {
+                        error!("Couldn't parse recieved message");
+                        continue;
+                    };
+                    if let Ok(mut array) = incoming.lock() {
+                        array.push(message);
+                    }
+                }
+                Ok(_) => {}
+                Err(tungstenite::Error::Io(error))
+                    if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+                Err(error) => {
+                    error!("{error}");
+                    return true;
+                }
+            }
+        }
+    }
+
+    fn start_socket(mut commands: Commands, config: Res<WsConfig>, incoming: Res<SocketSender>) {
+        let (sender, receiver) = channel();
+        let online = Arc::new(AtomicBool::new(false));
+
+        let url = config.get_url();
+        let incoming = incoming.0.clone();
+        let thread_online = online.clone();
+        thread::spawn(move || Self::run_socket(url, receiver, incoming, thread_online));
+
+        commands.insert_resource(NativeSocket {
+            sender: Mutex::new(sender),
+            online,
+        });
+    }
+
+    fn handle_native_request(
+        socket: Res<NativeSocket>,
+        mut request_reader: EventReader<WASMRequest>,
+    ) {
+        let Ok(sender) = socket.sender.lock() else {
+            return;
+        };
+        for event in request_reader.read().map(ToOwned::to_owned) {
+            let message = WrappedMessage::new(event.0);
+            let message = match serde_json::to_string(&message) {
+                Ok(message) => message,
+                Err(error) => {
+                    error!("{error}");
+                    continue;
+                }
+            };
+            if sender.send(message).is_err() {
+                error!("The socket thread has stopped");
+            }
+        }
+    }
+
+    fn update_connection_state(socket: Res<NativeSocket>, mut state: ResMut<ConnectionState>) {
+        let new_state = if socket.online.load(Ordering::Relaxed) {
+            ConnectionState::Online
+        } else {
+            ConnectionState::Offline
+        };
+        state.set_if_neq(new_state);
+    }
+}
+
+impl Plugin for NativeBackendPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<WsConfig>()
+            .init_resource::<SocketSender>()
+            .init_resource::<ConnectionState>()
+            .add_event::<WASMRequest>()
+            .add_systems(Startup, Self::start_socket)
+            .add_systems(
+                Update,
+                (
+                    Self::update_connection_state,
+                    Self::handle_native_request,
+                    WASMPlugin::handle_channel,
+                )
+                    .chain(),
+            );
+    }
+}
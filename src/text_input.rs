@@ -5,9 +5,11 @@ use bevy_simple_text_input::{
 
 use crate::{
     ast::UpdateAst,
+    control_just_pressed,
     focus::{ActiveEntity, FocusBundle, InteractionFocusBundle, SelectEvent},
-    ui_box::{BackgroundBox, BlockBundle, SpawnUIBox},
-    utils::{BlockType, HoleType, Language},
+    theme::{FixedBackground, Theme},
+    ui_box::{Arg, BackgroundBox, BlockBundle, Hole, SpawnUIBox},
+    utils::{BlockType, HoleType, Languages},
     ErrorEvent, GameSets,
 };
 
@@ -32,16 +34,17 @@ impl CustomTextInputBundle {
                 style: Style {
                     min_width: Val::Px(30.),
                     border: UiRect::bottom(Val::Px(2.)),
-                    height: Val::Px(20.),
+                    // INFO: Multi-line text grows the text box and with it the block
+                    min_height: Val::Px(20.),
                     ..default()
                 },
-                border_color: Color::BLACK.into(),
+                border_color: Theme::LIGHT.border.into(),
                 focus_policy: FocusPolicy::Block,
                 ..default()
             },
             text_input_bundle,
             text_input: TextInput { owner },
-            focusable: FocusBundle::new(Color::RED, Color::GREEN, Color::BLACK),
+            focusable: FocusBundle::new(Color::RED, Color::GREEN, Theme::LIGHT.border),
             entity_label: crate::EntityLabel::new("Text Box"),
         }
     }
@@ -73,7 +76,7 @@ impl SearchBoxBundle {
                     min_height: Val::Px(20.),
                     ..default()
                 },
-                border_color: Color::BLACK.into(),
+                border_color: Theme::LIGHT.border.into(),
                 focus_policy: FocusPolicy::Block,
                 ..default()
             },
@@ -81,12 +84,12 @@ impl SearchBoxBundle {
                 .with_placeholder(
                     "Search for block",
                     Some(TextStyle {
-                        color: Color::BLACK,
+                        color: Theme::LIGHT.text,
                         ..default()
                     }),
                 )
                 .with_text_style(TextStyle {
-                    color: Color::BLACK,
+                    color: Theme::LIGHT.text,
                     ..default()
                 })
                 .with_settings(TextInputSettings {
@@ -95,7 +98,7 @@ impl SearchBoxBundle {
                 })
                 .with_inactive(true),
             marker: SearchBox,
-            focus: FocusBundle::new(Color::RED, Color::GREEN, Color::BLACK),
+            focus: FocusBundle::new(Color::RED, Color::GREEN, Theme::LIGHT.border),
             entity_label: crate::EntityLabel::new("Search Box"),
         }
     }
@@ -104,6 +107,126 @@ impl SearchBoxBundle {
 #[derive(Resource, Default, Debug, Clone, Copy)]
 struct IsSearchVisible(bool);
 
+/// How many blocks are listed under the search box
+const MAX_SEARCH_RESULTS: usize = 8;
+
+/// The list with the blocks that match the query of the search box
+#[derive(Component, Debug, Clone, Copy)]
+pub struct SearchResultList;
+
+/// The names of the blocks that match the query and the one that is highlighted
+#[derive(Resource, Default, Debug, Clone)]
+struct SearchResults {
+    names: Vec<String>,
+    highlighted: usize,
+}
+
+/// How many of the blocks that were spawned from the search box are remembered
+const MAX_RECENT_BLOCKS: usize = 5;
+
+/// The blocks that were spawned from the search box, the latest first, and the query that was
+/// used last which the search box is opened with again
+#[derive(Resource, Default, Debug, Clone)]
+pub struct RecentBlocks {
+    pub names: Vec<String>,
+    pub last_query: String,
+}
+
+impl RecentBlocks {
+    fn push(&mut self, name: &str) {
+        self.names.retain(|recent| recent != name);
+        self.names.insert(0, name.to_owned());
+        self.names.truncate(MAX_RECENT_BLOCKS);
+    }
+}
+
+/// How many variable names are suggested under a variable text box
+const MAX_COMPLETIONS: usize = 6;
+
+/// The list of variable names under the focused variable text box
+#[derive(Component, Debug, Clone, Copy)]
+pub struct CompletionList;
+
+/// Fills the text box with the name when it is pressed
+#[derive(Component, Debug, Clone)]
+pub struct CompletionEntry {
+    pub target: Entity,
+    pub name: String,
+}
+
+/// The text box that the variable names are suggested for and the names that are shown
+#[derive(Resource, Default, Debug, Clone)]
+struct Completions {
+    target: Option<Entity>,
+    names: Vec<String>,
+}
+
+/// Scores how well the query matches the name, the characters of the query have to be found in
+/// the name in order. Matches at the start of the name or of a word and consecutive matches score
+/// higher
+fn fuzzy_score(name: &str, query: &str) -> Option<i32> {
+    let name = name.to_lowercase().chars().collect::<Vec<_>>();
+    let mut query = query
+        .to_lowercase()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .peekable();
+    let mut score = 0;
+    let mut previous_match = None;
+    for (index, &c) in name.iter().enumerate() {
+        if query.peek() != Some(&c) {
+            continue;
+        }
+        query.next();
+        score += 1;
+        if index == 0 || name[index - 1] == ' ' {
+            score += 8;
+        }
+        if previous_match.is_some_and(|previous| previous + 1 == index) {
+            score += 4;
+        }
+        previous_match = Some(index);
+    }
+    if query.peek().is_some() {
+        return None;
+    }
+    // INFO: Shorter names are preferred when the query matches them equally well
+    Some(score * 10 - name.len() as i32)
+}
+
+/// Gives the blocks whose name fuzzily matches the query, the best matches come first
+fn search_blocks<'a>(blocks: &'a [BlockType], query: &str) -> Vec<&'a BlockType> {
+    if query.trim().is_empty() {
+        return blocks.iter().collect();
+    }
+    let mut matches = blocks
+        .iter()
+        .filter_map(|block| Some((fuzzy_score(&block.name, query)?, block)))
+        .collect::<Vec<_>>();
+    matches.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+    matches.into_iter().map(|(_, block)| block).collect()
+}
+
+/// The names of the blocks that are listed for the query, without a query the blocks that were
+/// used recently come first
+fn search_block_names(blocks: &[BlockType], query: &str, recent: &[String]) -> Vec<String> {
+    let mut names = search_blocks(blocks, query)
+        .into_iter()
+        .map(|block| block.name.clone())
+        .collect::<Vec<_>>();
+    if query.trim().is_empty() {
+        // INFO: The sort is stable so the rest stay in the order of the language
+        names.sort_by_key(|name| {
+            recent
+                .iter()
+                .position(|recent| recent == name)
+                .unwrap_or(usize::MAX)
+        });
+    }
+    names.truncate(MAX_SEARCH_RESULTS);
+    names
+}
+
 pub struct CustomTextInputPlugin;
 
 impl CustomTextInputPlugin {
@@ -130,16 +253,40 @@ impl CustomTextInputPlugin {
         }
     }
 
+    /// Shift+Enter puts a new line in the text of a multi-line block instead of submitting it
+    fn insert_newline(
+        mut reader: EventReader<TextInputSubmitEvent>,
+        keys: Res<ButtonInput<KeyCode>>,
+        block_types: Query<&BlockType>,
+        mut text_inputs: Query<(&TextInput, &mut TextInputValue)>,
+    ) {
+        let shift = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+        for event in reader.read() {
+            let Ok((text_input, mut value)) = text_inputs.get_mut(event.entity) else {
+                continue;
+            };
+            if shift
+                && block_types
+                    .get(text_input.owner)
+                    .is_ok_and(BlockType::is_multiline)
+            {
+                // INFO: Submitting cleared the text so it is put back with the new line
+                value.0 = format!("{}\n", event.value);
+            }
+        }
+    }
+
     fn set_text_block_type(
         mut block_types: Query<&mut BlockType>,
         text_query: Query<(&TextInput, &TextInputValue), Changed<TextInputInactive>>,
         mut reader: EventReader<SelectEvent>,
+        languages: Res<Languages>,
     ) {
         if reader.read().next().is_some() {
             for (text_input, value) in &text_query {
                 if let Ok(mut block_type) = block_types.get_mut(text_input.owner) {
                     if block_type.name == "Text" {
-                        block_type.value = HoleType::get_derived_type(value.0.as_str());
+                        block_type.value = languages.active().get_derived_type(value.0.as_str());
                     }
                 }
             }
@@ -147,42 +294,54 @@ impl CustomTextInputPlugin {
     }
 
     fn spawn_search_box(mut commands: Commands, background: Query<Entity, With<BackgroundBox>>) {
-        commands
-            .entity(background.single())
-            .with_children(|parent| {
-                parent
-                    .spawn((
+        let Ok(background) = background.get_single() else {
+            return;
+        };
+        commands.entity(background).with_children(|parent| {
+            parent
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            bottom: Val::Px(0.),
+                            width: Val::Percent(100.),
+                            flex_direction: FlexDirection::Column,
+                            min_height: Val::Px(20.),
+                            padding: UiRect::all(Val::Px(5.)),
+                            ..default()
+                        },
+                        visibility: Visibility::Hidden,
+                        focus_policy: FocusPolicy::Pass,
+                        ..default()
+                    },
+                    SearchContainer,
+                ))
+                .with_children(|parent| {
+                    parent.spawn((
+                        TextBundle::from_section(
+                            "Search Box",
+                            TextStyle {
+                                color: Theme::LIGHT.text,
+                                ..default()
+                            },
+                        )
+                        .with_text_justify(JustifyText::Left),
+                        Label,
+                    ));
+                    parent.spawn((
                         NodeBundle {
                             style: Style {
-                                position_type: PositionType::Absolute,
-                                bottom: Val::Px(0.),
-                                width: Val::Percent(100.),
                                 flex_direction: FlexDirection::Column,
-                                min_height: Val::Px(20.),
-                                padding: UiRect::all(Val::Px(5.)),
                                 ..default()
                             },
-                            visibility: Visibility::Hidden,
                             focus_policy: FocusPolicy::Pass,
                             ..default()
                         },
-                        SearchContainer,
-                    ))
-                    .with_children(|parent| {
-                        parent.spawn((
-                            TextBundle::from_section(
-                                "Search Box",
-                                TextStyle {
-                                    color: Color::BLACK,
-                                    ..default()
-                                },
-                            )
-                            .with_text_justify(JustifyText::Left),
-                            Label,
-                        ));
-                        parent.spawn(SearchBoxBundle::new());
-                    });
-            });
+                        SearchResultList,
+                    ));
+                    parent.spawn(SearchBoxBundle::new());
+                });
+        });
     }
 
     fn handle_visiblity(
@@ -201,33 +360,275 @@ impl CustomTextInputPlugin {
     fn toggle_visibility(
         mut is_visible: ResMut<IsSearchVisible>,
         mut value_query: Query<(Entity, &mut TextInputValue), With<SearchBox>>,
+        recent: Res<RecentBlocks>,
         mut writer: EventWriter<SelectEvent>,
     ) {
+        let Ok((entity, mut value)) = value_query.get_single_mut() else {
+            return;
+        };
         // Toggle the visibility
         is_visible.0 = !is_visible.0;
-        // Clear the search box
-        let (entity, mut value) = value_query.single_mut();
-        value.0.clear();
+        // INFO: The search box starts with the last query, setting it also refreshes the results
+        value.0.clone_from(&recent.last_query);
         // Focus on the search box
         writer.send(SelectEvent(Some(entity)));
     }
 
+    fn close_search_box(
+        mut is_visible: ResMut<IsSearchVisible>,
+        background: Query<Entity, With<BackgroundBox>>,
+        mut writer: EventWriter<SelectEvent>,
+    ) {
+        if is_visible.0 {
+            is_visible.0 = false;
+            // INFO: The search box stops taking the keys once something else is focused
+            writer.send(SelectEvent(background.get_single().ok()));
+        }
+    }
+
+    fn update_search_results(
+        query: Query<&TextInputValue, (With<SearchBox>, Changed<TextInputValue>)>,
+        languages: Res<Languages>,
+        recent: Res<RecentBlocks>,
+        mut results: ResMut<SearchResults>,
+    ) {
+        let Ok(value) = query.get_single() else {
+            return;
+        };
+        results.names = search_block_names(&languages.active().blocks, &value.0, &recent.names);
+        results.highlighted = 0;
+    }
+
+    fn navigate_search_results(
+        keys: Res<ButtonInput<KeyCode>>,
+        active_entity: Res<ActiveEntity>,
+        search_box: Query<(), With<SearchBox>>,
+        mut results: ResMut<SearchResults>,
+    ) {
+        if !active_entity
+            .entity
+            .is_some_and(|entity| search_box.contains(entity))
+            || results.names.is_empty()
+        {
+            return;
+        }
+        let last = results.names.len() - 1;
+        if keys.just_pressed(KeyCode::ArrowUp) {
+            results.highlighted = results.highlighted.checked_sub(1).unwrap_or(last);
+        }
+        if keys.just_pressed(KeyCode::ArrowDown) {
+            results.highlighted = if results.highlighted >= last {
+                0
+            } else {
+                results.highlighted + 1
+            };
+        }
+    }
+
+    fn show_search_results(
+        results: Res<SearchResults>,
+        list: Query<Entity, With<SearchResultList>>,
+        mut commands: Commands,
+    ) {
+        let Ok(list) = list.get_single() else {
+            return;
+        };
+        let mut list = commands.entity(list);
+        list.despawn_descendants();
+        list.with_children(|parent| {
+            for (index, name) in results.names.iter().enumerate() {
+                let background_color = if index == results.highlighted {
+                    Theme::LIGHT.control_flow
+                } else {
+                    Color::NONE
+                };
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            padding: UiRect::horizontal(Val::Px(4.)),
+                            ..default()
+                        },
+                        background_color: background_color.into(),
+                        focus_policy: FocusPolicy::Pass,
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        parent.spawn(TextBundle::from_section(
+                            name.clone(),
+                            TextStyle {
+                                color: Theme::LIGHT.text,
+                                font_size: 16.,
+                                ..default()
+                            },
+                        ));
+                    });
+            }
+        });
+    }
+
+    /// Checks if the text box holds a variable name, either because its block is a variable or
+    /// because its block is in a variable hole
+    fn is_variable_input(
+        text_input: &TextInput,
+        block_types: &Query<&BlockType>,
+        args: &Query<&Arg>,
+        holes: &Query<&Hole>,
+    ) -> bool {
+        block_types
+            .get(text_input.owner)
+            .is_ok_and(|block_type| block_type.value == HoleType::Variable)
+            || args
+                .get(text_input.owner)
+                .ok()
+                .and_then(|arg| holes.get(arg.owner).ok())
+                .is_some_and(|hole| hole.hole_type == HoleType::Variable)
+    }
+
+    fn update_completions(
+        active_entity: Res<ActiveEntity>,
+        text_inputs: Query<(Entity, &TextInput, &TextInputValue)>,
+        block_types: Query<&BlockType>,
+        args: Query<&Arg>,
+        holes: Query<&Hole>,
+        mut completions: ResMut<Completions>,
+    ) {
+        let target = active_entity.entity.and_then(|entity| {
+            let (_, text_input, value) = text_inputs.get(entity).ok()?;
+            Self::is_variable_input(text_input, &block_types, &args, &holes)
+                .then_some((entity, value.0.as_str()))
+        });
+        let Some((target, prefix)) = target else {
+            if completions.target.is_some() {
+                *completions = Completions::default();
+            }
+            return;
+        };
+
+        let mut names = text_inputs
+            .iter()
+            .filter(|&(entity, text_input, _)| {
+                entity != target && Self::is_variable_input(text_input, &block_types, &args, &holes)
+            })
+            .map(|(_, _, value)| value.0.clone())
+            .filter(|name| name.starts_with(prefix) && name != prefix && !name.is_empty())
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        names.dedup();
+        names.truncate(MAX_COMPLETIONS);
+
+        if completions.target != Some(target) || completions.names != names {
+            *completions = Completions {
+                target: Some(target),
+                names,
+            };
+        }
+    }
+
+    fn show_completions(
+        completions: Res<Completions>,
+        lists: Query<Entity, With<CompletionList>>,
+        targets: Query<(&Node, &GlobalTransform)>,
+        mut commands: Commands,
+    ) {
+        for list in &lists {
+            commands.entity(list).despawn_recursive();
+        }
+        let Some((target, (node, transform))) = completions
+            .target
+            .filter(|_| !completions.names.is_empty())
+            .and_then(|target| Some((target, targets.get(target).ok()?)))
+        else {
+            return;
+        };
+
+        // INFO: The list is put right under the text box
+        let bottom_left = transform.translation().truncate() + node.size() * Vec2::new(-0.5, 0.5);
+        commands
+            .spawn((
+                NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        left: Val::Px(bottom_left.x),
+                        top: Val::Px(bottom_left.y),
+                        flex_direction: FlexDirection::Column,
+                        padding: UiRect::all(Val::Px(2.)),
+                        ..default()
+                    },
+                    background_color: BackgroundColor(Color::rgba(0., 0., 0., 0.8)),
+                    focus_policy: FocusPolicy::Block,
+                    z_index: ZIndex::Global(15),
+                    ..default()
+                },
+                CompletionList,
+                FixedBackground,
+            ))
+            .with_children(|parent| {
+                for name in &completions.names {
+                    parent
+                        .spawn((
+                            ButtonBundle {
+                                style: Style {
+                                    padding: UiRect::horizontal(Val::Px(4.)),
+                                    ..default()
+                                },
+                                background_color: BackgroundColor(Color::NONE),
+                                ..default()
+                            },
+                            CompletionEntry {
+                                target,
+                                name: name.clone(),
+                            },
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section(
+                                name.clone(),
+                                TextStyle {
+                                    color: Color::WHITE,
+                                    font_size: 14.,
+                                    ..default()
+                                },
+                            ));
+                        });
+                }
+            });
+    }
+
+    fn handle_completion_press(
+        entries: Query<(&Interaction, &CompletionEntry), Changed<Interaction>>,
+        mut values: Query<&mut TextInputValue>,
+    ) {
+        for (_, entry) in entries
+            .iter()
+            .filter(|(interaction, _)| matches!(interaction, Interaction::Pressed))
+        {
+            if let Ok(mut value) = values.get_mut(entry.target) {
+                value.0.clone_from(&entry.name);
+            }
+        }
+    }
+
     fn handle_search_box_submit(
         mut reader: EventReader<TextInputSubmitEvent>,
         mut error_writer: EventWriter<ErrorEvent>,
         mut spawn_box: EventWriter<SpawnUIBox>,
-        language: Res<Language>,
+        languages: Res<Languages>,
+        results: Res<SearchResults>,
+        mut recent: ResMut<RecentBlocks>,
         search_box: Query<&SearchBox>,
         background: Query<&Node, With<BackgroundBox>>,
     ) {
         for event in reader.read() {
             if search_box.get(event.entity).is_ok() {
                 info!("Search Box Submit: {:?}", event.value);
-                let Some(blocks) = language.blocks.iter().find(|block| {
-                    block
-                        .name
-                        .to_lowercase()
-                        .contains(event.value.to_lowercase().as_str())
+                // INFO: The highlighted result is spawned, the list is made from the same value
+                let highlighted = results
+                    .names
+                    .get(results.highlighted)
+                    .and_then(|name| languages.active().get_block(name));
+                let Some(blocks) = highlighted.or_else(|| {
+                    search_blocks(&languages.active().blocks, &event.value)
+                        .first()
+                        .map(|&block| block.to_owned())
                 }) else {
                     error_writer.send(ErrorEvent(format!(
                         "Block with name {} not found",
@@ -235,16 +636,22 @@ impl CustomTextInputPlugin {
                     )));
                     continue;
                 };
-                let coordinates = background.single().size() / 2.;
+                let Ok(background) = background.get_single() else {
+                    continue;
+                };
+                recent.push(&blocks.name);
+                recent.last_query.clone_from(&event.value);
+                let coordinates = background.size() / 2.;
                 spawn_box.send(SpawnUIBox {
                     marker: None,
+                    text: None,
                     bundle: BlockBundle::new(
                         coordinates.x,
                         coordinates.y,
                         40.,
                         40.,
                         InteractionFocusBundle::default(),
-                        blocks.to_owned(),
+                        blocks,
                     ),
                 });
             }
@@ -255,15 +662,29 @@ impl CustomTextInputPlugin {
 impl Plugin for CustomTextInputPlugin {
     fn build(&self, app: &mut App) {
         app.init_resource::<IsSearchVisible>()
+            .init_resource::<SearchResults>()
+            .init_resource::<RecentBlocks>()
+            .init_resource::<Completions>()
             .add_systems(PostStartup, Self::spawn_search_box)
             .add_systems(
                 Update,
                 (
                     Self::handle_text_focus,
+                    Self::insert_newline,
                     Self::set_text_block_type,
                     Self::handle_visiblity,
-                    Self::toggle_visibility.run_if(input_just_pressed(KeyCode::Slash)),
+                    Self::toggle_visibility
+                        .run_if(input_just_pressed(KeyCode::Slash))
+                        // INFO: Ctrl+/ disables the active block instead
+                        .run_if(not(control_just_pressed(KeyCode::Slash))),
+                    Self::close_search_box.run_if(input_just_pressed(KeyCode::Escape)),
+                    Self::navigate_search_results,
                     Self::handle_search_box_submit,
+                    Self::update_search_results,
+                    Self::show_search_results.run_if(resource_changed::<SearchResults>),
+                    Self::handle_completion_press,
+                    Self::update_completions,
+                    Self::show_completions.run_if(resource_changed::<Completions>),
                     Self::send_update_ast,
                 )
                     .chain()
@@ -271,3 +692,49 @@ impl Plugin for CustomTextInputPlugin {
             );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::Language;
+
+    fn best_match(query: &str) -> Option<String> {
+        let language = Language::new();
+        search_blocks(&language.blocks, query)
+            .first()
+            .map(|block| block.name.clone())
+    }
+
+    #[test]
+    fn matches_a_subsequence() {
+        assert_eq!(best_match("prnt").as_deref(), Some("Print"));
+    }
+
+    #[test]
+    fn prefers_a_prefix_match() {
+        assert_eq!(best_match("decl").as_deref(), Some("Declaration"));
+    }
+
+    #[test]
+    fn rejects_out_of_order_characters() {
+        assert_eq!(fuzzy_score("Print", "tnrp"), None);
+    }
+
+    #[test]
+    fn lists_the_recent_blocks_first_without_a_query() {
+        let language = Language::new();
+        let mut recent = RecentBlocks::default();
+        recent.push("Print");
+        recent.push("If");
+        recent.push("Print");
+        assert_eq!(recent.names, ["Print", "If"]);
+
+        let names = search_block_names(&language.blocks, "", &recent.names);
+        assert_eq!(names[..2], ["Print", "If"]);
+        // INFO: The recent blocks don't change the order of the matches of a query
+        assert_eq!(
+            search_block_names(&language.blocks, "decl", &recent.names).first(),
+            best_match("decl").as_ref()
+        );
+    }
+}
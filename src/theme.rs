@@ -0,0 +1,428 @@
+use bevy::prelude::*;
+use bevy_simple_text_input::TextInputTextStyle;
+
+use crate::{
+    connectors::ConnectionDirection,
+    control_just_pressed,
+    focus::{Focus, FocusColor},
+    ui_box::ColorOverride,
+    utils::ConceptType,
+    GameSets,
+};
+
+/// The colors that the UI is drawn with. The bundles are made with `Theme::LIGHT` and are
+/// recolored when they are spawned if another theme is used
+#[derive(Debug, Resource, Clone, Copy, PartialEq)]
+pub struct Theme {
+    pub background: Color,
+    pub text: Color,
+    pub border: Color,
+    pub connector: Color,
+    pub error: Color,
+    pub control_flow: Color,
+    pub input: Color,
+    pub output: Color,
+    /// The border of the holes a dragged block can be dropped in
+    pub highlight: Color,
+    /// The border of a Text block whose text fits the hole it is in
+    pub valid: Color,
+}
+
+impl Theme {
+    pub const LIGHT: Self = Self {
+        background: Color::WHITE,
+        text: Color::BLACK,
+        border: Color::BLACK,
+        connector: Color::BLACK,
+        error: Color::RED,
+        control_flow: Color::rgb_u8(170, 203, 253),
+        input: Color::rgb_u8(208, 227, 218),
+        output: Color::rgb_u8(252, 240, 137),
+        highlight: Color::ORANGE,
+        valid: Color::GREEN,
+    };
+
+    pub const DARK: Self = Self {
+        background: Color::rgb(0.12, 0.12, 0.14),
+        text: Color::rgb(0.9, 0.9, 0.9),
+        border: Color::rgb(0.75, 0.75, 0.75),
+        connector: Color::rgb(0.85, 0.85, 0.85),
+        error: Color::rgb(0.7, 0.15, 0.15),
+        control_flow: Color::rgb(0.2, 0.31, 0.47),
+        input: Color::rgb(0.21, 0.33, 0.27),
+        output: Color::rgb(0.47, 0.42, 0.12),
+        highlight: Color::rgb(0.95, 0.6, 0.2),
+        valid: Color::rgb(0.3, 0.7, 0.35),
+    };
+
+    pub fn concept_color(&self, concept_type: &ConceptType) -> Color {
+        match concept_type {
+            ConceptType::ControlFlow => self.control_flow,
+            ConceptType::Input => self.input,
+            ConceptType::Output => self.output,
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::LIGHT
+    }
+}
+
+/// Keeps the background of an overlay as it was made, the overlays are dark with white text on
+/// every theme and their black would otherwise be taken for the color of a connector
+#[derive(Debug, Component, Clone, Copy, Default)]
+pub struct FixedBackground;
+
+/// The font that is shipped in the assets folder
+const DEFAULT_FONT_PATH: &str = "fonts/DejaVuSans.ttf";
+
+/// Where to load the font of the text from, relative to the assets folder. It can be changed with
+/// the `LEARNABLE_FONT` variable or by inserting this before the plugins, when there's no path
+/// the default font of bevy is used
+#[derive(Debug, Resource, Clone)]
+pub struct FontConfig {
+    pub path: Option<String>,
+}
+
+impl Default for FontConfig {
+    fn default() -> Self {
+        Self {
+            path: Some(
+                std::env::var("LEARNABLE_FONT").unwrap_or_else(|_| DEFAULT_FONT_PATH.to_owned()),
+            ),
+        }
+    }
+}
+
+/// The font that all the text is drawn with. Once it is loaded it takes the place of the default
+/// font, so every `TextStyle` that doesn't set a font uses it
+#[derive(Debug, Resource, Clone, Default)]
+pub struct UiFont(pub Handle<Font>);
+
+/// The colors of the connections and of the concepts of the blocks. The colorblind palettes
+/// differ in lightness and not only in hue so that the colors can still be told apart
+#[derive(Debug, Resource, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Palette {
+    #[default]
+    Default,
+    Deuteranopia,
+    Protanopia,
+}
+
+impl Palette {
+    fn next(self) -> Self {
+        match self {
+            Palette::Default => Palette::Deuteranopia,
+            Palette::Deuteranopia => Palette::Protanopia,
+            Palette::Protanopia => Palette::Default,
+        }
+    }
+
+    pub fn connection_color(&self, direction: ConnectionDirection) -> Color {
+        let colors = match self {
+            Palette::Default => return direction.get_color(),
+            Palette::Deuteranopia => [
+                Color::rgb_u8(0, 68, 136),
+                Color::rgb_u8(187, 85, 102),
+                Color::rgb_u8(221, 170, 51),
+            ],
+            Palette::Protanopia => [
+                Color::rgb_u8(27, 42, 107),
+                Color::rgb_u8(86, 180, 233),
+                Color::rgb_u8(240, 228, 66),
+            ],
+        };
+        colors
+            .get(direction.get_parse_order())
+            .copied()
+            .unwrap_or(Color::BLACK)
+    }
+
+    pub fn concept_color(&self, concept_type: &ConceptType, theme: &Theme) -> Color {
+        let [control_flow, input, output] = match self {
+            Palette::Default => return theme.concept_color(concept_type),
+            Palette::Deuteranopia => [
+                Color::rgb_u8(127, 167, 217),
+                Color::rgb_u8(187, 204, 238),
+                Color::rgb_u8(245, 231, 161),
+            ],
+            Palette::Protanopia => [
+                Color::rgb_u8(111, 155, 209),
+                Color::rgb_u8(181, 199, 227),
+                Color::rgb_u8(255, 243, 176),
+            ],
+        };
+        let color = match concept_type {
+            ConceptType::ControlFlow => control_flow,
+            ConceptType::Input => input,
+            ConceptType::Output => output,
+        };
+        // INFO: The blocks are darker on the dark theme so that the light text can be read
+        if *theme == Theme::DARK {
+            Color::rgb(color.r() * 0.45, color.g() * 0.45, color.b() * 0.45)
+        } else {
+            color
+        }
+    }
+}
+
+/// The theme and palette that the entities are colored with
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct ColorScheme {
+    theme: Theme,
+    palette: Palette,
+}
+
+impl ColorScheme {
+    /// The bundles are made with these colors
+    const DEFAULT: Self = Self {
+        theme: Theme::LIGHT,
+        palette: Palette::Default,
+    };
+
+    /// Gives the color of this scheme that takes the place of `color`. Only the rgb is compared
+    /// so a faded block keeps its alpha
+    fn swap(color: Color, pairs: impl IntoIterator<Item = (Color, Color)>) -> Color {
+        pairs
+            .into_iter()
+            .find(|&(from, _)| from.with_a(1.) == color.with_a(1.))
+            .map_or(color, |(_, to)| to.with_a(color.a()))
+    }
+
+    fn swap_background(&self, from: &Self, color: Color) -> Color {
+        let concepts = [
+            ConceptType::ControlFlow,
+            ConceptType::Input,
+            ConceptType::Output,
+        ]
+        .map(|concept_type| {
+            (
+                from.palette.concept_color(&concept_type, &from.theme),
+                self.palette.concept_color(&concept_type, &self.theme),
+            )
+        });
+        Self::swap(
+            color,
+            [
+                (from.theme.connector, self.theme.connector),
+                (from.theme.error, self.theme.error),
+            ]
+            .into_iter()
+            .chain(concepts),
+        )
+    }
+
+    fn swap_border(&self, from: &Self, color: Color) -> Color {
+        Self::swap(color, [(from.theme.border, self.theme.border)])
+    }
+
+    fn swap_text(&self, from: &Self, color: Color) -> Color {
+        Self::swap(color, [(from.theme.text, self.theme.text)])
+    }
+
+    /// Lines and connectors have the color of their connection when they are inactive
+    fn swap_inactive(&self, from: &Self, color: Color) -> Color {
+        let connections = [
+            ConnectionDirection::Left,
+            ConnectionDirection::Right,
+            ConnectionDirection::Bottom,
+        ]
+        .map(|direction| {
+            (
+                from.palette.connection_color(direction),
+                self.palette.connection_color(direction),
+            )
+        });
+        Self::swap(
+            color,
+            [(from.theme.border, self.theme.border)]
+                .into_iter()
+                .chain(connections),
+        )
+    }
+
+    fn recolor(
+        &self,
+        from: &Self,
+        backgrounds: impl Iterator<Item = Mut<'_, BackgroundColor>>,
+        borders: impl Iterator<Item = Mut<'_, BorderColor>>,
+        texts: impl Iterator<Item = Mut<'_, Text>>,
+        text_input_styles: impl Iterator<Item = Mut<'_, TextInputTextStyle>>,
+        focus: impl Iterator<Item = (Mut<'_, Focus>, Mut<'_, FocusColor>)>,
+    ) {
+        for mut background in backgrounds {
+            background.0 = self.swap_background(from, background.0);
+        }
+        for mut border in borders {
+            border.0 = self.swap_border(from, border.0);
+        }
+        for mut text in texts {
+            for section in &mut text.sections {
+                section.style.color = self.swap_text(from, section.style.color);
+            }
+        }
+        for mut style in text_input_styles {
+            style.0.color = self.swap_text(from, style.0.color);
+        }
+        for (mut focus, mut focus_color) in focus {
+            let old_focus = *focus;
+            focus.active = self.swap_border(from, old_focus.active);
+            focus.hover = self.swap_border(from, old_focus.hover);
+            focus.inactive = self.swap_inactive(from, old_focus.inactive);
+            // INFO: The current color is matched with the state it shows because the color of a
+            // connection can be the same as the active color
+            focus_color.0 = if focus_color.0 == old_focus.inactive {
+                focus.inactive
+            } else if focus_color.0 == old_focus.active {
+                focus.active
+            } else if focus_color.0 == old_focus.hover {
+                focus.hover
+            } else {
+                focus_color.0
+            };
+        }
+    }
+}
+
+pub struct ThemePlugin;
+
+impl ThemePlugin {
+    fn toggle_theme(mut theme: ResMut<Theme>) {
+        *theme = if *theme == Theme::DARK {
+            Theme::LIGHT
+        } else {
+            Theme::DARK
+        };
+    }
+
+    fn cycle_palette(mut palette: ResMut<Palette>) {
+        *palette = palette.next();
+    }
+
+    fn load_font(
+        config: Res<FontConfig>,
+        asset_server: Res<AssetServer>,
+        mut font: ResMut<UiFont>,
+    ) {
+        if let Some(path) = &config.path {
+            font.0 = asset_server.load(path.clone());
+        }
+    }
+
+    /// Puts the loaded font in the place of the default font and lays the text out again with it
+    fn use_loaded_font(
+        mut events: EventReader<AssetEvent<Font>>,
+        font: Res<UiFont>,
+        mut fonts: ResMut<Assets<Font>>,
+        mut texts: Query<&mut Text>,
+    ) {
+        let loaded = events.read().any(|event| {
+            matches!(event, AssetEvent::LoadedWithDependencies { id } if *id == font.0.id())
+        });
+        let Some(loaded_font) = loaded.then(|| fonts.get(&font.0).cloned()).flatten() else {
+            return;
+        };
+        fonts.insert(AssetId::default(), loaded_font);
+        for mut text in &mut texts {
+            text.set_changed();
+        }
+    }
+
+    fn recolor_spawned_entities(
+        theme: Res<Theme>,
+        palette: Res<Palette>,
+        mut backgrounds: Query<
+            &mut BackgroundColor,
+            (
+                Added<BackgroundColor>,
+                Without<FixedBackground>,
+                Without<ColorOverride>,
+            ),
+        >,
+        mut borders: Query<&mut BorderColor, Added<BorderColor>>,
+        mut texts: Query<&mut Text, Added<Text>>,
+        mut text_input_styles: Query<&mut TextInputTextStyle, Added<TextInputTextStyle>>,
+        mut focus: Query<(&mut Focus, &mut FocusColor), Added<Focus>>,
+    ) {
+        let scheme = ColorScheme {
+            theme: *theme,
+            palette: *palette,
+        };
+        if scheme == ColorScheme::DEFAULT {
+            return;
+        }
+        scheme.recolor(
+            &ColorScheme::DEFAULT,
+            backgrounds.iter_mut(),
+            borders.iter_mut(),
+            texts.iter_mut(),
+            text_input_styles.iter_mut(),
+            focus.iter_mut(),
+        );
+    }
+
+    fn recolor_entities(
+        theme: Res<Theme>,
+        palette: Res<Palette>,
+        mut previous: Local<ColorScheme>,
+        mut clear_color: ResMut<ClearColor>,
+        // INFO: A block with a color of its own keeps it, its color may be the same as a color of
+        // the theme
+        mut backgrounds: Query<
+            &mut BackgroundColor,
+            (Without<FixedBackground>, Without<ColorOverride>),
+        >,
+        mut borders: Query<&mut BorderColor>,
+        mut texts: Query<&mut Text>,
+        mut text_input_styles: Query<&mut TextInputTextStyle>,
+        mut focus: Query<(&mut Focus, &mut FocusColor)>,
+    ) {
+        let scheme = ColorScheme {
+            theme: *theme,
+            palette: *palette,
+        };
+        if *previous == scheme {
+            return;
+        }
+        clear_color.0 = theme.background;
+        scheme.recolor(
+            &previous,
+            backgrounds.iter_mut(),
+            borders.iter_mut(),
+            texts.iter_mut(),
+            text_input_styles.iter_mut(),
+            focus.iter_mut(),
+        );
+        *previous = scheme;
+    }
+}
+
+impl Plugin for ThemePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Theme>()
+            .init_resource::<Palette>()
+            .init_resource::<FontConfig>()
+            .init_resource::<UiFont>()
+            .add_systems(PreStartup, Self::load_font)
+            .add_systems(
+                Update,
+                (
+                    Self::toggle_theme.run_if(control_just_pressed(KeyCode::KeyT)),
+                    Self::cycle_palette.run_if(control_just_pressed(KeyCode::KeyP)),
+                )
+                    .in_set(GameSets::Running),
+            )
+            .add_systems(
+                PostUpdate,
+                (
+                    Self::use_loaded_font,
+                    Self::recolor_spawned_entities,
+                    Self::recolor_entities
+                        .run_if(resource_changed::<Theme>.or_else(resource_changed::<Palette>)),
+                )
+                    .chain(),
+            );
+    }
+}
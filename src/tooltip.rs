@@ -0,0 +1,162 @@
+use std::time::Duration;
+
+use bevy::{prelude::*, ui::FocusPolicy, window::PrimaryWindow};
+
+use crate::{
+    focus::HoverEntity,
+    theme::FixedBackground,
+    ui_box::{Block, ValueCheck},
+    utils::BlockType,
+    GameSets,
+};
+
+/// How long a block has to be hovered before its tooltip is shown
+const TOOLTIP_DELAY: Duration = Duration::from_millis(500);
+/// How far the tooltip is from the cursor
+const TOOLTIP_OFFSET: Vec2 = Vec2::new(12., 12.);
+
+/// The block that is hovered and the tooltip that is shown for it
+#[derive(Debug, Resource)]
+pub struct Tooltip {
+    hovered: Option<Entity>,
+    timer: Timer,
+    tooltip: Option<Entity>,
+}
+
+impl Default for Tooltip {
+    fn default() -> Self {
+        Self {
+            hovered: None,
+            timer: Timer::new(TOOLTIP_DELAY, TimerMode::Once),
+            tooltip: None,
+        }
+    }
+}
+
+/// The node that shows the text of the tooltip
+#[derive(Debug, Component)]
+pub struct TooltipNode;
+
+#[derive(Bundle)]
+pub struct TooltipBundle {
+    node: NodeBundle,
+    marker: TooltipNode,
+    fixed_background: FixedBackground,
+}
+
+impl TooltipBundle {
+    fn new(position: Vec2) -> Self {
+        Self {
+            node: NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(position.x),
+                    top: Val::Px(position.y),
+                    padding: UiRect::all(Val::Px(4.)),
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::rgba(0., 0., 0., 0.8)),
+                // INFO: The tooltip is under the cursor so it must not take the clicks
+                focus_policy: FocusPolicy::Pass,
+                z_index: ZIndex::Global(20),
+                ..default()
+            },
+            marker: TooltipNode,
+            fixed_background: FixedBackground,
+        }
+    }
+}
+
+/// Shows the code that a block generates when it is hovered for a while
+pub struct TooltipPlugin;
+
+impl TooltipPlugin {
+    fn get_tooltip_text(block_type: &BlockType) -> String {
+        if block_type.holes.is_empty() {
+            return block_type.get_template();
+        }
+        let holes = block_type
+            .holes
+            .iter()
+            .map(|hole_type| format!("{hole_type:?}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("{}\nHoles: {holes}", block_type.get_template())
+    }
+
+    fn track_hover(
+        hover: Res<HoverEntity>,
+        blocks: Query<(), With<Block>>,
+        mut tooltip: ResMut<Tooltip>,
+        mut commands: Commands,
+    ) {
+        let hovered = hover.entity.filter(|&entity| blocks.contains(entity));
+        if hovered == tooltip.hovered {
+            return;
+        }
+        if let Some(entity) = tooltip.tooltip.take() {
+            if let Some(entity_commands) = commands.get_entity(entity) {
+                entity_commands.despawn_recursive();
+            }
+        }
+        tooltip.hovered = hovered;
+        tooltip.timer.reset();
+    }
+
+    fn show_tooltip(
+        time: Res<Time>,
+        mut tooltip: ResMut<Tooltip>,
+        blocks: Query<(&BlockType, Option<&ValueCheck>), With<Block>>,
+        windows: Query<&Window, With<PrimaryWindow>>,
+        ui_scale: Res<UiScale>,
+        mut commands: Commands,
+    ) {
+        if tooltip.tooltip.is_some() {
+            return;
+        }
+        let Some((block_type, check)) = tooltip.hovered.and_then(|entity| blocks.get(entity).ok())
+        else {
+            return;
+        };
+        if !tooltip.timer.tick(time.delta()).finished() {
+            return;
+        }
+        let Some(cursor) = windows
+            .get_single()
+            .ok()
+            .and_then(|window| window.cursor_position())
+        else {
+            return;
+        };
+
+        let mut text = Self::get_tooltip_text(block_type);
+        if let Some(error) = check.and_then(|check| check.error.as_deref()) {
+            text = format!("{text}\n{error}");
+        }
+        let entity = commands
+            .spawn(TooltipBundle::new(cursor / ui_scale.0 + TOOLTIP_OFFSET))
+            .with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    text,
+                    TextStyle {
+                        color: Color::WHITE,
+                        font_size: 14.,
+                        ..default()
+                    },
+                ));
+            })
+            .id();
+        tooltip.tooltip = Some(entity);
+    }
+}
+
+impl Plugin for TooltipPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Tooltip>().add_systems(
+            Update,
+            (Self::track_hover, Self::show_tooltip)
+                .chain()
+                .in_set(GameSets::Running),
+        );
+    }
+}
@@ -1,19 +1,28 @@
 use bevy::{
+    ecs::system::EntityCommands,
     input::{common_conditions::input_just_pressed, keyboard::KeyboardInput, ButtonState},
     prelude::*,
     ui::FocusPolicy,
+    window::PrimaryWindow,
 };
-use bevy_simple_text_input::{TextInputBundle, TextInputPlugin};
+use bevy_simple_text_input::{TextInputBundle, TextInputPlugin, TextInputValue};
 
 use crate::{
-    ast::{AddToAst, RemoveFromAst, UpdateAst},
-    connectors::{Connector, SpawnConnector},
+    ast::{AddToAst, Ast, BlockData, BlockDataMap, RemoveFromAst, UpdateAst},
+    camera::CanvasView,
+    collision::Collision,
+    connectors::{ConnectionDirection, Connector, SpawnConnector},
+    control_just_pressed,
     focus::{
-        ActiveEntity, DragEntity, DragState, Draggable, FocusColor, HoverEntity,
-        InteractionFocusBundle,
+        ActiveEntity, DragEntity, DragState, Draggable, Focus, FocusColor, HoverEntity,
+        InteractionFocusBundle, Selection,
+    },
+    text_input::{CustomTextInputBundle, TextInput},
+    theme::{FixedBackground, Palette, Theme},
+    utils::{
+        align_positions, find_free_position, get_aabb2d, is_number_literal, Alignment, BlockType,
+        ConceptType, HoleType, Language, LanguageConfig, Languages, NumberRange, Position, Size,
     },
-    text_input::CustomTextInputBundle,
-    utils::{BlockType, HoleType, Language, Position, Size},
     wasm::{Message, WASMRequest},
     DeleteEvent, EntityLabel, ErrorEvent, GameSets,
 };
@@ -21,6 +30,70 @@ use crate::{
 #[derive(Component, Debug, Clone, Copy, Default)]
 pub struct UIBox;
 
+/// How far a duplicated block is placed from the original
+const DUPLICATE_OFFSET: Vec2 = Vec2::new(20., 20.);
+/// The smallest size a block can be resized to
+const MIN_BLOCK_SIZE: Vec2 = Vec2::new(40., 30.);
+/// The colors that Ctrl+K cycles a block through before it goes back to its concept color
+const COLOR_OVERRIDES: [Color; 5] = [
+    Color::rgb(0.98, 0.71, 0.71),
+    Color::rgb(0.72, 0.91, 0.69),
+    Color::rgb(0.99, 0.82, 0.58),
+    Color::rgb(0.82, 0.74, 0.96),
+    Color::rgb(0.85, 0.85, 0.85),
+];
+
+/// How far the arrows move the active block when the grid is off, with Shift and with Ctrl
+const NUDGE_STEP: f32 = 10.;
+const FINE_NUDGE_STEP: f32 = 1.;
+const LARGE_NUDGE_STEP: f32 = 50.;
+
+/// When enabled, dropped blocks are snapped to the nearest multiple of `size`
+#[derive(Debug, Resource, Clone, Copy)]
+pub struct GridSnap {
+    pub size: f32,
+    pub enabled: bool,
+}
+
+impl Default for GridSnap {
+    fn default() -> Self {
+        Self {
+            size: 20.,
+            enabled: false,
+        }
+    }
+}
+
+/// When enabled, dropped blocks are kept on the background at least `margin` away from its edges
+#[derive(Debug, Resource, Clone, Copy)]
+pub struct CanvasClamp {
+    pub margin: f32,
+    pub enabled: bool,
+}
+
+impl Default for CanvasClamp {
+    fn default() -> Self {
+        Self {
+            margin: 10.,
+            enabled: true,
+        }
+    }
+}
+
+/// Only the blocks of this concept are shown fully, the rest are faded. Cycled with Ctrl+F
+#[derive(Debug, Resource, Default)]
+pub struct ConceptFilter {
+    pub concept: Option<ConceptType>,
+}
+
+impl ConceptFilter {
+    /// Whether the block is faded out by the filter
+    pub fn hides(&self, block_type: &BlockType) -> bool {
+        self.concept
+            .is_some_and(|concept| concept != block_type.concept_type)
+    }
+}
+
 #[derive(Component, Clone, Copy)]
 pub struct BackgroundBox;
 
@@ -55,6 +128,10 @@ impl BackgroundBoxBundle {
 #[derive(Component, Debug, Clone, Copy, Default)]
 pub struct Block;
 
+/// Used as the color of the block instead of the color of its concept type
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct ColorOverride(pub Color);
+
 #[derive(Bundle, Debug, Clone, Default)]
 pub struct BlockBundle {
     marker: (Block, UIBox),
@@ -65,6 +142,7 @@ pub struct BlockBundle {
     label: EntityLabel,
     focus_bundle: InteractionFocusBundle,
     draggable: Draggable,
+    collision: Collision,
 }
 
 impl BlockBundle {
@@ -76,9 +154,10 @@ impl BlockBundle {
         focus_bundle: InteractionFocusBundle,
         block_type: BlockType,
     ) -> Self {
-        let color = block_type.concept_type.get_color();
+        let color = Theme::LIGHT.concept_color(&block_type.concept_type);
         Self {
             draggable: Draggable,
+            collision: Collision,
             marker: (Block, UIBox),
             block_type,
             position: Position(Vec2::new(x, y)),
@@ -104,36 +183,77 @@ impl BlockBundle {
             focus_bundle,
         }
     }
+
+    /// Moves the top left corner of the block to `position`
+    pub fn with_position(mut self, position: Vec2) -> Self {
+        self.position = Position(position);
+        self.node.style.left = Val::Px(position.x);
+        self.node.style.top = Val::Px(position.y);
+        self
+    }
+
+    /// Lays the block out in the flow of the hole that it is spawned in
+    pub fn into_arg(mut self) -> Self {
+        self.node.style.position_type = PositionType::Relative;
+        self.node.style.top = Val::Px(0.);
+        self.node.style.left = Val::Px(0.);
+        self
+    }
 }
 
+/// A single error message inside the `ErrorList`
 #[derive(Debug, Component)]
 pub struct ErrorBox;
 
+/// The container that the error messages are stacked in
+#[derive(Debug, Component)]
+pub struct ErrorList;
+
 #[derive(Bundle)]
-pub struct ErrorBoxBundle {
-    node: TextBundle,
-    marker: (UIBox, ErrorBox),
+pub struct ErrorListBundle {
+    node: NodeBundle,
+    marker: ErrorList,
 }
 
-impl ErrorBoxBundle {
-    pub fn new(error: String) -> Self {
+impl ErrorListBundle {
+    fn new() -> Self {
         Self {
-            node: TextBundle {
-                text: Text::from_section(
-                    error,
-                    TextStyle {
-                        color: Color::WHITE,
-                        ..default()
-                    },
-                ),
+            node: NodeBundle {
                 style: Style {
                     position_type: PositionType::Absolute,
                     right: Val::Px(0.),
                     top: Val::Px(0.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::FlexEnd,
+                    row_gap: Val::Px(4.),
+                    ..default()
+                },
+                focus_policy: FocusPolicy::Pass,
+                ..default()
+            },
+            marker: ErrorList,
+        }
+    }
+}
+
+#[derive(Bundle)]
+pub struct ErrorBoxBundle {
+    node: NodeBundle,
+    marker: (UIBox, ErrorBox),
+}
+
+impl ErrorBoxBundle {
+    pub fn new() -> Self {
+        Self {
+            node: NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Row,
+                    align_items: AlignItems::Center,
+                    column_gap: Val::Px(8.),
                     padding: UiRect::all(Val::Px(8.)),
                     ..default()
                 },
-                background_color: Color::RED.into(),
+                background_color: Theme::LIGHT.error.into(),
                 ..default()
             },
             marker: (UIBox, ErrorBox),
@@ -141,6 +261,205 @@ impl ErrorBoxBundle {
     }
 }
 
+/// Blocks that are dropped on the trash are deleted
+#[derive(Debug, Component, Clone, Copy)]
+pub struct Trash;
+
+/// A disabled block is dimmed and left out of the generated code together with its branches
+#[derive(Debug, Component, Clone, Copy)]
+pub struct Disabled;
+
+/// How see-through a disabled block is
+const DISABLED_ALPHA: f32 = 0.4;
+/// How see-through a block that isn't of the concept of the filter is
+const FILTERED_ALPHA: f32 = 0.15;
+
+fn block_alpha(disabled: bool, filtered: bool) -> f32 {
+    if filtered {
+        FILTERED_ALPHA
+    } else if disabled {
+        DISABLED_ALPHA
+    } else {
+        1.
+    }
+}
+
+/// Shows how many of the connectors of a block already have a line
+#[derive(Debug, Component, Clone, Copy)]
+pub struct ConnectionBadge;
+
+/// The order of a hole that stays in it when a block is put in the hole
+#[derive(Debug, Component, Clone, Copy)]
+pub struct HoleOrderLabel;
+
+/// A hole that the dragged block can be dropped in, it has the inactive color it had before
+#[derive(Debug, Component, Clone, Copy)]
+struct HighlightedHole(Color);
+
+/// Whether the text of a Text block fits the hole that it is in, with the inactive color the
+/// block had before it was checked
+#[derive(Debug, Component, Clone)]
+pub struct ValueCheck {
+    inactive: Color,
+    pub error: Option<String>,
+}
+
+#[derive(Bundle)]
+struct TrashBundle {
+    node: NodeBundle,
+    trash: Trash,
+    label: EntityLabel,
+    focus_bundle: InteractionFocusBundle,
+}
+
+impl TrashBundle {
+    fn new() -> Self {
+        Self {
+            node: NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    right: Val::Px(16.),
+                    bottom: Val::Px(48.),
+                    width: Val::Px(56.),
+                    height: Val::Px(56.),
+                    border: UiRect::all(Val::Px(2.)),
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                border_color: BorderColor(Theme::LIGHT.border),
+                focus_policy: FocusPolicy::Block,
+                ..default()
+            },
+            trash: Trash,
+            label: EntityLabel::new("Trash"),
+            // INFO: The trash is highlighted when a dragged block is over it
+            focus_bundle: InteractionFocusBundle::new(
+                Theme::LIGHT.border,
+                Theme::LIGHT.error,
+                Theme::LIGHT.border,
+            ),
+        }
+    }
+}
+
+/// When enabled, deleting a block that has connections or args asks for a confirmation first
+#[derive(Debug, Resource, Clone, Copy)]
+pub struct DeleteConfirmConfig {
+    pub enabled: bool,
+}
+
+impl Default for DeleteConfirmConfig {
+    fn default() -> Self {
+        Self { enabled: true }
+    }
+}
+
+/// The dialog that asks if `block` should be deleted
+#[derive(Debug, Component, Clone, Copy)]
+pub struct DeleteConfirmDialog {
+    pub block: Entity,
+}
+
+/// Deletes the block of the dialog when `confirm` is true and closes the dialog when pressed
+#[derive(Debug, Component, Clone, Copy)]
+pub struct DeleteConfirmButton {
+    pub confirm: bool,
+}
+
+#[derive(Bundle)]
+struct DeleteConfirmDialogBundle {
+    node: NodeBundle,
+    dialog: DeleteConfirmDialog,
+    fixed_background: FixedBackground,
+}
+
+impl DeleteConfirmDialogBundle {
+    fn new(block: Entity) -> Self {
+        Self {
+            node: NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Percent(50.),
+                    top: Val::Percent(40.),
+                    flex_direction: FlexDirection::Column,
+                    align_items: AlignItems::Center,
+                    row_gap: Val::Px(8.),
+                    padding: UiRect::all(Val::Px(12.)),
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::rgba(0., 0., 0., 0.8)),
+                focus_policy: FocusPolicy::Block,
+                z_index: ZIndex::Global(30),
+                ..default()
+            },
+            dialog: DeleteConfirmDialog { block },
+            fixed_background: FixedBackground,
+        }
+    }
+}
+
+/// Despawns the `ErrorBox` it is a child of when pressed
+#[derive(Debug, Component)]
+pub struct CloseErrorButton;
+
+#[derive(Bundle)]
+pub struct CloseErrorBundle {
+    button: ButtonBundle,
+    marker: CloseErrorButton,
+}
+
+impl CloseErrorBundle {
+    pub fn new() -> Self {
+        Self {
+            button: ButtonBundle {
+                background_color: BackgroundColor(Color::NONE),
+                // INFO: The errors are on top of the background so the click shouldn't reach it
+                focus_policy: FocusPolicy::Block,
+                ..default()
+            },
+            marker: CloseErrorButton,
+        }
+    }
+}
+
+/// The handle in the bottom right corner of a block that resizes it when dragged
+#[derive(Debug, Component, Clone, Copy)]
+pub struct ResizeHandle {
+    pub owner: Entity,
+}
+
+#[derive(Bundle)]
+pub struct ResizeHandleBundle {
+    node: NodeBundle,
+    marker: ResizeHandle,
+    focus_bundle: InteractionFocusBundle,
+    draggable: Draggable,
+}
+
+impl ResizeHandleBundle {
+    fn new(owner: Entity) -> Self {
+        Self {
+            node: NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    right: Val::Px(0.),
+                    bottom: Val::Px(0.),
+                    width: Val::Px(8.),
+                    height: Val::Px(8.),
+                    ..default()
+                },
+                background_color: BackgroundColor(Color::rgba(0., 0., 0., 0.3)),
+                focus_policy: FocusPolicy::Block,
+                ..default()
+            },
+            marker: ResizeHandle { owner },
+            focus_bundle: InteractionFocusBundle::default(),
+            draggable: Draggable,
+        }
+    }
+}
+
 #[derive(Component)]
 struct HoleContainer;
 #[derive(Bundle)]
@@ -171,7 +490,7 @@ impl HoleContainerBundle {
     }
 }
 
-#[derive(Component)]
+#[derive(Component, Debug, Clone)]
 pub struct Hole {
     pub owner: Entity,
     pub order: usize,
@@ -200,15 +519,212 @@ impl HoleBundle {
                     padding: UiRect::all(Val::Px(4.)),
                     border: UiRect::all(Val::Px(1.)),
                     min_width: Val::Px(10.),
+                    align_items: AlignItems::Center,
+                    column_gap: Val::Px(4.),
                     ..default()
                 },
                 focus_policy: bevy::ui::FocusPolicy::Block,
-                border_color: BorderColor(Color::BLACK),
+                border_color: BorderColor(Theme::LIGHT.border),
+                ..default()
+            },
+
+            focus_bundle: InteractionFocusBundle::new(
+                Theme::LIGHT.border,
+                Color::GREEN,
+                Theme::LIGHT.border,
+            ),
+        }
+    }
+
+    /// The order of the hole, it is shown before whatever is in the hole
+    fn label(order: usize) -> (TextBundle, HoleOrderLabel) {
+        let text = TextBundle::from_section(
+            order.to_string(),
+            TextStyle {
+                color: Theme::LIGHT.text,
+                font_size: 12.,
+                ..Default::default()
+            },
+        )
+        .with_text_justify(JustifyText::Center);
+        (text, HoleOrderLabel)
+    }
+
+    /// Spawns what is shown while the hole is empty, a bool hole can be toggled and a number
+    /// hole can be typed in or stepped until a block is put in it
+    fn spawn_placeholder(parent: &mut ChildBuilder, hole: &Hole) {
+        Self::spawn_placeholder_with_value(parent, hole, None);
+    }
+
+    /// Spawns the placeholder of the hole, the toggle and the number input start with the value
+    fn spawn_placeholder_with_value(parent: &mut ChildBuilder, hole: &Hole, value: Option<&str>) {
+        match hole.hole_type {
+            HoleType::Bool => {
+                let bundle = HoleValueBundle::new(
+                    hole.owner,
+                    hole.order,
+                    vec!["true".to_owned(), "false".to_owned()],
+                )
+                .with_value(value);
+                let label = bundle.label();
+                parent.spawn(bundle).with_children(|parent| {
+                    parent.spawn(label);
+                });
+            }
+            HoleType::Number => Self::spawn_number_input(parent, hole, value),
+            _ => {}
+        }
+    }
+
+    fn spawn_number_input(parent: &mut ChildBuilder, hole: &Hole, value: Option<&str>) {
+        let value = value.unwrap_or_default().to_owned();
+        let text_style = TextStyle {
+            color: Theme::LIGHT.text,
+            font_size: 15.,
+            ..default()
+        };
+        parent
+            .spawn(NodeBundle {
+                style: Style {
+                    flex_direction: FlexDirection::Row,
+                    align_items: AlignItems::Center,
+                    column_gap: Val::Px(2.),
+                    ..default()
+                },
+                focus_policy: FocusPolicy::Pass,
+                ..default()
+            })
+            .with_children(|parent| {
+                let text_bundle = TextInputBundle::default()
+                    .with_text_style(text_style.clone())
+                    .with_value(value.clone());
+                let input = parent
+                    .spawn((
+                        CustomTextInputBundle::new(text_bundle, hole.owner),
+                        HoleValue {
+                            owner: hole.owner,
+                            order: hole.order,
+                            value,
+                        },
+                        NumberInput,
+                    ))
+                    .id();
+                for (label, step) in [("-", -1), ("+", 1)] {
+                    parent
+                        .spawn((
+                            ButtonBundle {
+                                style: Style {
+                                    padding: UiRect::horizontal(Val::Px(4.)),
+                                    border: UiRect::all(Val::Px(1.)),
+                                    ..default()
+                                },
+                                border_color: BorderColor(Theme::LIGHT.border),
+                                background_color: BackgroundColor(Color::NONE),
+                                focus_policy: FocusPolicy::Block,
+                                ..default()
+                            },
+                            NumberStepper { input, step },
+                        ))
+                        .with_children(|parent| {
+                            parent.spawn(TextBundle::from_section(label, text_style.clone()));
+                        });
+                }
+            });
+    }
+}
+
+/// A hole that is filled in by picking one of its options instead of dropping a block in it
+#[derive(Debug, Component, Clone)]
+pub struct HoleValue {
+    pub owner: Entity,
+    pub order: usize,
+    pub value: String,
+}
+
+/// The text box of a number hole, its value is only put in the `HoleValue` when it is a number
+#[derive(Debug, Component, Clone, Copy)]
+pub struct NumberInput;
+
+/// Adds `step` to the number in the `input` text box when it is pressed
+#[derive(Debug, Component, Clone, Copy)]
+pub struct NumberStepper {
+    pub input: Entity,
+    pub step: i64,
+}
+
+/// Adds the step to the value, integers stay integers and decimals keep their number of digits
+fn step_number(value: &str, step: i64, range: NumberRange) -> Option<String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Some(range.clamp(step as f64).to_string());
+    }
+    if let Ok(integer) = value.parse::<i64>() {
+        return Some(range.clamp(integer.saturating_add(step) as f64).to_string());
+    }
+    if !is_number_literal(value) {
+        return None;
+    }
+    let decimals = value
+        .split_once('.')
+        .map_or(0, |(_, decimals)| decimals.len());
+    let number = range.clamp(value.parse::<f64>().ok()? + step as f64);
+    Some(format!("{number:.decimals$}"))
+}
+
+/// The values that a `HoleValue` goes through when it is pressed
+#[derive(Debug, Component, Clone)]
+pub struct HoleOptions(pub Vec<String>);
+
+#[derive(Bundle)]
+struct HoleValueBundle {
+    button: ButtonBundle,
+    value: HoleValue,
+    options: HoleOptions,
+    label: EntityLabel,
+}
+
+impl HoleValueBundle {
+    fn new(owner: Entity, order: usize, options: Vec<String>) -> Self {
+        Self {
+            button: ButtonBundle {
+                style: Style {
+                    padding: UiRect::axes(Val::Px(6.), Val::Px(4.)),
+                    border: UiRect::all(Val::Px(1.)),
+                    min_width: Val::Px(10.),
+                    ..default()
+                },
+                border_color: BorderColor(Theme::LIGHT.border),
+                background_color: BackgroundColor(Color::NONE),
+                focus_policy: FocusPolicy::Block,
                 ..default()
             },
+            value: HoleValue {
+                owner,
+                order,
+                value: options.first().cloned().unwrap_or_default(),
+            },
+            options: HoleOptions(options),
+            label: EntityLabel::new("Hole Value"),
+        }
+    }
 
-            focus_bundle: InteractionFocusBundle::new(Color::BLACK, Color::GREEN, Color::BLACK),
+    fn with_value(mut self, value: Option<&str>) -> Self {
+        if let Some(value) = value {
+            value.clone_into(&mut self.value.value);
         }
+        self
+    }
+
+    fn label(&self) -> TextBundle {
+        TextBundle::from_section(
+            self.value.value.clone(),
+            TextStyle {
+                color: Theme::LIGHT.text,
+                font_size: 15.,
+                ..Default::default()
+            },
+        )
+        .with_text_justify(JustifyText::Center)
     }
 }
 
@@ -222,8 +738,14 @@ pub struct Arg {
 pub struct SpawnUIBox {
     pub bundle: BlockBundle,
     pub marker: Option<crate::Marker>, // connections: [Option<ConnectionType>; 3],
+    /// The starting value of the text input of a `Text` block
+    pub text: Option<String>,
 }
 
+/// Makes the language with this name the active one
+#[derive(Event, Debug, Clone)]
+pub struct SwitchLanguage(pub String);
+
 #[derive(Event, Debug, Clone, Copy)]
 pub struct SpawnArg {
     pub arg: Entity,
@@ -242,24 +764,53 @@ pub struct UIBoxPlugin;
 impl UIBoxPlugin {
     fn spawn_background_box(mut commands: Commands) {
         let bundle = BackgroundBoxBundle::new();
-        commands.spawn(bundle);
+        commands.spawn(bundle).with_children(|parent| {
+            parent.spawn(ErrorListBundle::new());
+            parent.spawn(TrashBundle::new()).with_children(|parent| {
+                parent.spawn(TextBundle::from_section(
+                    "Trash",
+                    TextStyle {
+                        color: Theme::LIGHT.text,
+                        font_size: 14.,
+                        ..default()
+                    },
+                ));
+            });
+        });
     }
 
+    /// Spawns a block under the cursor when its key is pressed while the background is active
+    #[allow(clippy::too_many_arguments)]
     fn spawn_box(
         mut keyboard_events: EventReader<KeyboardInput>,
         mut writer: EventWriter<SpawnUIBox>,
-        background: Query<(Entity, &Node), With<BackgroundBox>>,
+        background: Query<Entity, With<BackgroundBox>>,
         active: Res<ActiveEntity>,
-        language: Res<Language>,
+        languages: Res<Languages>,
+        keyboard: Res<ButtonInput<KeyCode>>,
+        windows: Query<&Window, With<PrimaryWindow>>,
+        ui_scale: Res<UiScale>,
         mut error_writer: EventWriter<ErrorEvent>,
     ) {
-        let (background_entity, background) = background.single();
+        let Ok(background_entity) = background.get_single() else {
+            return;
+        };
+        // INFO: The same keys are used with Ctrl and Alt for the shortcuts
+        let modified = keyboard.any_pressed([
+            KeyCode::ControlLeft,
+            KeyCode::ControlRight,
+            KeyCode::AltLeft,
+            KeyCode::AltRight,
+        ]);
+        let cursor = windows.get_single().ok().and_then(Window::cursor_position);
         // if the active entity is the background entity then we can spawn a box
-        if active
+        if let Some(cursor) = active
             .entity
-            .is_some_and(|entity| entity == background_entity)
+            .filter(|&entity| entity == background_entity && !modified)
+            .and(cursor)
         {
-            let background_size = (background.size() / 2.) - 50.;
+            let size = Vec2::new(50., 60.);
+            let position = cursor / ui_scale.0 - size / 2.;
             for keyboard_event in keyboard_events.read() {
                 if keyboard_event.state == ButtonState::Pressed {
                     let block_type = match keyboard_event.key_code {
@@ -271,7 +822,7 @@ impl UIBoxPlugin {
                         KeyCode::KeyB => "Print",
                         _ => continue,
                     };
-                    let Some(block_type) = language.get_block(block_type) else {
+                    let Some(block_type) = languages.active().get_block(block_type) else {
                         error_writer.send(ErrorEvent(format!(
                             "Couldn't spawn {block_type} for language"
                         )));
@@ -279,25 +830,52 @@ impl UIBoxPlugin {
                     };
                     writer.send(SpawnUIBox {
                         bundle: BlockBundle::new(
-                            background_size.x,
-                            background_size.y,
-                            50.,
-                            60.,
+                            position.x,
+                            position.y,
+                            size.x,
+                            size.y,
                             InteractionFocusBundle::default(),
                             block_type,
                         ),
                         marker: None,
+                        text: None,
                     });
                 }
             }
         }
     }
 
-    fn spawn_initial_box(mut writer: EventWriter<SpawnUIBox>, language: Res<Language>) {
-        let start_block = language.get_block("Start").unwrap();
+    fn load_language(
+        config: Res<LanguageConfig>,
+        mut languages: ResMut<Languages>,
+        mut error_writer: EventWriter<ErrorEvent>,
+    ) {
+        let Some(path) = config.path.as_deref() else {
+            return;
+        };
+        match Language::from_path(path) {
+            Ok(loaded) => {
+                let name = loaded.name.clone();
+                languages.insert(loaded);
+                languages.set_active(&name);
+            }
+            Err(error) => {
+                error_writer.send(ErrorEvent(format!(
+                    "{error}, falling back to the default language"
+                )));
+            }
+        }
+    }
+
+    pub(crate) fn spawn_initial_box(
+        mut writer: EventWriter<SpawnUIBox>,
+        languages: Res<Languages>,
+    ) {
+        let start_block = languages.active().get_block("Start").unwrap();
 
         writer.send(SpawnUIBox {
             marker: None,
+            text: None,
             bundle: BlockBundle::new(
                 0.,
                 0.,
@@ -309,160 +887,683 @@ impl UIBoxPlugin {
         });
     }
 
-    fn handle_spawn_ui_box(
+    /// Spawns the block with its text, holes and resize handle under the parent. `spawn_arg` is
+    /// given every hole and returns true if it spawned a block in it, otherwise the hole gets its
+    /// placeholder. The hole values are set from `values`
+    pub(crate) fn spawn_block<'a>(
+        parent: &'a mut ChildBuilder,
+        bundle: BlockBundle,
+        text_value: Option<String>,
+        values: &[BlockData],
+        language: &Language,
+        spawn_arg: &mut dyn FnMut(&mut ChildBuilder, &Hole) -> bool,
+    ) -> EntityCommands<'a> {
+        let text = bundle.block_type.to_string();
+        let holes = bundle.block_type.get_holes();
+        let block_type = bundle.block_type.clone();
+        let get_value = |order: usize| {
+            values
+                .iter()
+                .filter_map(BlockData::get_value)
+                .find(|&(position, _)| position == order)
+                .map(|(_, value)| value.as_str())
+        };
+
+        let mut ui_box = parent.spawn(bundle);
+        let ui_box_id = ui_box.id();
+        ui_box.with_children(|parent| {
+            // Spawn Text
+            parent.spawn((
+                TextBundle::from_section(
+                    text,
+                    TextStyle {
+                        color: Theme::LIGHT.text,
+                        font_size: 20.,
+                        ..default()
+                    },
+                )
+                .with_text_justify(JustifyText::Left),
+                Label,
+            ));
+
+            if holes > 0 {
+                // Spawn Hole Container
+                let mut hole_container = parent.spawn(HoleContainerBundle::new());
+                hole_container.with_children(|parent| {
+                    match block_type {
+                        block_type if block_type.has_text() => {
+                            let text_bundle = TextInputBundle::default()
+                                .with_text_style(TextStyle {
+                                    color: Theme::LIGHT.text,
+                                    font_size: 15.,
+                                    ..default()
+                                })
+                                .with_value(text_value.unwrap_or_default());
+                            parent.spawn(CustomTextInputBundle::new(text_bundle, ui_box_id));
+                        }
+                        _ => {
+                            for (order, hole_type) in block_type.holes.into_iter().enumerate() {
+                                if hole_type == HoleType::Comparitor {
+                                    let bundle = HoleValueBundle::new(
+                                        ui_box_id,
+                                        order,
+                                        language.get_comparitors(),
+                                    )
+                                    .with_value(get_value(order));
+                                    let label = bundle.label();
+                                    parent.spawn(bundle).with_children(|parent| {
+                                        parent.spawn(label);
+                                    });
+                                    continue;
+                                }
+                                let bundle = HoleBundle::new(ui_box_id, order, hole_type);
+                                let hole = bundle.hole.clone();
+                                parent.spawn(bundle).with_children(|parent| {
+                                    parent.spawn(HoleBundle::label(order));
+                                    if !spawn_arg(parent, &hole) {
+                                        HoleBundle::spawn_placeholder_with_value(
+                                            parent,
+                                            &hole,
+                                            get_value(order),
+                                        );
+                                    }
+                                });
+                            }
+                        }
+                    };
+                });
+            }
+
+            parent.spawn(ResizeHandleBundle::new(ui_box_id));
+        });
+        ui_box
+    }
+
+    pub(crate) fn handle_spawn_ui_box(
         mut reader: EventReader<SpawnUIBox>,
         mut connector_writer: EventWriter<SpawnConnector>,
         mut add_ast_writer: EventWriter<AddToAst>,
         mut commands: Commands,
         background: Query<Entity, With<BackgroundBox>>,
+        blocks: Query<(&Position, &Size), (With<Block>, Without<Arg>)>,
+        languages: Res<Languages>,
     ) {
+        let Ok(background) = background.get_single() else {
+            return;
+        };
+        // INFO: The blocks spawned by this system are added so that they don't pile up either
+        let mut occupied = blocks
+            .iter()
+            .map(|(position, size)| get_aabb2d(position, size))
+            .collect::<Vec<_>>();
         for SpawnUIBox {
             bundle,
             marker,
+            text: text_value,
             // connections,
         } in reader.read().map(ToOwned::to_owned)
         {
-            let mut container = commands
-                .get_entity(background.single())
-                .expect("Should never fail");
+            // INFO: Pasted blocks keep their layout, their args overlap their owners on purpose
+            let bundle = if marker.is_some() {
+                bundle
+            } else {
+                let position = find_free_position(bundle.position.0, bundle.size.0, &occupied);
+                bundle.with_position(position)
+            };
+            occupied.push(get_aabb2d(&bundle.position, &bundle.size));
+            let mut container = commands.entity(background);
 
             container.with_children(|parent_commands| {
-                let text = bundle.block_type.to_string();
-                let holes = bundle.block_type.get_holes();
                 let block_type = bundle.block_type.clone();
-                let connections = block_type.connectors.clone();
+                let language = languages
+                    .get(&block_type.language)
+                    .unwrap_or_else(|| languages.active());
 
-                let mut ui_box = parent_commands.spawn(bundle);
+                let mut ui_box = Self::spawn_block(
+                    parent_commands,
+                    bundle,
+                    text_value,
+                    &[],
+                    language,
+                    &mut |_, _| false,
+                );
                 if let Some(marker) = marker {
                     ui_box.insert(marker);
                 }
-
                 let ui_box_id = ui_box.id();
 
                 add_ast_writer.send(AddToAst {
                     parent: None,
                     child: (ui_box_id, block_type.clone()),
                 });
+                Self::send_connectors(&mut connector_writer, ui_box_id, &block_type.connectors);
+            });
+        }
+    }
+
+    /// Moves the pressed hole value on to its next option
+    fn cycle_hole_value(
+        mut hole_values: Query<
+            (&Interaction, &mut HoleValue, &HoleOptions, &Children),
+            Changed<Interaction>,
+        >,
+        mut texts: Query<&mut Text>,
+        mut update_writer: EventWriter<UpdateAst>,
+    ) {
+        for (_, mut hole_value, HoleOptions(options), children) in hole_values
+            .iter_mut()
+            .filter(|(interaction, ..)| matches!(interaction, Interaction::Pressed))
+        {
+            let next = options
+                .iter()
+                .position(|option| *option == hole_value.value)
+                .map_or(0, |index| (index + 1) % options.len());
+            let Some(next) = options.get(next) else {
+                continue;
+            };
+            Self::set_hole_value(&mut hole_value, children, &mut texts, next);
+            update_writer.send_default();
+        }
+    }
+
+    fn step_number_input(
+        steppers: Query<(&Interaction, &NumberStepper), Changed<Interaction>>,
+        mut inputs: Query<(&HoleValue, &mut TextInputValue), With<NumberInput>>,
+        block_types: Query<&BlockType>,
+    ) {
+        for (_, stepper) in steppers
+            .iter()
+            .filter(|(interaction, _)| matches!(interaction, Interaction::Pressed))
+        {
+            let Ok((hole_value, mut value)) = inputs.get_mut(stepper.input) else {
+                continue;
+            };
+            let range = block_types
+                .get(hole_value.owner)
+                .map(|block_type| block_type.get_number_range(hole_value.order))
+                .unwrap_or_default();
+            if let Some(stepped) = step_number(&value.0, stepper.step, range) {
+                value.0 = stepped;
+            }
+        }
+    }
 
-                ui_box.with_children(|parent| {
-                    // Spawn Text
-                    parent.spawn((
-                        TextBundle::from_section(
-                            text,
-                            TextStyle {
-                                color: Color::BLACK,
-                                font_size: 20.,
+    /// Puts the typed number in the hole, anything that isn't a number is left out of the code
+    fn sync_number_input(
+        mut inputs: Query<
+            (&TextInputValue, &mut HoleValue),
+            (With<NumberInput>, Changed<TextInputValue>),
+        >,
+        mut update_writer: EventWriter<UpdateAst>,
+    ) {
+        for (value, mut hole_value) in &mut inputs {
+            let number = value.0.trim();
+            let number = if HoleType::Number.valid_input(number) {
+                number
+            } else {
+                ""
+            };
+            if hole_value.value != number {
+                number.clone_into(&mut hole_value.value);
+                update_writer.send_default();
+            }
+        }
+    }
+
+    /// Changes the value of the hole and the text that shows it
+    pub(crate) fn set_hole_value(
+        hole_value: &mut HoleValue,
+        children: &Children,
+        texts: &mut Query<&mut Text>,
+        value: &str,
+    ) {
+        value.clone_into(&mut hole_value.value);
+        let mut texts = texts.iter_many_mut(children);
+        while let Some(mut text) = texts.fetch_next() {
+            value.clone_into(&mut text.sections[0].value);
+        }
+    }
+
+    pub(crate) fn send_connectors(
+        connector_writer: &mut EventWriter<SpawnConnector>,
+        fixture: Entity,
+        directions: &[ConnectionDirection],
+    ) {
+        for &direction in directions {
+            connector_writer.send(SpawnConnector {
+                connector: Connector {
+                    fixture,
+                    direction,
+                    // connection_type: ConnectionType::Flow,
+                    connected: false,
+                },
+                radius: 7.,
+            });
+        }
+    }
+
+    /// Makes sure that every block on the canvas has all of the connectors of its block type,
+    /// this runs in `Last` since the connectors are spawned in `PostUpdate`
+    fn check_connector_count(
+        blocks: Query<(Entity, &BlockType, Option<&Children>), (With<Block>, Without<Arg>)>,
+        connectors: Query<(), With<Connector>>,
+    ) {
+        for (entity, block_type, children) in &blocks {
+            let count = children
+                .into_iter()
+                .flatten()
+                .filter(|&&child| connectors.contains(child))
+                .count();
+            if count != block_type.connectors.len() {
+                error!(
+                    "Block {entity:?} has {count} connectors but {} expects {}",
+                    block_type.name,
+                    block_type.connectors.len()
+                );
+            }
+        }
+    }
+
+    /// Keeps the badge of every block up to date with its used and available connections, a
+    /// block without connectors e.g. an arg has no badge
+    fn update_connection_badges(
+        ast: Res<Ast>,
+        blocks: Query<(Entity, Option<&Children>), With<Block>>,
+        connectors: Query<(), With<Connector>>,
+        added_connectors: Query<(), Added<Connector>>,
+        mut removed_connectors: RemovedComponents<Connector>,
+        mut badges: Query<&mut Text, With<ConnectionBadge>>,
+        mut commands: Commands,
+    ) {
+        let removed = removed_connectors.read().last().is_some();
+        if !ast.is_changed() && !removed && added_connectors.is_empty() {
+            return;
+        }
+        for (entity, children) in &blocks {
+            let children = children.into_iter().flatten().copied();
+            let available = children
+                .clone()
+                .filter(|&child| connectors.contains(child))
+                .count();
+            let badge = children.clone().find(|&child| badges.contains(child));
+            if available == 0 {
+                if let Some(badge) = badge {
+                    commands.entity(badge).despawn_recursive();
+                }
+                continue;
+            }
+            let used = ast
+                .map
+                .get(&entity)
+                .map_or(0, |connections| connections.iter().flatten().count());
+            let value = format!("{used}/{available}");
+            match badge.and_then(|badge| badges.get_mut(badge).ok()) {
+                Some(mut text) => {
+                    if text.sections[0].value != value {
+                        text.sections[0].value = value;
+                    }
+                }
+                None => {
+                    commands.entity(entity).with_children(|parent| {
+                        parent.spawn((
+                            TextBundle::from_section(
+                                value,
+                                TextStyle {
+                                    color: Theme::LIGHT.text,
+                                    font_size: 12.,
+                                    ..default()
+                                },
+                            )
+                            .with_style(Style {
+                                position_type: PositionType::Absolute,
+                                top: Val::Px(-16.),
+                                right: Val::Px(0.),
                                 ..default()
-                            },
-                        )
-                        .with_text_justify(JustifyText::Left),
-                        Label,
-                    ));
+                            }),
+                            ConnectionBadge,
+                        ));
+                    });
+                }
+            }
+        }
+    }
+
+    fn resize_block(
+        drag: Res<DragEntity>,
+        handles: Query<&ResizeHandle>,
+        ui_scale: Res<UiScale>,
+        mut cursor_motion: EventReader<CursorMoved>,
+        mut blocks: Query<(&mut Style, &mut Size), With<Block>>,
+    ) {
+        let delta: Vec2 = cursor_motion
+            .read()
+            .map(|motion| motion.delta.unwrap_or_default())
+            .sum();
+        let Some((mut style, mut size)) = drag
+            .entity
+            .and_then(|entity| handles.get(entity).ok())
+            .and_then(|handle| blocks.get_mut(handle.owner).ok())
+        else {
+            return;
+        };
+        if delta == Vec2::ZERO {
+            return;
+        }
+        let new_size = (size.0 + delta / ui_scale.0).max(MIN_BLOCK_SIZE);
+        style.width = Val::Px(new_size.x);
+        style.min_width = Val::Px(new_size.x);
+        style.min_height = Val::Px(new_size.y);
+        size.0 = new_size;
+    }
+
+    fn update_size(mut query: Query<(&mut Size, &Node), With<Block>>) {
+        for (mut box_size, box_node) in &mut query {
+            box_size.0 = box_node.size();
+        }
+    }
+
+    fn translate_position(
+        mut query: Query<(&Position, &mut Style), (With<UIBox>, Changed<Position>)>,
+    ) {
+        for (box_pos, mut box_style) in &mut query {
+            if box_style.position_type == PositionType::Absolute {
+                box_style.top = Val::Px(box_pos.0.y);
+                box_style.left = Val::Px(box_pos.0.x);
+            }
+        }
+    }
+
+    fn translate_position_args(
+        mut query: Query<(&mut Position, &GlobalTransform), (With<Arg>, Changed<GlobalTransform>)>,
+    ) {
+        for (mut position, transform) in &mut query {
+            position.0 = transform.translation().xy();
+        }
+    }
+
+    fn handle_color_change(
+        query: Query<
+            (Entity, &FocusColor),
+            (
+                Changed<FocusColor>,
+                Or<(With<UIBox>, With<Hole>, With<Trash>)>,
+            ),
+        >,
+        mut style_query: Query<&mut BorderColor, Or<(With<UIBox>, With<Hole>, With<Trash>)>>,
+    ) {
+        for (entity, focus_color) in &query {
+            let Ok(mut border_color) = style_query.get_mut(entity) else {
+                error!("Couldn't find the color");
+                continue;
+            };
+            border_color.0 = focus_color.0;
+        }
+    }
+
+    fn cycle_color_override(
+        active: Res<ActiveEntity>,
+        blocks: Query<Option<&ColorOverride>, With<Block>>,
+        mut commands: Commands,
+    ) {
+        let Some((active, color_override)) = active
+            .entity
+            .and_then(|entity| Some((entity, blocks.get(entity).ok()?)))
+        else {
+            return;
+        };
+        let next = color_override
+            .and_then(|ColorOverride(color)| COLOR_OVERRIDES.iter().position(|c| c == color))
+            .map_or(0, |index| index + 1);
+        match COLOR_OVERRIDES.get(next) {
+            Some(&color) => commands.entity(active).insert(ColorOverride(color)),
+            None => commands.entity(active).remove::<ColorOverride>(),
+        };
+    }
+
+    fn handle_color_override(
+        mut removed: RemovedComponents<ColorOverride>,
+        changed: Query<Entity, Changed<ColorOverride>>,
+        mut blocks: Query<(&mut BackgroundColor, &BlockType, Option<&ColorOverride>), With<Block>>,
+        theme: Res<Theme>,
+        palette: Res<Palette>,
+    ) {
+        let mut blocks = blocks.iter_many_mut(changed.iter().chain(removed.read()));
+        while let Some((mut background, block_type, color_override)) = blocks.fetch_next() {
+            // INFO: The alpha is kept because it is lowered while the block is being dragged
+            let alpha = background.0.a();
+            background.0 = color_override
+                .map_or_else(
+                    || palette.concept_color(&block_type.concept_type, &theme),
+                    |color| color.0,
+                )
+                .with_a(alpha);
+        }
+    }
 
-                    if holes > 0 {
-                        // Spawn Hole Container
-                        let mut hole_container = parent.spawn(HoleContainerBundle::new());
-                        hole_container.with_children(|parent| {
-                            match block_type {
-                                block_type if block_type.has_text() => {
-                                    let text_bundle =
-                                        TextInputBundle::default().with_text_style(TextStyle {
-                                            color: Color::BLACK,
-                                            font_size: 15.,
-                                            ..default()
-                                        });
-                                    parent
-                                        .spawn(CustomTextInputBundle::new(text_bundle, ui_box_id));
-                                }
-                                _ => {
-                                    for (order, hole_type) in
-                                        block_type.holes.into_iter().enumerate()
-                                    {
-                                        parent
-                                            .spawn(HoleBundle::new(ui_box_id, order, hole_type))
-                                            .with_children(|parent| {
-                                                parent.spawn(
-                                                    TextBundle::from_section(
-                                                        order.to_string(),
-                                                        TextStyle {
-                                                            color: Color::BLACK,
-                                                            font_size: 15.,
-                                                            ..Default::default()
-                                                        },
-                                                    )
-                                                    .with_text_justify(JustifyText::Center),
-                                                );
-                                            });
-                                    }
-                                }
-                            };
-                        });
-                    }
-                });
+    /// Ctrl+/ disables the active block or enables it again
+    fn toggle_disabled(
+        active: Res<ActiveEntity>,
+        blocks: Query<Has<Disabled>, With<Block>>,
+        mut commands: Commands,
+    ) {
+        let Some((entity, disabled)) = active
+            .entity
+            .and_then(|entity| Some((entity, blocks.get(entity).ok()?)))
+        else {
+            return;
+        };
+        if disabled {
+            commands.entity(entity).remove::<Disabled>();
+        } else {
+            commands.entity(entity).insert(Disabled);
+        }
+    }
 
-                for direction in connections {
-                    connector_writer.send(SpawnConnector {
-                        connector: Connector {
-                            fixture: ui_box.id(),
-                            direction,
-                            // connection_type: ConnectionType::Flow,
-                            connected: false,
-                        },
-                        radius: 7.,
-                    });
-                }
-            });
+    /// Ctrl+F goes through the concepts that the blocks can be filtered by and then back to
+    /// showing every block
+    fn cycle_concept_filter(mut filter: ResMut<ConceptFilter>) {
+        filter.concept = match filter.concept {
+            None => Some(ConceptType::ControlFlow),
+            Some(ConceptType::ControlFlow) => Some(ConceptType::Input),
+            Some(ConceptType::Input) => Some(ConceptType::Output),
+            Some(ConceptType::Output) => None,
+        };
+        match filter.concept {
+            Some(concept) => info!("Only showing the {concept:?} blocks"),
+            None => info!("Showing every block"),
         }
     }
 
-    fn update_size(mut query: Query<(&mut Size, &Node), With<Block>>) {
-        for (mut box_size, box_node) in &mut query {
-            box_size.0 = box_node.size();
+    /// Dims the disabled blocks and the blocks that the concept filter hides
+    fn dim_blocks(
+        filter: Res<ConceptFilter>,
+        disabled: Query<Entity, Added<Disabled>>,
+        mut enabled: RemovedComponents<Disabled>,
+        added: Query<Entity, Added<Block>>,
+        mut blocks: Query<(Entity, &mut BackgroundColor, &BlockType, Has<Disabled>), With<Block>>,
+    ) {
+        let entities = if filter.is_changed() {
+            blocks.iter().map(|(entity, ..)| entity).collect::<Vec<_>>()
+        } else {
+            disabled
+                .iter()
+                .chain(&added)
+                .chain(enabled.read())
+                .collect()
+        };
+        let mut dimmed = blocks.iter_many_mut(&entities);
+        while let Some((_, mut background, block_type, disabled)) = dimmed.fetch_next() {
+            background
+                .0
+                .set_a(block_alpha(disabled, filter.hides(block_type)));
         }
     }
 
-    fn translate_position(
-        mut query: Query<(&Position, &mut Style), (With<UIBox>, Changed<Position>)>,
+    fn snap_to_grid(
+        grid_snap: Res<GridSnap>,
+        drag: Res<DragEntity>,
+        selection: Res<Selection>,
+        mut boxes: Query<&mut Position, (Without<Arg>, With<Block>)>,
     ) {
-        for (box_pos, mut box_style) in &mut query {
-            if box_style.position_type == PositionType::Absolute {
-                box_style.top = Val::Px(box_pos.0.y);
-                box_style.left = Val::Px(box_pos.0.x);
+        let Some(drag_entity) = drag
+            .entity
+            .filter(|_| grid_snap.enabled && grid_snap.size > 0.)
+        else {
+            return;
+        };
+        let snap = |position: &mut Position| {
+            position.0 = (position.0 / grid_snap.size).round() * grid_snap.size;
+        };
+
+        if selection.entities.contains(&drag_entity) {
+            let mut selected = boxes.iter_many_mut(&selection.entities);
+            while let Some(mut position) = selected.fetch_next() {
+                snap(&mut position);
             }
+        } else if let Ok(mut position) = boxes.get_mut(drag_entity) {
+            snap(&mut position);
         }
     }
 
-    fn translate_position_args(
-        mut query: Query<(&mut Position, &GlobalTransform), (With<Arg>, Changed<GlobalTransform>)>,
+    /// Moves the dropped blocks back on the background so that they can't get lost outside of it
+    fn clamp_to_canvas(
+        canvas_clamp: Res<CanvasClamp>,
+        drag: Res<DragEntity>,
+        selection: Res<Selection>,
+        background: Query<&Node, With<BackgroundBox>>,
+        mut boxes: Query<(&mut Position, &Size), (Without<Arg>, With<Block>)>,
     ) {
-        for (mut position, transform) in &mut query {
-            position.0 = transform.translation().xy();
+        let Some(drag_entity) = drag.entity.filter(|_| canvas_clamp.enabled) else {
+            return;
+        };
+        let Ok(background) = background.get_single() else {
+            return;
+        };
+        let min = Vec2::splat(canvas_clamp.margin);
+        let clamp = |position: &mut Position, size: &Size| {
+            let max = (background.size() - size.0 - canvas_clamp.margin).max(min);
+            position.0 = position.0.clamp(min, max);
+        };
+
+        if selection.entities.contains(&drag_entity) {
+            let mut selected = boxes.iter_many_mut(&selection.entities);
+            while let Some((mut position, size)) = selected.fetch_next() {
+                clamp(&mut position, size);
+            }
+        } else if let Ok((mut position, size)) = boxes.get_mut(drag_entity) {
+            clamp(&mut position, size);
         }
     }
 
-    fn handle_color_change(
-        query: Query<(Entity, &FocusColor), (Changed<FocusColor>, Or<(With<UIBox>, With<Hole>)>)>,
-        mut style_query: Query<&mut BorderColor, Or<(With<UIBox>, With<Hole>)>>,
+    /// Alt with the arrows lines up the edges of the selected blocks, Alt+C and Alt+M line up
+    /// their centers and Alt+H and Alt+V spread them out evenly
+    fn align_selection(
+        keyboard: Res<ButtonInput<KeyCode>>,
+        selection: Res<Selection>,
+        grid_snap: Res<GridSnap>,
+        mut boxes: Query<(&mut Position, &Size), (Without<Arg>, With<Block>)>,
     ) {
-        for (entity, focus_color) in &query {
-            let Ok(mut border_color) = style_query.get_mut(entity) else {
-                error!("Couldn't find the color");
-                continue;
-            };
-            border_color.0 = focus_color.0;
+        let alignment = match keyboard.get_just_pressed().next() {
+            Some(KeyCode::ArrowLeft) => Alignment::Left,
+            Some(KeyCode::ArrowRight) => Alignment::Right,
+            Some(KeyCode::ArrowUp) => Alignment::Top,
+            Some(KeyCode::ArrowDown) => Alignment::Bottom,
+            Some(KeyCode::KeyC) => Alignment::CenterHorizontal,
+            Some(KeyCode::KeyM) => Alignment::CenterVertical,
+            Some(KeyCode::KeyH) => Alignment::DistributeHorizontal,
+            Some(KeyCode::KeyV) => Alignment::DistributeVertical,
+            _ => return,
+        };
+        let entities = selection
+            .entities
+            .iter()
+            .copied()
+            .filter(|&entity| boxes.contains(entity))
+            .collect::<Vec<_>>();
+        if entities.len() < 2 {
+            return;
+        }
+        let rects = entities
+            .iter()
+            .filter_map(|&entity| boxes.get(entity).ok())
+            .map(|(position, size)| (position.0, size.0))
+            .collect::<Vec<_>>();
+        let positions = align_positions(&rects, alignment);
+        for (&entity, new_position) in entities.iter().zip(positions) {
+            if let Ok((mut position, _)) = boxes.get_mut(entity) {
+                position.0 = if grid_snap.enabled && grid_snap.size > 0. {
+                    (new_position / grid_snap.size).round() * grid_snap.size
+                } else {
+                    new_position
+                };
+            }
+        }
+    }
+
+    fn toggle_grid_snap(mut grid_snap: ResMut<GridSnap>) {
+        grid_snap.enabled = !grid_snap.enabled;
+        info!("Grid snapping is now {}", grid_snap.enabled);
+    }
+
+    fn draw_grid(
+        grid_snap: Res<GridSnap>,
+        view: CanvasView,
+        theme: Res<Theme>,
+        mut gizmos: Gizmos,
+    ) {
+        if !grid_snap.enabled || grid_snap.size <= 0. {
+            return;
+        }
+        let background_size = view.size();
+        let color = theme.text.with_a(0.05);
+
+        let mut x = 0.;
+        while x <= background_size.x {
+            let from = view.to_world(Vec2::new(x, 0.));
+            let to = view.to_world(Vec2::new(x, background_size.y));
+            gizmos.line_2d(from, to, color);
+            x += grid_snap.size;
+        }
+        let mut y = 0.;
+        while y <= background_size.y {
+            let from = view.to_world(Vec2::new(0., y));
+            let to = view.to_world(Vec2::new(background_size.x, y));
+            gizmos.line_2d(from, to, color);
+            y += grid_snap.size;
         }
     }
 
+    /// The arrows move the active block by a grid unit, by a pixel with Shift and by a large step
+    /// with Ctrl
     fn move_according_to_keyboard(
         active: Res<ActiveEntity>,
         mut boxes: Query<&mut Position, With<Block>>,
         mut keyboard_events: EventReader<KeyboardInput>,
+        keyboard: Res<ButtonInput<KeyCode>>,
+        grid_snap: Res<GridSnap>,
     ) {
+        // INFO: Alt with the arrows aligns the selection instead
+        if keyboard.any_pressed([KeyCode::AltLeft, KeyCode::AltRight]) {
+            keyboard_events.clear();
+            return;
+        }
         if let Some(active) = active.entity.filter(|&entity| boxes.get(entity).is_ok()) {
             let mut position = boxes
                 .get_mut(active)
                 .expect("Expected the active box to be in the world tree ");
-            for keyboard_event in keyboard_events.read() {
+            let step = if keyboard.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+                FINE_NUDGE_STEP
+            } else if keyboard.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) {
+                LARGE_NUDGE_STEP
+            } else if grid_snap.enabled {
+                grid_snap.size
+            } else {
+                NUDGE_STEP
+            };
+            for keyboard_event in keyboard_events
+                .read()
+                .filter(|event| event.state == ButtonState::Pressed)
+            {
                 let vector = match keyboard_event.key_code {
                     KeyCode::ArrowUp => Vec2::NEG_Y,
                     KeyCode::ArrowDown => Vec2::Y,
@@ -470,7 +1571,7 @@ impl UIBoxPlugin {
                     KeyCode::ArrowRight => Vec2::X,
                     _ => Vec2::ZERO,
                 };
-                position.0 += vector * 10.;
+                position.0 += vector * step;
             }
         }
     }
@@ -478,15 +1579,28 @@ impl UIBoxPlugin {
     fn move_active_box_according_to_mouse(
         mut mouse_motion_event: EventReader<CursorMoved>,
         active: Res<DragEntity>,
+        selection: Res<Selection>,
+        ui_scale: Res<UiScale>,
         mut boxes: Query<&mut Position, (Without<Arg>, With<Block>)>,
     ) {
-        if let Some(mut pos) = active.entity.and_then(|entity| boxes.get_mut(entity).ok()) {
-            for delta in mouse_motion_event
-                .read()
-                .map(|motion| motion.delta.unwrap_or_default())
-            {
+        let Some(drag_entity) = active.entity.filter(|&entity| boxes.contains(entity)) else {
+            return;
+        };
+        let delta: Vec2 = mouse_motion_event
+            .read()
+            .map(|motion| motion.delta.unwrap_or_default())
+            .sum();
+        // INFO: The cursor moves in the coordinates of the window which the UiScale doesn't change
+        let delta = delta / ui_scale.0;
+
+        // INFO: Dragging a block that is part of the selection moves the whole selection
+        if selection.entities.contains(&drag_entity) {
+            let mut selected = boxes.iter_many_mut(&selection.entities);
+            while let Some(mut pos) = selected.fetch_next() {
                 pos.0 += delta;
             }
+        } else if let Ok(mut pos) = boxes.get_mut(drag_entity) {
+            pos.0 += delta;
         }
     }
 
@@ -508,20 +1622,205 @@ impl UIBoxPlugin {
         }
     }
 
+    /// Spawns a copy of the active block next to it, the copy has no connections or args
+    fn duplicate_block(
+        active: Res<ActiveEntity>,
+        blocks: Query<(&Position, &Size, &BlockType), With<Block>>,
+        text_inputs: Query<(&TextInput, &TextInputValue)>,
+        mut writer: EventWriter<SpawnUIBox>,
+    ) {
+        let Some((active, (position, size, block_type))) = active
+            .entity
+            .and_then(|entity| Some((entity, blocks.get(entity).ok()?)))
+        else {
+            return;
+        };
+        let text = text_inputs
+            .iter()
+            .find(|(text_input, _)| text_input.owner == active)
+            .map(|(_, value)| value.0.clone());
+        let position = position.0 + DUPLICATE_OFFSET;
+        writer.send(SpawnUIBox {
+            bundle: BlockBundle::new(
+                position.x,
+                position.y,
+                size.0.x,
+                size.0.y,
+                InteractionFocusBundle::default(),
+                block_type.clone(),
+            ),
+            marker: None,
+            text,
+        });
+    }
+
+    fn send_delete(
+        block: Entity,
+        delete_writer: &mut EventWriter<DeleteEvent>,
+        remove_ast_writer: &mut EventWriter<RemoveFromAst>,
+    ) {
+        delete_writer.send(DeleteEvent(block));
+        remove_ast_writer.send(RemoveFromAst {
+            parent: None,
+            child: block,
+        });
+    }
+
+    /// Counts the lines to and from the block and the args in its holes
+    fn count_connections(
+        block: Entity,
+        ast: &Ast,
+        holes: &Query<(&Hole, Option<&Children>)>,
+        boxes: &Query<&Block>,
+    ) -> usize {
+        let outgoing = ast
+            .map
+            .get(&block)
+            .map_or(0, |connections| connections.iter().flatten().count());
+        let incoming = ast
+            .map
+            .values()
+            .flatten()
+            .flatten()
+            .filter(|(entity, _)| *entity == block)
+            .count();
+        let args = holes
+            .iter()
+            .filter(|(hole, _)| hole.owner == block)
+            .flat_map(|(_, children)| children.into_iter().flatten())
+            .filter(|&&child| boxes.contains(child))
+            .count();
+        outgoing + incoming + args
+    }
+
     fn delete_block(
         active: Res<ActiveEntity>,
         boxes: Query<&Block>,
+        ast: Res<Ast>,
+        holes: Query<(&Hole, Option<&Children>)>,
+        config: Res<DeleteConfirmConfig>,
+        dialogs: Query<Entity, With<DeleteConfirmDialog>>,
         mut delete_writer: EventWriter<DeleteEvent>,
         mut remove_ast_writer: EventWriter<RemoveFromAst>,
+        mut commands: Commands,
     ) {
         // If the active box is a block
-        if let Some(active) = active.entity.filter(|&entity| boxes.get(entity).is_ok()) {
-            delete_writer.send(DeleteEvent(active));
-            remove_ast_writer.send(RemoveFromAst {
-                parent: None,
-                child: active,
+        let Some(active) = active.entity.filter(|&entity| boxes.get(entity).is_ok()) else {
+            return;
+        };
+        let connections = Self::count_connections(active, &ast, &holes, &boxes);
+        if !config.enabled || connections == 0 {
+            Self::send_delete(active, &mut delete_writer, &mut remove_ast_writer);
+            return;
+        }
+
+        for dialog in &dialogs {
+            commands.entity(dialog).despawn_recursive();
+        }
+        let text_style = TextStyle {
+            color: Color::WHITE,
+            font_size: 18.,
+            ..default()
+        };
+        let message = if connections == 1 {
+            "Delete block and 1 connection?".to_owned()
+        } else {
+            format!("Delete block and {connections} connections?")
+        };
+        commands
+            .spawn(DeleteConfirmDialogBundle::new(active))
+            .with_children(|parent| {
+                parent.spawn(TextBundle::from_section(message, text_style.clone()));
+                parent
+                    .spawn(NodeBundle {
+                        style: Style {
+                            column_gap: Val::Px(8.),
+                            ..default()
+                        },
+                        ..default()
+                    })
+                    .with_children(|parent| {
+                        for (label, confirm) in [("Delete", true), ("Cancel", false)] {
+                            parent
+                                .spawn((
+                                    ButtonBundle {
+                                        style: Style {
+                                            padding: UiRect::axes(Val::Px(8.), Val::Px(4.)),
+                                            border: UiRect::all(Val::Px(1.)),
+                                            ..default()
+                                        },
+                                        border_color: BorderColor(Color::WHITE),
+                                        background_color: BackgroundColor(Color::NONE),
+                                        ..default()
+                                    },
+                                    DeleteConfirmButton { confirm },
+                                ))
+                                .with_children(|parent| {
+                                    parent
+                                        .spawn(TextBundle::from_section(label, text_style.clone()));
+                                });
+                        }
+                    });
+            });
+    }
+
+    fn handle_drop_on_trash(
+        drag_entity: Res<DragEntity>,
+        hover_entity: Res<HoverEntity>,
+        trash: Query<(), With<Trash>>,
+        blocks: Query<Option<&Arg>, With<Block>>,
+        holes: Query<&Hole>,
+        mut block_map: ResMut<BlockDataMap>,
+        mut delete_writer: EventWriter<DeleteEvent>,
+        mut remove_ast_writer: EventWriter<RemoveFromAst>,
+        mut update_writer: EventWriter<UpdateAst>,
+        mut commands: Commands,
+    ) {
+        if !hover_entity
+            .entity
+            .is_some_and(|entity| trash.contains(entity))
+        {
+            return;
+        }
+        let Some((entity, arg)) = drag_entity
+            .entity
+            .and_then(|entity| Some((entity, blocks.get(entity).ok()?)))
+        else {
+            return;
+        };
+        // INFO: An arg leaves an empty hole behind
+        if let Some((arg, hole)) = arg.and_then(|arg| Some((arg, holes.get(arg.owner).ok()?))) {
+            block_map.remove_arg(hole.owner, entity);
+            commands.entity(arg.owner).with_children(|parent| {
+                HoleBundle::spawn_placeholder(parent, hole);
             });
         }
+        Self::send_delete(entity, &mut delete_writer, &mut remove_ast_writer);
+        update_writer.send_default();
+    }
+
+    fn handle_delete_confirm(
+        buttons: Query<(&Interaction, &DeleteConfirmButton), Changed<Interaction>>,
+        dialogs: Query<(Entity, &DeleteConfirmDialog)>,
+        keys: Res<ButtonInput<KeyCode>>,
+        mut delete_writer: EventWriter<DeleteEvent>,
+        mut remove_ast_writer: EventWriter<RemoveFromAst>,
+        mut commands: Commands,
+    ) {
+        let pressed = buttons
+            .iter()
+            .find(|(interaction, _)| matches!(interaction, Interaction::Pressed))
+            .map(|(_, button)| button.confirm);
+        let Some(confirm) = pressed.or_else(|| keys.just_pressed(KeyCode::Escape).then_some(false))
+        else {
+            return;
+        };
+        for (entity, dialog) in &dialogs {
+            if confirm {
+                Self::send_delete(dialog.block, &mut delete_writer, &mut remove_ast_writer);
+            }
+            commands.entity(entity).despawn_recursive();
+        }
     }
 
     fn make_focus_passable(
@@ -544,17 +1843,27 @@ impl UIBoxPlugin {
     }
     fn make_focus_unpassable(
         drag_entity: Res<DragEntity>,
+        filter: Res<ConceptFilter>,
         mut focus_block: Query<
-            (&mut FocusPolicy, &mut BackgroundColor, &mut Transform),
+            (
+                &mut FocusPolicy,
+                &mut BackgroundColor,
+                &mut Transform,
+                &BlockType,
+                Has<Disabled>,
+            ),
             With<Block>,
         >,
     ) {
         if let Some(entity) = drag_entity.entity {
-            let Ok((mut policy, mut background, mut transform)) = focus_block.get_mut(entity)
+            let Ok((mut policy, mut background, mut transform, block_type, disabled)) =
+                focus_block.get_mut(entity)
             else {
                 return;
             };
-            background.0 = background.0.with_a(1.);
+            background.0 = background
+                .0
+                .with_a(block_alpha(disabled, filter.hides(block_type)));
             transform.translation.z = 100.;
             *policy = FocusPolicy::Block;
         } else {
@@ -562,7 +1871,7 @@ impl UIBoxPlugin {
         }
     }
 
-    fn handle_spawn_active_arg(
+    pub(crate) fn handle_spawn_active_arg(
         mut arg_reader: EventReader<SpawnArg>,
         mut commands: Commands,
         mut update_writer: EventWriter<UpdateAst>,
@@ -570,9 +1879,22 @@ impl UIBoxPlugin {
         connectors: Query<&Connector>,
         mut style: Query<&mut Style>,
         hole: Query<&Hole>,
+        args: Query<&Arg>,
+        order_labels: Query<(), With<HoleOrderLabel>>,
     ) {
         for event in arg_reader.read() {
             info!("Running the spawning of args");
+            // INFO: When an argument is moved between holes, the hole it left is empty again
+            if let Some((old_hole, old)) = args
+                .get(event.arg)
+                .ok()
+                .filter(|arg| arg.owner != event.parent)
+                .and_then(|arg| Some((arg.owner, hole.get(arg.owner).ok()?)))
+            {
+                commands.entity(old_hole).with_children(|parent| {
+                    HoleBundle::spawn_placeholder(parent, old);
+                });
+            }
             let Ok(mut style) = style.get_mut(event.arg) else {
                 info!("Couldn't get the style for the argument");
                 continue;
@@ -581,11 +1903,16 @@ impl UIBoxPlugin {
             style.top = Val::Px(0.);
             style.left = Val::Px(0.);
 
-            let Some(mut parent_commands) = commands.get_entity(event.parent) else {
+            if commands.get_entity(event.parent).is_none() {
                 info!("Couldn't get the commands for the parent");
                 continue;
-            };
-            parent_commands.despawn_descendants();
+            }
+            // INFO: The placeholder makes way for the arg but the order of the hole stays
+            for &child in children.get(event.parent).into_iter().flatten() {
+                if !order_labels.contains(child) {
+                    commands.entity(child).despawn_recursive();
+                }
+            }
 
             let Some(mut arg_commands) = commands.get_entity(event.arg) else {
                 info!("Couldn't get the commands for the arguments");
@@ -624,40 +1951,226 @@ impl UIBoxPlugin {
         hole_query: Query<(Entity, &Hole)>,
         arg_query: Query<&Arg>,
         boxes: Query<(Entity, &BlockType), With<Block>>,
+        text_inputs: Query<(&TextInput, &TextInputValue)>,
+        languages: Res<Languages>,
         mut arg_writer: EventWriter<SpawnArg>,
         mut error_writer: EventWriter<ErrorEvent>,
     ) {
-        if let Some((drag_entity, block_type)) = drag_entity
-            .entity
-            .and_then(|entity| {
-                boxes
-                    .get(entity)
-                    .ok()
-                    .filter(|(_, block_type)| block_type.can_be_in_a_hole())
-            })
-            .filter(|&(entity, _)| arg_query.get(entity).is_err())
-        {
+        if let Some((drag_entity, block_type)) = drag_entity.entity.and_then(|entity| {
+            boxes
+                .get(entity)
+                .ok()
+                .filter(|(_, block_type)| block_type.can_be_in_a_hole())
+        }) {
+            // INFO: An argument can only be moved to another hole of the block it is in
+            let current_hole = arg_query
+                .get(drag_entity)
+                .ok()
+                .and_then(|arg| hole_query.get(arg.owner).ok());
             if let Some((hover_entity, hole)) = hover_entity
                 .entity
                 .and_then(|entity| hole_query.get(entity).ok())
                 .filter(|(_, hole)| hole.owner != drag_entity)
+                .filter(|&(hover_entity, hole)| {
+                    current_hole.map_or(true, |(current_entity, current_hole)| {
+                        current_entity != hover_entity && current_hole.owner == hole.owner
+                    })
+                })
             {
                 let block_type_value = &block_type.value;
                 let hole_type_value = &hole.hole_type;
-                if hole_type_value == &HoleType::Any || block_type_value == hole_type_value {
+                let reserved_word = text_inputs
+                    .iter()
+                    .find(|(text_input, _)| text_input.owner == drag_entity)
+                    .map(|(_, value)| value.0.as_str())
+                    .filter(|value| languages.active().is_reserved(value));
+                if Self::is_arg_ancestor(drag_entity, hole.owner, &arg_query, &hole_query) {
+                    error_writer.send(ErrorEvent(
+                        "A block can't be dropped into a hole of one of its own arguments".into(),
+                    ));
+                } else if Self::is_hole_filled(hover_entity, &arg_query) {
+                    error_writer.send(ErrorEvent(
+                        "That hole already has a block in it, take it out first".into(),
+                    ));
+                } else if let Some(reserved_word) =
+                    reserved_word.filter(|_| hole_type_value == &HoleType::Variable)
+                {
+                    error_writer.send(ErrorEvent(format!(
+                        "'{reserved_word}' is a reserved word and can't be used as a variable name"
+                    )));
+                } else if hole_type_value.accepts(block_type_value) {
                     arg_writer.send(SpawnArg {
                         arg: drag_entity,
                         parent: hover_entity,
                     });
                 } else {
-                    error_writer.send(ErrorEvent(
-                        format!("Block with type {block_type_value:?} was dropped in a hole that expected {hole_type_value:?}")
-                    ));
+                    let (hole_name, owner_name) = boxes
+                        .get(hole.owner)
+                        .map(|(_, owner_type)| {
+                            (
+                                owner_type.get_hole_name(hole.order),
+                                owner_type.name.as_str(),
+                            )
+                        })
+                        .unwrap_or_else(|_| (format!("hole {}", hole.order + 1), "block"));
+                    error_writer.send(ErrorEvent(format!(
+                        "Can't put '{}' into the '{hole_name}' slot of '{owner_name}' (expects {hole_type_value:?})",
+                        block_type.name
+                    )));
+                }
+            }
+        }
+    }
+
+    /// Tints every hole that the dragged block could be dropped in
+    fn highlight_valid_holes(
+        drag_entity: Res<DragEntity>,
+        boxes: Query<&BlockType, With<Block>>,
+        hole_query: Query<(Entity, &Hole)>,
+        arg_query: Query<&Arg>,
+        mut focus: Query<(&mut Focus, &mut FocusColor), With<Hole>>,
+        theme: Res<Theme>,
+        mut commands: Commands,
+    ) {
+        let Some((drag_entity, block_type)) = drag_entity.entity.and_then(|entity| {
+            boxes
+                .get(entity)
+                .ok()
+                .filter(|block_type| block_type.can_be_in_a_hole())
+                .map(|block_type| (entity, block_type))
+        }) else {
+            return;
+        };
+        // INFO: The same rules as when the block is dropped in the hole
+        let current_hole = arg_query
+            .get(drag_entity)
+            .ok()
+            .and_then(|arg| hole_query.get(arg.owner).ok());
+        for (hole_entity, hole) in &hole_query {
+            let is_valid = hole.hole_type.accepts(&block_type.value)
+                && current_hole.map_or(true, |(current_entity, current_hole)| {
+                    current_entity != hole_entity && current_hole.owner == hole.owner
+                })
+                && !Self::is_hole_filled(hole_entity, &arg_query)
+                && !Self::is_arg_ancestor(drag_entity, hole.owner, &arg_query, &hole_query);
+            if !is_valid {
+                continue;
+            }
+            if let Ok((mut focus, mut focus_color)) = focus.get_mut(hole_entity) {
+                commands
+                    .entity(hole_entity)
+                    .insert(HighlightedHole(focus.inactive));
+                focus.inactive = theme.highlight;
+                focus_color.0 = theme.highlight;
+            }
+        }
+    }
+
+    /// Checks the text of the Text blocks in holes as soon as it changes, the border of the block
+    /// is green when the text fits the type of the hole and red when it doesn't
+    #[allow(clippy::too_many_arguments)]
+    fn check_text_args(
+        text_inputs: Query<(&TextInput, &TextInputValue)>,
+        mut blocks: Query<
+            (
+                &BlockType,
+                Option<&Arg>,
+                Option<&ValueCheck>,
+                &mut Focus,
+                &mut FocusColor,
+            ),
+            With<Block>,
+        >,
+        holes: Query<&Hole>,
+        languages: Res<Languages>,
+        theme: Res<Theme>,
+        mut commands: Commands,
+    ) {
+        for (text_input, value) in &text_inputs {
+            let Ok((block_type, arg, check, mut focus, mut focus_color)) =
+                blocks.get_mut(text_input.owner)
+            else {
+                continue;
+            };
+            if block_type.name != "Text" {
+                continue;
+            }
+            let language = languages
+                .get(&block_type.language)
+                .unwrap_or_else(|| languages.active());
+            // INFO: An empty Text block is an empty hole which is reported when the code is made
+            let error = arg
+                .and_then(|arg| holes.get(arg.owner).ok())
+                .filter(|_| !value.0.is_empty())
+                .map(|hole| {
+                    (!language.valid_input(&hole.hole_type, &value.0))
+                        .then(|| format!("'{}' isn't a valid {:?}", value.0, hole.hole_type))
+                });
+
+            if check.map(|check| &check.error) == error.as_ref() {
+                continue;
+            }
+            let inactive = check.map_or(focus.inactive, |check| check.inactive);
+            let color = match &error {
+                Some(Some(_)) => theme.error,
+                Some(None) => theme.valid,
+                None => inactive,
+            };
+            if focus_color.0 == focus.inactive {
+                focus_color.0 = color;
+            }
+            focus.inactive = color;
+            match error {
+                Some(error) => {
+                    commands
+                        .entity(text_input.owner)
+                        .insert(ValueCheck { inactive, error });
+                }
+                None => {
+                    commands.entity(text_input.owner).remove::<ValueCheck>();
                 }
             }
         }
     }
 
+    fn clear_hole_highlight(
+        mut holes: Query<(Entity, &HighlightedHole, &mut Focus, &mut FocusColor)>,
+        mut commands: Commands,
+    ) {
+        for (entity, &HighlightedHole(inactive), mut focus, mut focus_color) in &mut holes {
+            focus.inactive = inactive;
+            focus_color.0 = inactive;
+            commands.entity(entity).remove::<HighlightedHole>();
+        }
+    }
+
+    /// Checks if a block is already in the hole, dropping another one in it would replace it
+    fn is_hole_filled(hole: Entity, args: &Query<&Arg>) -> bool {
+        args.iter().any(|arg| arg.owner == hole)
+    }
+
+    /// Checks if `block` is `ancestor` or is nested inside of one of the holes of `ancestor`
+    fn is_arg_ancestor(
+        ancestor: Entity,
+        mut block: Entity,
+        args: &Query<&Arg>,
+        holes: &Query<(Entity, &Hole)>,
+    ) -> bool {
+        loop {
+            if block == ancestor {
+                return true;
+            }
+            let Some((_, hole)) = args
+                .get(block)
+                .ok()
+                .and_then(|arg| holes.get(arg.owner).ok())
+            else {
+                return false;
+            };
+            block = hole.owner;
+        }
+    }
+
     fn move_arg_according_to_mouse(
         curr_drag: Res<DragEntity>,
         mut arg_query: Query<&mut GlobalTransform, With<Arg>>,
@@ -681,31 +2194,61 @@ impl UIBoxPlugin {
         curr_drag: Res<DragEntity>,
         hover_entity: Res<HoverEntity>,
         background: Query<&BackgroundBox>,
-        mut args: Query<(Entity, &GlobalTransform, &mut Style), With<Arg>>,
+        holes: Query<&Hole>,
+        mut args: Query<
+            (
+                Entity,
+                &Arg,
+                &BlockType,
+                &GlobalTransform,
+                &Node,
+                &mut Position,
+                &mut Style,
+            ),
+            With<Arg>,
+        >,
+        mut block_map: ResMut<BlockDataMap>,
+        ui_scale: Res<UiScale>,
         mut commands: Commands,
+        mut connector_writer: EventWriter<SpawnConnector>,
         mut update_writer: EventWriter<UpdateAst>,
     ) {
         if let Some(hover_entity) = hover_entity
             .entity
             .filter(|&entity| background.get(entity).is_ok())
         {
-            if let Some((entity, global_transform, mut styles)) = curr_drag
+            if let Some((
+                entity,
+                arg,
+                block_type,
+                global_transform,
+                node,
+                mut position,
+                mut styles,
+            )) = curr_drag
                 .entity
                 .and_then(|entity| args.get_mut(entity).ok())
             {
                 commands.entity(entity).remove::<Arg>();
+                if let Ok(hole) = holes.get(arg.owner) {
+                    block_map.remove_arg(hole.owner, entity);
+                    commands.entity(arg.owner).with_children(|parent| {
+                        HoleBundle::spawn_placeholder(parent, hole);
+                    });
+                }
 
-                let mut background = commands.entity(hover_entity);
-
-                // INFO: Set the variable to be absolute and get the current global position and set it
-                // the top and left to that position
-
-                let curr_pos = global_transform.translation().xy();
+                // INFO: The global transform is the center of the block, so the top left corner
+                // is half of its size away from it. Both are scaled by the UiScale but the
+                // position isn't
+                position.0 = (global_transform.translation().xy() - node.size() / 2.) / ui_scale.0;
                 styles.position_type = PositionType::Absolute;
-                styles.top = Val::Px(curr_pos.y);
-                styles.left = Val::Px(curr_pos.x);
+                styles.top = Val::Px(position.0.y);
+                styles.left = Val::Px(position.0.x);
 
-                background.push_children(&[entity]);
+                commands.entity(hover_entity).push_children(&[entity]);
+
+                // INFO: The connectors were despawned when the block was put in the hole
+                Self::send_connectors(&mut connector_writer, entity, &block_type.connectors);
             }
         }
         update_writer.send_default();
@@ -719,17 +2262,91 @@ impl UIBoxPlugin {
         }
     }
 
-    fn send_language_list(language: Res<Language>, mut socket_writer: EventWriter<WASMRequest>) {
-        let list = language.get_lang_data();
+    fn send_language_list(languages: Res<Languages>, mut socket_writer: EventWriter<WASMRequest>) {
+        let list = languages.active().get_lang_data();
         socket_writer.send(WASMRequest(Message::LanguageList(list)));
     }
+
+    #[allow(clippy::too_many_arguments)]
+    fn handle_switch_language(
+        mut reader: EventReader<SwitchLanguage>,
+        mut languages: ResMut<Languages>,
+        mut blocks: Query<&mut BlockType>,
+        mut hole_values: Query<(&mut HoleValue, &mut HoleOptions, &Children)>,
+        mut texts: Query<&mut Text>,
+        mut update_writer: EventWriter<UpdateAst>,
+        mut error_writer: EventWriter<ErrorEvent>,
+        mut socket_writer: EventWriter<WASMRequest>,
+    ) {
+        for SwitchLanguage(name) in reader.read() {
+            if !languages.set_active(name) {
+                error_writer.send(ErrorEvent(format!("The language {name} is not loaded")));
+                continue;
+            }
+            let language = languages.active();
+
+            // INFO: Swap the block types of the existing blocks so that the code gets generated
+            // with the templates of the new language
+            for mut block_type in &mut blocks {
+                let Some(new_block_type) = language.get_block(&block_type.name) else {
+                    error_writer.send(ErrorEvent(format!(
+                        "Block {} doesn't exist in {name}",
+                        block_type.name
+                    )));
+                    continue;
+                };
+                if new_block_type.holes != block_type.holes {
+                    error_writer.send(ErrorEvent(format!(
+                        "Block {} has different holes in {name}",
+                        block_type.name
+                    )));
+                    continue;
+                }
+                let value = block_type.value.clone();
+                *block_type = new_block_type;
+                if block_type.has_text() {
+                    block_type.value = value;
+                }
+            }
+
+            // INFO: The comparison holes can only pick the operators of the new language
+            for (mut hole_value, mut options, children) in &mut hole_values {
+                let is_comparitor = blocks.get(hole_value.owner).is_ok_and(|block_type| {
+                    block_type.holes.get(hole_value.order) == Some(&HoleType::Comparitor)
+                });
+                if !is_comparitor {
+                    continue;
+                }
+                options.0 = language.get_comparitors();
+                if options.0.contains(&hole_value.value) {
+                    continue;
+                }
+                let first = options.0.first().cloned().unwrap_or_default();
+                error_writer.send(ErrorEvent(format!(
+                    "{name} has no '{}' comparison, it was changed to '{first}'",
+                    hole_value.value
+                )));
+                Self::set_hole_value(&mut hole_value, children, &mut texts, &first);
+            }
+
+            socket_writer.send(WASMRequest(Message::LanguageList(language.get_lang_data())));
+            update_writer.send_default();
+        }
+    }
 }
 
 impl Plugin for UIBoxPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<SpawnUIBox>()
             .add_event::<SpawnArg>()
-            .insert_resource(Language::new())
+            .add_event::<SwitchLanguage>()
+            .insert_resource(Languages::new())
+            .init_resource::<GridSnap>()
+            .init_resource::<CanvasClamp>()
+            .init_resource::<ConceptFilter>()
+            .init_resource::<DeleteConfirmConfig>()
+            .init_resource::<LanguageConfig>()
+            .add_systems(PreStartup, Self::load_language)
             .add_systems(
                 Startup,
                 (
@@ -738,14 +2355,24 @@ impl Plugin for UIBoxPlugin {
                     Self::send_language_list,
                 ),
             )
-            .add_systems(OnEnter(DragState::Started), Self::make_focus_passable)
+            .add_systems(
+                OnEnter(DragState::Started),
+                (Self::make_focus_passable, Self::highlight_valid_holes),
+            )
+            .add_systems(
+                Last,
+                Self::check_connector_count.run_if(|| cfg!(debug_assertions)),
+            )
             .add_systems(
                 OnExit(DragState::Started),
                 (
+                    Self::handle_drop_on_trash,
                     Self::handle_hover_on_hole,
-                    Self::handle_spawn_active_arg,
                     Self::handle_outside_hole,
                     Self::make_focus_unpassable,
+                    Self::clear_hole_highlight,
+                    Self::snap_to_grid,
+                    Self::clamp_to_canvas,
                 )
                     .chain(),
             )
@@ -754,12 +2381,17 @@ impl Plugin for UIBoxPlugin {
                 (
                     (
                         Self::handle_spawn_ui_box,
+                        Self::cycle_hole_value,
+                        Self::step_number_input,
+                        Self::sync_number_input,
                         Self::handle_color_change,
+                        Self::handle_color_override,
                         Self::move_active_box_according_to_mouse
                             .run_if(in_state(DragState::Started)),
                         Self::move_according_to_keyboard,
                         Self::move_arg_according_to_mouse.run_if(in_state(DragState::Started)),
-                        // Self::spawn_box,
+                        Self::resize_block.run_if(in_state(DragState::Started)),
+                        Self::spawn_box,
                         Self::translate_position,
                         Self::translate_position_args,
                         Self::update_size,
@@ -767,6 +2399,38 @@ impl Plugin for UIBoxPlugin {
                     )
                         .chain()
                         .in_set(GameSets::Running),
+                    Self::handle_switch_language.in_set(GameSets::Running),
+                    // INFO: This runs outside of the drag state so that pasted args can be spawned
+                    Self::handle_spawn_active_arg
+                        .run_if(on_event::<SpawnArg>())
+                        .in_set(GameSets::Running),
+                    Self::cycle_color_override
+                        .run_if(control_just_pressed(KeyCode::KeyK))
+                        .in_set(GameSets::Running),
+                    Self::handle_delete_confirm.in_set(GameSets::Running),
+                    Self::update_connection_badges.in_set(GameSets::Running),
+                    Self::check_text_args.in_set(GameSets::Running),
+                    (
+                        Self::toggle_disabled.run_if(control_just_pressed(KeyCode::Slash)),
+                        Self::cycle_concept_filter.run_if(control_just_pressed(KeyCode::KeyF)),
+                        Self::dim_blocks,
+                    )
+                        .chain()
+                        .in_set(GameSets::Running),
+                    Self::duplicate_block
+                        .run_if(control_just_pressed(KeyCode::KeyD))
+                        .before(Self::handle_spawn_ui_box)
+                        .in_set(GameSets::Running),
+                    Self::align_selection
+                        .run_if(|keyboard: Res<ButtonInput<KeyCode>>| {
+                            keyboard.any_pressed([KeyCode::AltLeft, KeyCode::AltRight])
+                        })
+                        .in_set(GameSets::Running),
+                    (
+                        Self::toggle_grid_snap.run_if(control_just_pressed(KeyCode::KeyG)),
+                        Self::draw_grid,
+                    )
+                        .in_set(GameSets::Running),
                     Self::delete_block
                         .run_if(
                             input_just_pressed(KeyCode::Backspace)
@@ -778,3 +2442,133 @@ impl Plugin for UIBoxPlugin {
             .add_plugins(TextInputPlugin);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bevy::ecs::system::RunSystemOnce;
+
+    use super::*;
+
+    #[test]
+    fn restores_the_connectors_of_a_block_taken_out_of_a_hole() {
+        let mut world = World::new();
+        world.init_resource::<BlockDataMap>();
+        world.init_resource::<Events<SpawnConnector>>();
+        world.init_resource::<Events<UpdateAst>>();
+        world.insert_resource(UiScale(2.));
+        let print = Language::new()
+            .get_block("Print")
+            .expect("The Print block should exist");
+        let background = world.spawn(BackgroundBox).id();
+        let owner = world.spawn_empty().id();
+        let hole = world
+            .spawn(Hole {
+                owner,
+                order: 0,
+                hole_type: HoleType::Any,
+            })
+            .id();
+        let arg = world
+            .spawn((
+                Arg {
+                    owner: hole,
+                    order: 0,
+                },
+                print.clone(),
+                GlobalTransform::from_xyz(100., 60., 0.),
+                Node::default(),
+                Position::default(),
+                Style::default(),
+            ))
+            .id();
+        world.insert_resource(DragEntity {
+            entity: Some(arg),
+            drag_start: None,
+        });
+        world.insert_resource(HoverEntity {
+            entity: Some(background),
+        });
+
+        world.run_system_once(UIBoxPlugin::handle_outside_hole);
+
+        assert!(world.get::<Arg>(arg).is_none());
+        assert_eq!(
+            world.get::<Position>(arg).map(|position| position.0),
+            Some(Vec2::new(50., 30.))
+        );
+        let connectors = world
+            .resource_mut::<Events<SpawnConnector>>()
+            .drain()
+            .map(|event| (event.connector.fixture, event.connector.direction))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            connectors,
+            print
+                .connectors
+                .iter()
+                .map(|&direction| (arg, direction))
+                .collect::<Vec<_>>()
+        );
+        assert!(!connectors.is_empty());
+    }
+
+    #[test]
+    fn finds_a_block_two_holes_up() {
+        let mut world = World::new();
+        let hole = |owner| Hole {
+            owner,
+            order: 0,
+            hole_type: HoleType::Any,
+        };
+        let top = world.spawn_empty().id();
+        let outer_hole = world.spawn(hole(top)).id();
+        let middle = world
+            .spawn(Arg {
+                owner: outer_hole,
+                order: 0,
+            })
+            .id();
+        let inner_hole = world.spawn(hole(middle)).id();
+        let inner = world
+            .spawn(Arg {
+                owner: inner_hole,
+                order: 0,
+            })
+            .id();
+
+        let is_ancestor = |world: &mut World, ancestor, block| {
+            world.run_system_once(move |args: Query<&Arg>, holes: Query<(Entity, &Hole)>| {
+                UIBoxPlugin::is_arg_ancestor(ancestor, block, &args, &holes)
+            })
+        };
+        assert!(is_ancestor(&mut world, top, inner));
+        assert!(is_ancestor(&mut world, middle, inner));
+        assert!(!is_ancestor(&mut world, inner, top));
+        assert!(!is_ancestor(&mut world, inner, middle));
+    }
+
+    #[test]
+    fn knows_when_a_hole_is_filled() {
+        let mut world = World::new();
+        let owner = world.spawn_empty().id();
+        let [filled, empty] = [0, 1].map(|order| {
+            world
+                .spawn(Hole {
+                    owner,
+                    order,
+                    hole_type: HoleType::Any,
+                })
+                .id()
+        });
+        world.spawn(Arg {
+            owner: filled,
+            order: 0,
+        });
+
+        let is_filled = |world: &mut World, hole| {
+            world.run_system_once(move |args: Query<&Arg>| UIBoxPlugin::is_hole_filled(hole, &args))
+        };
+        assert!(is_filled(&mut world, filled));
+        assert!(!is_filled(&mut world, empty));
+    }
+}
@@ -1,14 +1,15 @@
-use bevy::prelude::*;
+use bevy::{input::common_conditions::input_just_pressed, prelude::*, window::PrimaryWindow};
 use serde::{Deserialize, Serialize};
 
 use crate::{
     ast::{AddToAst, RemoveFromAst},
+    camera::{CanvasView, MyCameraComponent},
     connectors::{ConnectionDirection, Connector},
-    focus::{DragEntity, DragState, FocusColor, LineFocusBundle},
-    translate_vec_to_world,
+    control_just_pressed,
+    focus::{ActiveEntity, DragEntity, DragState, Focus, FocusColor, HoverEntity, LineFocusBundle},
     ui_box::{Arg, BackgroundBox, Block},
-    utils::{BlockType, Position, Size},
-    DeleteEvent, GameSets,
+    utils::{get_relative_direction, point_line_collision, BlockType, Position, Size},
+    DeleteEvent, ErrorEvent, GameSets,
 };
 
 #[derive(Component, Debug, Clone, Copy)]
@@ -37,11 +38,7 @@ pub struct LineBundle {
 
 impl LineBundle {
     pub fn new(from: Entity, from_direction: ConnectionDirection, to: Entity) -> Self {
-        let color = match from_direction {
-            ConnectionDirection::Left => Color::BLUE,
-            ConnectionDirection::Right => Color::RED,
-            _ => Color::BLACK,
-        };
+        let color = from_direction.get_color();
         Self {
             line: UiLine {
                 from,
@@ -52,6 +49,14 @@ impl LineBundle {
             focus_bundle: LineFocusBundle::new(Color::RED, Color::GREEN, color),
         }
     }
+
+    /// A line between two blocks that are already connected
+    pub fn connected(line: UiLine) -> Self {
+        Self {
+            line,
+            ..Self::new(line.from, line.from_direction, line.to)
+        }
+    }
 }
 
 #[derive(Debug, Bundle)]
@@ -103,7 +108,56 @@ pub struct SpawnSegments(pub Entity, pub Vec<Segment>);
 
 pub struct UiLinePlugin;
 
-// TODO: Implement focusable for lines
+/// How close to the end of a line the cursor has to be to grab it
+const LINE_GRAB_DISTANCE: f32 = 10.;
+
+/// Gets the points of a path made of horizontal and vertical segments between two connection
+/// points. The path leaves and enters along the axis of the connection direction
+fn get_orthogonal_points(
+    (from, from_direction): (Vec2, ConnectionDirection),
+    (to, to_direction): (Vec2, ConnectionDirection),
+) -> Vec<Vec2> {
+    let difference = to - from;
+    if difference.x == 0. || difference.y == 0. {
+        return vec![from, to];
+    }
+
+    let is_horizontal = |direction| match direction {
+        ConnectionDirection::Left | ConnectionDirection::Right => true,
+        ConnectionDirection::Top | ConnectionDirection::Bottom => false,
+        ConnectionDirection::Center => difference.x.abs() > difference.y.abs(),
+    };
+    let from_horizontal = is_horizontal(from_direction);
+    // INFO: A line that isn't connected yet follows the axis of the connector it started from
+    let to_horizontal = match to_direction {
+        ConnectionDirection::Center => from_horizontal,
+        direction => is_horizontal(direction),
+    };
+
+    match (from_horizontal, to_horizontal) {
+        (true, true) => {
+            let middle_x = from.x + difference.x / 2.;
+            vec![
+                from,
+                Vec2::new(middle_x, from.y),
+                Vec2::new(middle_x, to.y),
+                to,
+            ]
+        }
+        (false, false) => {
+            let middle_y = from.y + difference.y / 2.;
+            vec![
+                from,
+                Vec2::new(from.x, middle_y),
+                Vec2::new(to.x, middle_y),
+                to,
+            ]
+        }
+        (true, false) => vec![from, Vec2::new(to.x, from.y), to],
+        (false, true) => vec![from, Vec2::new(from.x, to.y), to],
+    }
+}
+
 impl UiLinePlugin {
     fn configure_line(mut gizmos_store: ResMut<GizmoConfigStore>) {
         let (line_config, _) = gizmos_store.config_mut::<LineGizmos>();
@@ -157,6 +211,161 @@ impl UiLinePlugin {
         }
     }
 
+    /// Grabbing the end of a connected line disconnects it and draws it again from its connector,
+    /// so it connects to the block it is released on or is deleted when it's released on nothing
+    #[allow(clippy::too_many_arguments)]
+    fn grab_line_end(
+        mut lines: Query<(Entity, &mut UiLine, &Children)>,
+        segments: Query<&Segment>,
+        mut connectors: Query<
+            (Entity, &Connector, &mut GlobalTransform),
+            Without<MyCameraComponent>,
+        >,
+        interactions: Query<&Interaction, (With<Focus>, Without<BackgroundBox>)>,
+        windows: Query<&Window, With<PrimaryWindow>>,
+        view: CanvasView,
+        ui_scale: Res<UiScale>,
+        mut active_drawing: ResMut<ActivelyDrawingLine>,
+        mut drag: ResMut<DragEntity>,
+        mut next_state: ResMut<NextState<DragState>>,
+        mut remove_from_ast_writer: EventWriter<RemoveFromAst>,
+    ) {
+        // INFO: The end of a line is on the edge of its block, pressing the block drags it instead
+        if active_drawing.entity.is_some()
+            || interactions
+                .iter()
+                .any(|interaction| matches!(interaction, Interaction::Pressed))
+        {
+            return;
+        }
+        let Some(cursor) = windows
+            .get_single()
+            .ok()
+            .and_then(|window| window.cursor_position())
+        else {
+            return;
+        };
+        let world_cursor = view.cursor_to_world(cursor);
+        let Some((line_entity, mut line)) = lines
+            .iter_mut()
+            .filter(|(_, line, _)| !connectors.contains(line.to))
+            .find(|(_, _, children)| {
+                segments.iter_many(*children).last().is_some_and(|segment| {
+                    view.to_world(segment.to).distance(world_cursor) <= LINE_GRAB_DISTANCE
+                })
+            })
+            .map(|(entity, line, _)| (entity, line))
+        else {
+            return;
+        };
+        let Some((connector_entity, _, mut connector_transform)) =
+            connectors.iter_mut().find(|(_, connector, _)| {
+                connector.fixture == line.from && connector.direction == line.from_direction
+            })
+        else {
+            return;
+        };
+
+        remove_from_ast_writer.send(RemoveFromAst {
+            parent: Some((line.from, line.from_direction.get_parse_order())),
+            child: line.to,
+        });
+        line.to = connector_entity;
+        line.to_direction = ConnectionDirection::Center;
+        active_drawing.entity = Some(line_entity);
+
+        // INFO: The connector is put under the cursor and dragged like it was pressed
+        drag.entity = Some(connector_entity);
+        drag.drag_start = Some(connector_transform.translation().xy());
+        let translation = (cursor / ui_scale.0).extend(connector_transform.translation().z);
+        *connector_transform = GlobalTransform::from_translation(translation);
+        next_state.set(DragState::Started);
+    }
+
+    /// A block that is dropped on a line is put in the flow between the two blocks of the line,
+    /// the line goes to the block and a new line goes from its bottom to where the line went
+    #[allow(clippy::too_many_arguments)]
+    fn insert_into_line(
+        drag: Res<DragEntity>,
+        hover: Res<HoverEntity>,
+        background: Query<(), With<BackgroundBox>>,
+        blocks: Query<(&Position, &Size, &BlockType), (With<Block>, Without<Arg>)>,
+        mut lines: Query<&mut UiLine>,
+        segments: Query<&Segment>,
+        windows: Query<&Window, With<PrimaryWindow>>,
+        view: CanvasView,
+        mut commands: Commands,
+        mut add_to_ast_writer: EventWriter<AddToAst>,
+        mut error_writer: EventWriter<ErrorEvent>,
+    ) {
+        let Some((entity, (position, size, block_type))) = drag
+            .entity
+            .and_then(|entity| Some((entity, blocks.get(entity).ok()?)))
+        else {
+            return;
+        };
+        // INFO: Only a block that isn't in the flow yet can be put in it
+        if !hover
+            .entity
+            .is_some_and(|entity| background.contains(entity))
+            || lines
+                .iter()
+                .any(|line| line.from == entity || line.to == entity)
+        {
+            return;
+        }
+        let Some(cursor) = windows
+            .get_single()
+            .ok()
+            .and_then(|window| window.cursor_position())
+            .map(|cursor| view.cursor_to_world(cursor))
+        else {
+            return;
+        };
+        let Some(mut line) = segments
+            .iter()
+            .find(|segment| {
+                let from = view.to_world(segment.from);
+                let to = view.to_world(segment.to);
+                point_line_collision((from, to), cursor, Some(5.))
+            })
+            .and_then(|segment| lines.get_mut(segment.owner).ok())
+        else {
+            return;
+        };
+        if !block_type.connectors.contains(&ConnectionDirection::Bottom) {
+            error_writer.send(ErrorEvent(format!(
+                "'{}' can't be put in the flow because it has no bottom connector",
+                block_type.name
+            )));
+            return;
+        }
+        let (Ok((from_pos, from_size, _)), Ok((to_pos, to_size, to_type))) =
+            (blocks.get(line.from), blocks.get(line.to))
+        else {
+            return;
+        };
+
+        let next = UiLine {
+            from: entity,
+            from_direction: ConnectionDirection::Bottom,
+            to: line.to,
+            to_direction: get_relative_direction((to_pos, to_size), (position, size)),
+        };
+        // INFO: Adding to the ast replaces the child that was in the place of the new one
+        add_to_ast_writer.send(AddToAst {
+            parent: Some((line.from, line.from_direction.get_parse_order())),
+            child: (entity, block_type.to_owned()),
+        });
+        add_to_ast_writer.send(AddToAst {
+            parent: Some((entity, next.from_direction.get_parse_order())),
+            child: (next.to, to_type.to_owned()),
+        });
+        line.to = entity;
+        line.to_direction = get_relative_direction((position, size), (from_pos, from_size));
+        commands.spawn(LineBundle::connected(next));
+    }
+
     fn handle_spawn_line(
         mut reader: EventReader<SpawnLineEvent>,
         mut active: ResMut<ActivelyDrawingLine>,
@@ -171,9 +380,6 @@ impl UiLinePlugin {
     fn handle_delete_line(
         mut reader: EventReader<DeleteLine>,
         mut commands: Commands,
-        parent: Query<(&Block, &Children)>,
-        lines: Query<&UiLine>,
-        mut connectors: Query<(&Connector, &mut Visibility), With<Connector>>,
         mut active: ResMut<ActivelyDrawingLine>,
     ) {
         for &DeleteLine(deleted_entity) in reader.read() {
@@ -182,21 +388,6 @@ impl UiLinePlugin {
                 continue;
             };
 
-            // INFO: Make connector visible if it has not despawned yet
-            let line = lines
-                .get(deleted_entity)
-                .expect("Expected the line to be deleted");
-
-            if let Ok((_, children)) = parent.get(line.from) {
-                for &child in children {
-                    if let Ok((connector, mut connector_visibility)) = connectors.get_mut(child) {
-                        if connector.direction == line.from_direction {
-                            *connector_visibility = Visibility::Visible;
-                        }
-                    }
-                }
-            }
-
             // Despawn the line
             entity_commands.despawn_recursive();
 
@@ -241,39 +432,52 @@ impl UiLinePlugin {
 
     fn make_segments(
         mut writer: EventWriter<SpawnSegments>,
+        mut delete_writer: EventWriter<DeleteLine>,
         lines: Query<(Entity, &UiLine)>,
         query: Query<(&Position, &Size)>,
-        changed_query: Query<(&Position, &Size), Or<(Changed<Position>, Changed<Size>)>>,
+        changed_query: Query<(), Or<(Changed<Position>, Changed<Size>)>>,
+        resized_background: Query<(), (With<BackgroundBox>, Changed<Node>)>,
+        entities: &Entities,
     ) {
+        // INFO: The lines are drawn relative to the size of the background so they are all
+        // rebuilt when the window is resized
+        let rebuild_all = !resized_background.is_empty();
         for (entity, line) in &lines {
-            let ((Position(from_pos), Size(from_size)), (Position(to_pos), Size(to_size))) =
-                if let Ok((&from_entity_pos, &from_entity_size)) = changed_query.get(line.from) {
-                    let (&to_pos, &to_size) = match changed_query.get(line.to).ok() {
-                        Some(to) => to,
-                        None => match query.get(line.to).ok() {
-                            Some(val) => val,
-                            None => continue,
-                        },
-                    };
-                    ((from_entity_pos, from_entity_size), (to_pos, to_size))
-                } else if let Ok((&to_entity_pos, &to_entity_size)) = changed_query.get(line.to) {
-                    let (&from_pos, &from_size) = query.get(line.from).unwrap();
-                    ((from_pos, from_size), (to_entity_pos, to_entity_size))
-                } else {
-                    continue;
-                };
+            let (
+                Ok((&Position(from_pos), &Size(from_size))),
+                Ok((&Position(to_pos), &Size(to_size))),
+            ) = (query.get(line.from), query.get(line.to))
+            else {
+                // INFO: A block that was despawned out from under the line takes the line with it
+                if !entities.contains(line.from) || !entities.contains(line.to) {
+                    delete_writer.send(DeleteLine(entity));
+                }
+                continue;
+            };
+            if !rebuild_all
+                && !changed_query.contains(line.from)
+                && !changed_query.contains(line.to)
+            {
+                continue;
+            }
 
             let segment_from_pos = from_pos + (from_size * (line.from_direction.get_vec() / 100.));
             let segment_to_pos = to_pos + (to_size * (line.to_direction.get_vec() / 100.));
 
-            writer.send(SpawnSegments(
-                entity,
-                vec![Segment {
-                    from: segment_from_pos,
-                    to: segment_to_pos,
+            let points = get_orthogonal_points(
+                (segment_from_pos, line.from_direction),
+                (segment_to_pos, line.to_direction),
+            );
+            let segments = points
+                .windows(2)
+                .map(|points| Segment {
+                    from: points[0],
+                    to: points[1],
                     owner: entity,
-                }],
-            ));
+                })
+                .collect();
+
+            writer.send(SpawnSegments(entity, segments));
         }
     }
 
@@ -281,20 +485,23 @@ impl UiLinePlugin {
         lines: Query<(&UiLine, &FocusColor, &Children)>,
         segments: Query<&Segment>,
         mut gizmos: Gizmos<LineGizmos>,
-        background: Query<&Node, With<BackgroundBox>>,
+        view: CanvasView,
     ) {
-        let background_size = background.single().size();
         for (_, focus_color, children) in &lines {
-            for &child in children {
-                let Ok(segment) = segments.get(child) else {
-                    continue;
-                };
-                let segment_from =
-                    translate_vec_to_world(segment.from, background_size.y, background_size.x);
-                let segment_to =
-                    translate_vec_to_world(segment.to, background_size.y, background_size.x);
-
-                gizmos.line_2d(segment_from, segment_to, focus_color.0);
+            let mut line_segments = segments.iter_many(children).peekable();
+            while let Some(segment) = line_segments.next() {
+                let segment_from = view.to_world(segment.from);
+                let segment_to = view.to_world(segment.to);
+
+                // INFO: The last segment ends at the block that is connected to so it gets the
+                // arrowhead that shows the direction of the flow
+                if line_segments.peek().is_none() {
+                    gizmos
+                        .arrow_2d(segment_from, segment_to, focus_color.0)
+                        .with_tip_length(10.);
+                } else {
+                    gizmos.line_2d(segment_from, segment_to, focus_color.0);
+                }
             }
         }
     }
@@ -306,27 +513,81 @@ impl UiLinePlugin {
         mut remove_from_ast_writer: EventWriter<RemoveFromAst>,
     ) {
         for &DeleteEvent(deleted_entity) in delete_reader.read() {
-            for (line_entity, line) in lines
-                .iter()
-                .filter(|(_, line)| line.from == deleted_entity || line.to == deleted_entity)
-            {
-                remove_from_ast_writer.send(RemoveFromAst {
-                    parent: Some((line.from, line.from_direction.get_parse_order())),
-                    child: line.to,
-                });
+            Self::disconnect_block(
+                deleted_entity,
+                &lines,
+                &mut delete_line_writer,
+                &mut remove_from_ast_writer,
+            );
+        }
+    }
 
-                delete_line_writer.send(DeleteLine(line_entity));
-            }
+    /// Ctrl+U deletes every line that starts or ends at the active block but keeps the block
+    fn disconnect_active_block(
+        active: Res<ActiveEntity>,
+        blocks: Query<(), With<Block>>,
+        lines: Query<(Entity, &UiLine)>,
+        mut delete_line_writer: EventWriter<DeleteLine>,
+        mut remove_from_ast_writer: EventWriter<RemoveFromAst>,
+    ) {
+        let Some(block) = active.entity.filter(|&entity| blocks.contains(entity)) else {
+            return;
+        };
+        Self::disconnect_block(
+            block,
+            &lines,
+            &mut delete_line_writer,
+            &mut remove_from_ast_writer,
+        );
+    }
+
+    fn disconnect_block(
+        block: Entity,
+        lines: &Query<(Entity, &UiLine)>,
+        delete_line_writer: &mut EventWriter<DeleteLine>,
+        remove_from_ast_writer: &mut EventWriter<RemoveFromAst>,
+    ) {
+        for (line_entity, line) in lines
+            .iter()
+            .filter(|(_, line)| line.from == block || line.to == block)
+        {
+            remove_from_ast_writer.send(RemoveFromAst {
+                parent: Some((line.from, line.from_direction.get_parse_order())),
+                child: line.to,
+            });
+
+            delete_line_writer.send(DeleteLine(line_entity));
         }
     }
 
+    fn delete_active_line(
+        mut active: ResMut<ActiveEntity>,
+        lines: Query<&UiLine>,
+        background: Query<Entity, With<BackgroundBox>>,
+        mut delete_line_writer: EventWriter<DeleteLine>,
+        mut remove_from_ast_writer: EventWriter<RemoveFromAst>,
+    ) {
+        let Some((line_entity, line)) = active
+            .entity
+            .and_then(|entity| Some((entity, lines.get(entity).ok()?)))
+        else {
+            return;
+        };
+
+        remove_from_ast_writer.send(RemoveFromAst {
+            parent: Some((line.from, line.from_direction.get_parse_order())),
+            child: line.to,
+        });
+        delete_line_writer.send(DeleteLine(line_entity));
+        active.entity = background.get_single().ok();
+    }
+
     fn draw_debug_make_segements(
-        background: Query<&Node, With<BackgroundBox>>,
+        view: CanvasView,
         mut gizmos: Gizmos<LineGizmos>,
         query: Query<(&Position, &Size)>,
         lines: Query<&UiLine>,
     ) {
-        let background_size = background.single().size();
         for line in &lines {
             let (&Position(from_pos), &Size(from_size)) =
                 query.get(line.from).expect("Expected to be in world tree");
@@ -334,10 +595,8 @@ impl UiLinePlugin {
                 .get(line.to)
                 .expect("Expected to be in the world tree");
 
-            let from_pos = translate_vec_to_world(from_pos, background_size.y, background_size.x)
-                + from_size * Vec2::new(0.5, -0.5);
-            let to_pos = translate_vec_to_world(to_pos, background_size.y, background_size.x)
-                + to_size * Vec2::new(0.5, -0.5);
+            let from_pos = view.to_world(from_pos) + from_size * Vec2::new(0.5, -0.5);
+            let to_pos = view.to_world(to_pos) + to_size * Vec2::new(0.5, -0.5);
             let from_size = from_size + 40.;
             let to_size = to_size + 40.;
 
@@ -383,10 +642,72 @@ impl Plugin for UiLinePlugin {
                     )
                         .chain()
                         .in_set(GameSets::Running),
+                    Self::grab_line_end
+                        .run_if(
+                            in_state(DragState::Ended)
+                                .and_then(input_just_pressed(MouseButton::Left)),
+                        )
+                        .in_set(GameSets::Running),
                     Self::handle_connected_delete.in_set(GameSets::Despawn),
+                    Self::disconnect_active_block
+                        .run_if(control_just_pressed(KeyCode::KeyU))
+                        .in_set(GameSets::Despawn),
+                    Self::delete_active_line
+                        .run_if(
+                            input_just_pressed(KeyCode::Backspace)
+                                .or_else(input_just_pressed(KeyCode::Delete)),
+                        )
+                        .in_set(GameSets::Despawn),
                 ),
             )
-            .add_systems(OnExit(DragState::Started), Self::handle_mouse_release);
+            .add_systems(
+                OnExit(DragState::Started),
+                (Self::handle_mouse_release, Self::insert_into_line),
+            );
         // .add_systems(Last, Self::draw_debug_make_segements);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removes_the_lines_of_a_despawned_block() {
+        let mut app = App::new();
+        app.init_resource::<ActivelyDrawingLine>()
+            .add_event::<SpawnSegments>()
+            .add_event::<DeleteLine>()
+            .add_systems(
+                Update,
+                (
+                    UiLinePlugin::make_segments,
+                    UiLinePlugin::handle_delete_line,
+                )
+                    .chain(),
+            );
+        let from = app
+            .world
+            .spawn((Block, Position(Vec2::ZERO), Size(Vec2::splat(40.))))
+            .id();
+        let to = app
+            .world
+            .spawn((Block, Position(Vec2::new(0., 100.)), Size(Vec2::splat(40.))))
+            .id();
+        let line = app
+            .world
+            .spawn(LineBundle::connected(UiLine {
+                from,
+                from_direction: ConnectionDirection::Bottom,
+                to,
+                to_direction: ConnectionDirection::Top,
+            }))
+            .id();
+        app.update();
+        assert!(app.world.get_entity(line).is_some());
+
+        app.world.despawn(to);
+        app.update();
+        assert!(app.world.get_entity(line).is_none());
+    }
+}
@@ -2,14 +2,22 @@
 mod size;
 // mod temp_line;
 
-use std::{fmt::Debug, fs};
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    fs,
+    path::{Path, PathBuf},
+};
 
 use serde::{Deserialize, Serialize};
 pub use size::*;
 // pub use temp_line::*;
 
 use bevy::{
-    math::bounding::Aabb2d, prelude::*, render::render_resource::encase::rts_array::Length,
+    math::bounding::{Aabb2d, IntersectsVolume},
+    prelude::*,
+    render::render_resource::encase::rts_array::Length,
+    utils::{HashMap, HashSet},
 };
 
 use crate::connectors::ConnectionDirection;
@@ -66,6 +74,9 @@ use crate::connectors::ConnectionDirection;
 //     Diamond,
 // }
 
+/// The comparison operators that every language supports
+pub const COMPARITORS: [&str; 6] = [">", "<", ">=", "<=", "==", "!="];
+
 #[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
 pub enum HoleType {
     #[default]
@@ -77,6 +88,8 @@ pub enum HoleType {
     Comparitor,
     Variable,
     Type(String),
+    /// Comma separated values that each match the inner type e.g. `1, 2, 3`
+    List(Box<HoleType>),
 }
 
 impl HoleType {
@@ -85,20 +98,59 @@ impl HoleType {
             return false;
         }
         match self {
-            HoleType::Number => value.parse::<u128>().is_ok() || value.parse::<f64>().is_ok(),
+            HoleType::Number => is_number_literal(value),
             HoleType::String => true,
             HoleType::Bool => value.eq("true") || value.eq("false"),
-            HoleType::Comparitor => matches!(value, ">" | "<" | "==" | "!="),
+            HoleType::Comparitor => COMPARITORS.contains(&value),
             HoleType::Variable => {
                 matches!(value.chars().next().unwrap(), 'a'..='z' | 'A'..='Z' | '_')
                     && !value.contains(char::is_whitespace)
             }
+            HoleType::List(inner) => split_list(value)
+                .into_iter()
+                .all(|item| inner.valid_input(item)),
             _ => true,
         }
     }
 
+    /// Checks if a block whose value has the type `value_type` can be put in this hole. A list
+    /// hole also takes a single element which becomes a list with one item. A variable can hold
+    /// a value of any type so it goes in the holes of the value types
+    pub fn accepts(&self, value_type: &HoleType) -> bool {
+        match (self, value_type) {
+            (HoleType::Any, _) => true,
+            (HoleType::Number | HoleType::String | HoleType::Bool, HoleType::Variable) => true,
+            (HoleType::List(inner), HoleType::List(value_inner)) => {
+                **value_inner == HoleType::Any || inner.accepts(value_inner)
+            }
+            (HoleType::List(inner), value_type) => inner.accepts(value_type),
+            (hole_type, value_type) => hole_type == value_type,
+        }
+    }
+
+    /// The value that an empty hole of this type is filled with. The types without a default
+    /// have to be filled in before the code can be generated
+    pub fn default_value(&self) -> Option<&'static str> {
+        match self {
+            HoleType::Number => Some("0"),
+            // INFO: The templates put the quotes around the string holes
+            HoleType::String => Some(""),
+            HoleType::List(_) => Some("[]"),
+            _ => None,
+        }
+    }
+
     // This function tries to get the HoleType from the value
     pub fn get_derived_type(value: &str) -> Self {
+        if value.starts_with('[') && value.ends_with(']') {
+            let mut types = split_list(value).into_iter().map(Self::get_derived_type);
+            let first = types.next().unwrap_or_default();
+            return if types.all(|item_type| item_type == first) {
+                Self::List(Box::new(first))
+            } else {
+                Self::List(Box::new(Self::Any))
+            };
+        }
         match value {
             e if HoleType::Number.valid_input(e) => Self::Number,
             e if HoleType::Bool.valid_input(e) => Self::Bool,
@@ -109,6 +161,53 @@ impl HoleType {
     }
 }
 
+/// Splits a list value into its trimmed items, the surrounding brackets are optional so both
+/// `1, 2` and `[1, 2]` give `["1", "2"]`
+pub fn split_list(value: &str) -> Vec<&str> {
+    let value = value.trim();
+    let value = value
+        .strip_prefix('[')
+        .and_then(|value| value.strip_suffix(']'))
+        .unwrap_or(value)
+        .trim();
+    if value.is_empty() {
+        return Vec::new();
+    }
+    value.split(',').map(str::trim).collect()
+}
+
+/// Checks if the value is a number literal that can be put in the generated code as is.
+///
+/// The valid forms are:
+/// - Integers with an optional sign e.g. `5`, `-5`, `+5`
+/// - Decimals e.g. `3.14`, `-0.5`, `.5`
+/// - Scientific notation e.g. `1e3`, `2.5E-4`
+/// - Hexadecimal integers with an optional sign e.g. `0xFF`, `-0x1f`
+///
+/// `NaN`, `inf` and anything that doesn't parse as a single number (like `1.2.3`) are rejected
+pub fn is_number_literal(value: &str) -> bool {
+    let unsigned = value
+        .strip_prefix('-')
+        .or_else(|| value.strip_prefix('+'))
+        .unwrap_or(value);
+
+    if let Some(hex) = unsigned
+        .strip_prefix("0x")
+        .or_else(|| unsigned.strip_prefix("0X"))
+    {
+        return !hex.is_empty() && hex.chars().all(|char| char.is_ascii_hexdigit());
+    }
+
+    // This rules out the words that f64 accepts like `NaN` and `inf`
+    let only_numeric_chars = unsigned
+        .chars()
+        .all(|char| char.is_ascii_digit() || matches!(char, '.' | 'e' | 'E' | '+' | '-'));
+
+    only_numeric_chars
+        && unsigned.starts_with(|char: char| char.is_ascii_digit() || char == '.')
+        && value.parse::<f64>().is_ok_and(f64::is_finite)
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
 pub enum ConceptType {
     #[default]
@@ -117,13 +216,31 @@ pub enum ConceptType {
     Output,
 }
 
-impl ConceptType {
-    pub fn get_color(&self) -> Color {
-        match self {
-            ConceptType::ControlFlow => Color::rgb_u8(170, 203, 253),
-            ConceptType::Input => Color::rgb_u8(208, 227, 218),
-            ConceptType::Output => Color::rgb_u8(252, 240, 137),
-        }
+/// How a block changes the indentation of the blocks that come after it in the flow
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Scope {
+    #[default]
+    None,
+    /// The blocks after this one are nested one level deeper e.g. the start of a loop
+    Open,
+    /// This block and the ones after it are one level less nested e.g. the end of a loop
+    Close,
+}
+
+/// The smallest and largest value of a number hole, `hole` counts from 1 like the placeholders
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub struct NumberRange {
+    pub hole: usize,
+    #[serde(default)]
+    pub min: Option<f64>,
+    #[serde(default)]
+    pub max: Option<f64>,
+}
+
+impl NumberRange {
+    pub fn clamp(&self, value: f64) -> f64 {
+        let value = self.min.map_or(value, |min| value.max(min));
+        self.max.map_or(value, |max| value.min(max))
     }
 }
 
@@ -140,11 +257,64 @@ pub struct BlockType {
     pub in_hole: bool,
     pub value: HoleType,
     pub concept_type: ConceptType,
+    #[serde(default)]
+    pub scope: Scope,
+    #[serde(default)]
+    pub number_ranges: Vec<NumberRange>,
+    /// What the holes are called in error messages, in the same order as `holes`
+    #[serde(default)]
+    pub hole_names: Vec<String>,
 }
 
-#[derive(Debug, Resource, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Language {
+    pub name: String,
     pub blocks: Vec<BlockType>,
+    /// Comparison operators this language supports on top of [`COMPARITORS`] e.g. `===`
+    #[serde(default)]
+    pub comparitors: Vec<String>,
+    /// Keywords that can't be used as variable names
+    #[serde(default)]
+    pub reserved_words: HashSet<String>,
+    /// What is put in front of a line for every level it is nested, e.g. four spaces for python.
+    /// Languages that don't care about indentation can leave this empty
+    #[serde(default)]
+    pub indent: String,
+    /// The file extension of the generated code
+    #[serde(default)]
+    pub extension: String,
+    /// What each character is replaced with when it is put inside a string literal
+    #[serde(default = "default_escapes")]
+    pub escapes: BTreeMap<char, String>,
+    /// What an empty `Any` hole is filled with, e.g. `/* ? */`. Without it the hole is required
+    #[serde(default)]
+    pub placeholder: Option<String>,
+    /// What an empty hole or branch is replaced with in the generated code. It should be valid
+    /// code in the language, e.g. `...` in python, so the rest of the program still runs
+    #[serde(default = "default_missing")]
+    pub missing: String,
+    /// How a literal is written in this language when it differs from the blocks, e.g. `true`
+    /// is `True` in python
+    #[serde(default)]
+    pub literals: BTreeMap<String, String>,
+}
+
+fn default_missing() -> String {
+    "/* missing */".to_owned()
+}
+
+fn default_escapes() -> BTreeMap<char, String> {
+    [
+        ('\\', "\\\\"),
+        ('"', "\\\""),
+        ('\'', "\\'"),
+        ('\n', "\\n"),
+        ('\r', "\\r"),
+        ('\t', "\\t"),
+    ]
+    .into_iter()
+    .map(|(char, escaped)| (char, escaped.to_owned()))
+    .collect()
 }
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct LanguageData {
@@ -152,12 +322,171 @@ pub struct LanguageData {
     description: Option<String>,
 }
 
+/// Where to load the block language from. When there's no path the embedded language is used
+#[derive(Debug, Resource, Clone)]
+pub struct LanguageConfig {
+    pub path: Option<PathBuf>,
+}
+
+impl Default for LanguageConfig {
+    fn default() -> Self {
+        Self {
+            path: std::env::var_os("LEARNABLE_LANGUAGE").map(PathBuf::from),
+        }
+    }
+}
+
 impl Language {
     pub fn new() -> Self {
         let file = include_str!("../../blocks/javascript.toml");
-        let language: Language = toml::from_str(file).unwrap();
-        language
+        Self::parse(file).expect("The embedded language should always be valid")
+    }
+
+    pub fn python() -> Self {
+        let file = include_str!("../../blocks/python.toml");
+        Self::parse(file).expect("The embedded language should always be valid")
+    }
+
+    pub fn parse(file: &str) -> Result<Self, String> {
+        let language: Self = toml::from_str(file)
+            .map_err(|error| format!("Couldn't parse the language: {error}"))?;
+        language.validate()?;
+        Ok(language)
+    }
+
+    /// Checks that the placeholders in every template string are exactly `{{1}}` to `{{n}}`, where
+    /// `n` is the number of holes plus the number of branches of the block
+    pub fn validate(&self) -> Result<(), String> {
+        let errors = self
+            .blocks
+            .iter()
+            .filter_map(|block| {
+                let expected = block.get_holes() + block.get_branches();
+                let mut placeholders = block.get_placeholders();
+                placeholders.sort_unstable();
+                placeholders.dedup();
+                if placeholders.iter().copied().eq(1..=expected) {
+                    None
+                } else {
+                    Some(format!(
+                        "- {block} has {} holes and {} branches but its template uses the placeholders {placeholders:?}",
+                        block.get_holes(),
+                        block.get_branches(),
+                    ))
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "The language has invalid templates:\n{}",
+                errors.join("\n")
+            ))
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        let file = fs::read_to_string(path).map_err(|error| {
+            format!("Couldn't read the language at {}: {error}", path.display())
+        })?;
+        Self::parse(&file)
+    }
+
+    /// Checks the value against the hole type, including the extra rules defined by the language
+    pub fn valid_input(&self, hole_type: &HoleType, value: &str) -> bool {
+        match hole_type {
+            HoleType::Comparitor if self.is_comparitor(value) => true,
+            HoleType::Variable if self.is_reserved(value) => false,
+            HoleType::List(inner) => split_list(value)
+                .into_iter()
+                .all(|item| self.valid_input(inner, item)),
+            hole_type => hole_type.valid_input(value),
+        }
+    }
+
+    /// Puts the items of a list value in the list syntax of the language e.g. `[1, 2, 3]`
+    pub fn format_list(&self, inner: &HoleType, value: &str) -> String {
+        let items = split_list(value)
+            .into_iter()
+            .map(|item| match inner {
+                HoleType::String => format!("\"{}\"", self.escape_string(item)),
+                _ => self.format_literal(item),
+            })
+            .collect::<Vec<_>>();
+        format!("[{}]", items.join(", "))
+    }
+
+    /// The value that an empty hole of this type is filled with in this language
+    pub fn default_value(&self, hole_type: &HoleType) -> Option<String> {
+        match hole_type {
+            HoleType::Any => self.placeholder.clone(),
+            hole_type => hole_type.default_value().map(ToOwned::to_owned),
+        }
+    }
+
+    pub fn is_reserved(&self, value: &str) -> bool {
+        self.reserved_words.contains(value)
+    }
+
+    pub fn get_derived_type(&self, value: &str) -> HoleType {
+        if self.is_comparitor(value) {
+            return HoleType::Comparitor;
+        }
+        HoleType::get_derived_type(value)
+    }
+
+    pub fn format_literal(&self, value: &str) -> String {
+        self.literals
+            .get(value)
+            .cloned()
+            .unwrap_or_else(|| value.to_owned())
+    }
+
+    pub fn escape_string(&self, value: &str) -> String {
+        value
+            .chars()
+            .map(|char| match self.escapes.get(&char) {
+                Some(escaped) => escaped.clone(),
+                None => char.to_string(),
+            })
+            .collect()
+    }
+
+    /// Indents every non empty line of the code by `depth` levels
+    pub fn indent_code(&self, code: &str, depth: usize) -> String {
+        if self.indent.is_empty() || depth == 0 {
+            return code.to_owned();
+        }
+        let prefix = self.indent.repeat(depth);
+        code.lines()
+            .map(|line| {
+                if line.is_empty() {
+                    line.to_owned()
+                } else {
+                    format!("{prefix}{line}")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The comparison operators of every language followed by the ones of this language
+    pub fn get_comparitors(&self) -> Vec<String> {
+        COMPARITORS
+            .iter()
+            .map(|&comparitor| comparitor.to_owned())
+            .chain(self.comparitors.iter().cloned())
+            .collect()
     }
+
+    fn is_comparitor(&self, value: &str) -> bool {
+        self.comparitors
+            .iter()
+            .any(|comparitor| comparitor == value)
+    }
+
     pub fn get_block(&self, name: &str) -> Option<BlockType> {
         self.blocks
             .iter()
@@ -165,6 +494,20 @@ impl Language {
             .map(ToOwned::to_owned)
     }
 
+    /// The block of this language that has the same name as the block, so a program made of the
+    /// blocks of another language can be generated in this one
+    pub fn resolve_block(&self, block_type: &BlockType) -> Result<BlockType, String> {
+        if block_type.language == self.name {
+            return Ok(block_type.clone());
+        }
+        self.get_block(&block_type.name).ok_or_else(|| {
+            format!(
+                "{block_type} has no block in {}, so the program can't be generated in it",
+                self.name
+            )
+        })
+    }
+
     pub fn get_lang_data(&self) -> Vec<LanguageData> {
         self.blocks
             .iter()
@@ -180,6 +523,52 @@ impl Language {
     }
 }
 
+/// All the languages that are loaded, keyed by their name, and the one blocks are spawned from
+#[derive(Debug, Resource)]
+pub struct Languages {
+    pub map: HashMap<String, Language>,
+    active: String,
+}
+
+impl Languages {
+    pub fn new() -> Self {
+        let language = Language::new();
+        let active = language.name.clone();
+        let mut languages = Self {
+            map: HashMap::default(),
+            active,
+        };
+        languages.insert(language);
+        languages.insert(Language::python());
+        languages
+    }
+
+    /// Adds the language, replacing any language that has the same name
+    pub fn insert(&mut self, language: Language) {
+        self.map.insert(language.name.clone(), language);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Language> {
+        self.map.get(name)
+    }
+
+    pub fn active(&self) -> &Language {
+        self.map
+            .get(&self.active)
+            .expect("The active language should always be loaded")
+    }
+
+    /// Makes the language with this name the active one, returns false if it isn't loaded
+    pub fn set_active(&mut self, name: &str) -> bool {
+        if self.map.contains_key(name) {
+            name.clone_into(&mut self.active);
+            true
+        } else {
+            false
+        }
+    }
+}
+
 // #[derive(Component, Debug, Clone, Copy, Default)]
 // pub enum BlockType {
 //     #[default]
@@ -203,13 +592,81 @@ impl BlockType {
         self.in_hole
     }
 
+    /// The range of the number hole with this order, holes without one take any number
+    pub fn get_number_range(&self, order: usize) -> NumberRange {
+        self.number_ranges
+            .iter()
+            .find(|range| range.hole == order + 1)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The name of the hole with this order, unnamed holes are named after their position
+    pub fn get_hole_name(&self, order: usize) -> String {
+        self.hole_names
+            .get(order)
+            .cloned()
+            .unwrap_or_else(|| format!("hole {}", order + 1))
+    }
+
     #[inline]
     pub fn get_holes(&self) -> usize {
         self.holes.len()
     }
 
+    /// The number of connectors that are expanded inside the template instead of after it
+    pub fn get_branches(&self) -> usize {
+        self.connectors
+            .iter()
+            .filter(|&&connector| connector != ConnectionDirection::Bottom)
+            .count()
+    }
+
+    /// The connectors whose branches are expanded inside the template, in their parse order. The
+    /// first one fills the placeholder after the holes, the second the one after that and so on
+    pub fn get_branch_directions(&self) -> Vec<ConnectionDirection> {
+        let mut directions = self
+            .connectors
+            .iter()
+            .copied()
+            .filter(|&connector| connector != ConnectionDirection::Bottom)
+            .collect::<Vec<_>>();
+        directions.sort_by_key(ConnectionDirection::get_parse_order);
+        directions
+    }
+
+    /// Which branch of the block the connection with this parse order is, if the block has it
+    pub fn get_branch_index(&self, order: usize) -> Option<usize> {
+        self.get_branch_directions()
+            .iter()
+            .position(|direction| direction.get_parse_order() == order)
+    }
+
+    /// Gets the numbers of all the `{{n}}` placeholders in the template string
+    pub fn get_placeholders(&self) -> Vec<usize> {
+        self.template_string
+            .split("{{")
+            .skip(1)
+            .filter_map(|rest| rest.split_once("}}"))
+            .filter_map(|(number, _)| number.parse().ok())
+            .collect()
+    }
+
     pub fn has_text(&self) -> bool {
-        matches!(self.name.as_str(), "Text" | "String" | "Variable")
+        matches!(
+            self.name.as_str(),
+            "Text" | "String" | "Variable" | "Comment"
+        )
+    }
+
+    /// A comment holds a note about the diagram and is left out of the generated code
+    pub fn is_comment(&self) -> bool {
+        self.name == "Comment"
+    }
+
+    /// Variable names can't span lines but the text of the other text blocks can
+    pub fn is_multiline(&self) -> bool {
+        matches!(self.name.as_str(), "Text" | "String" | "Comment")
     }
 
     // #[inline]
@@ -258,20 +715,23 @@ pub fn print_events<E: Event + std::fmt::Debug>(mut reader: EventReader<E>) {
     }
 }
 
-/// This function checks if a line and a point is intersecting, It uses the distance from the two
-/// points to do so. If the addition of the distances are equal to length of the line then we can
-/// say that the point is colliding and even get where we are colliding
+/// This function checks if a line and a point is intersecting. The point is colliding when its
+/// distance from the closest point of the line is within the buffer, so the hit region is as
+/// thick at the ends of the line as in its middle
 pub fn point_line_collision(
     (from_vec, to_vec): (Vec2, Vec2),
     point: Vec2,
     buffer: Option<f32>,
 ) -> bool {
-    let line_len = from_vec.distance(to_vec);
-    let d1 = from_vec.distance(point);
-    let d2 = to_vec.distance(point);
-    let buffer = buffer.unwrap_or_default();
-    let added_dist = d1 + d2;
-    added_dist >= line_len - buffer && added_dist <= line_len + buffer
+    let line = to_vec - from_vec;
+    // INFO: How far along the line the closest point is, clamped so that it stays on the line
+    let along = if line == Vec2::ZERO {
+        0.
+    } else {
+        ((point - from_vec).dot(line) / line.length_squared()).clamp(0., 1.)
+    };
+    let closest = from_vec + line * along;
+    closest.distance(point) <= buffer.unwrap_or_default()
 }
 
 pub fn get_aabb2d(pos: &Position, size: &Size) -> Aabb2d {
@@ -280,36 +740,341 @@ pub fn get_aabb2d(pos: &Position, size: &Size) -> Aabb2d {
     Aabb2d::new(position, center_size)
 }
 
-/// This gets the relative direction of the target from the source
+/// How the selected blocks are lined up, the edges are those of the bounds of all of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alignment {
+    Left,
+    Right,
+    Top,
+    Bottom,
+    /// The centers are put on the same vertical line
+    CenterHorizontal,
+    /// The centers are put on the same horizontal line
+    CenterVertical,
+    /// The gaps between the blocks from left to right are made the same
+    DistributeHorizontal,
+    /// The gaps between the blocks from top to bottom are made the same
+    DistributeVertical,
+}
+
+/// Gives the new top left corners of the boxes, which are the top left corner and size of each
+/// box, in the same order
+pub fn align_positions(boxes: &[(Vec2, Vec2)], alignment: Alignment) -> Vec<Vec2> {
+    let Some((min, max)) = boxes
+        .iter()
+        .map(|&(position, size)| (position, position + size))
+        .reduce(|(min1, max1), (min2, max2)| (min1.min(min2), max1.max(max2)))
+    else {
+        return Vec::new();
+    };
+    let center = (min + max) / 2.;
+    let distribute = |axis: usize| {
+        let mut positions = boxes
+            .iter()
+            .map(|&(position, _)| position)
+            .collect::<Vec<_>>();
+        if boxes.len() < 3 {
+            return positions;
+        }
+        let mut order = (0..boxes.len()).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| boxes[a].0[axis].total_cmp(&boxes[b].0[axis]));
+        let total: f32 = boxes.iter().map(|&(_, size)| size[axis]).sum();
+        let gap = (max[axis] - min[axis] - total) / (boxes.len() - 1) as f32;
+        let mut next = min[axis];
+        for index in order {
+            positions[index][axis] = next;
+            next += boxes[index].1[axis] + gap;
+        }
+        positions
+    };
+    match alignment {
+        Alignment::DistributeHorizontal => distribute(0),
+        Alignment::DistributeVertical => distribute(1),
+        _ => boxes
+            .iter()
+            .map(|&(position, size)| match alignment {
+                Alignment::Left => Vec2::new(min.x, position.y),
+                Alignment::Right => Vec2::new(max.x - size.x, position.y),
+                Alignment::Top => Vec2::new(position.x, min.y),
+                Alignment::Bottom => Vec2::new(position.x, max.y - size.y),
+                Alignment::CenterHorizontal => Vec2::new(center.x - size.x / 2., position.y),
+                Alignment::CenterVertical => Vec2::new(position.x, center.y - size.y / 2.),
+                Alignment::DistributeHorizontal | Alignment::DistributeVertical => position,
+            })
+            .collect(),
+    }
+}
+
+/// How far apart the spots that are tried for a new block are
+const SPAWN_STEP: f32 = 20.;
+/// How many rings of spots around the start are tried before giving up
+const SPAWN_RINGS: i32 = 50;
+
+/// Finds the spot nearest to `start` where a block of `size` doesn't overlap any of the
+/// `occupied` boxes. The spots are tried in square rings around the start, when there's no free
+/// spot the block is put on the start anyway
+pub fn find_free_position(start: Vec2, size: Vec2, occupied: &[Aabb2d]) -> Vec2 {
+    let is_free = |position: &Vec2| {
+        let aabb = get_aabb2d(&Position(*position), &Size(size));
+        !occupied.iter().any(|other| other.intersects(&aabb))
+    };
+    for ring in 0..=SPAWN_RINGS {
+        let mut offsets = (-ring..=ring)
+            .flat_map(|x| (-ring..=ring).map(move |y| IVec2::new(x, y)))
+            .filter(|offset| offset.x.abs().max(offset.y.abs()) == ring)
+            .collect::<Vec<_>>();
+        offsets.sort_by_key(|offset| offset.length_squared());
+        if let Some(position) = offsets
+            .into_iter()
+            .map(|offset| start + offset.as_vec2() * SPAWN_STEP)
+            .find(is_free)
+        {
+            return position;
+        }
+    }
+    start
+}
+
+/// How close the horizontal and vertical offsets of the target have to be for it to count as
+/// being on a corner of the source
+const CORNER_TOLERANCE: f32 = 0.01;
+
+/// This gets the relative direction of the target from the source. The offset of the target is
+/// measured relative to the size of the source, so a target on a corner of the source is
+/// diagonal whatever the shape of the source is. When the target is on a corner the vertical
+/// direction wins, so a corner between the bottom and a side is `Bottom`. A target on the center
+/// of the source is `Center`
 pub fn get_relative_direction(
     (&Position(src_pos), &Size(src_size)): (&Position, &Size),
     (&Position(target_pos), &Size(target_size)): (&Position, &Size),
 ) -> ConnectionDirection {
     let src_center = src_pos + (src_size / 2.);
     let target_center = target_pos + (target_size / 2.);
-    let difference = target_center - src_center;
-    let normal_vector = difference.normalize_or_zero();
+    let offset = (target_center - src_center) / src_size.max(Vec2::ONE);
 
-    if normal_vector == Vec2::ZERO {
+    if offset == Vec2::ZERO {
         info!("They are the same going to return all");
         return ConnectionDirection::Center;
     }
-    let shifted_center = target_center + normal_vector;
-    let distance_from_top_edge = (shifted_center.y - target_pos.y).abs();
-    let distance_from_left_edge = (shifted_center.x - target_pos.x).abs();
-    let distance_from_bottom_edge = (shifted_center.y - (target_pos.y + target_size.y)).abs();
-    let distance_from_right_edge = (shifted_center.x - (target_pos.x + target_size.x)).abs();
+    let (horizontal, vertical) = (offset.x.abs(), offset.y.abs());
+    if horizontal - vertical > CORNER_TOLERANCE {
+        if offset.x > 0. {
+            ConnectionDirection::Right
+        } else {
+            ConnectionDirection::Left
+        }
+    } else if offset.y > 0. {
+        ConnectionDirection::Bottom
+    } else {
+        ConnectionDirection::Top
+    }
+}
 
-    match distance_from_top_edge
-        .min(distance_from_left_edge)
-        .min(distance_from_bottom_edge)
-        .min(distance_from_right_edge)
-    {
-        x if x == distance_from_right_edge => ConnectionDirection::Right,
-        x if x == distance_from_bottom_edge => ConnectionDirection::Bottom,
-        x if x == distance_from_left_edge => ConnectionDirection::Left,
-        x if x == distance_from_top_edge => ConnectionDirection::Top,
-        _ => unreachable!(),
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE: (Vec2, Vec2) = (Vec2::new(0., 0.), Vec2::new(100., 0.));
+
+    #[test]
+    fn point_near_the_middle_collides_within_the_buffer() {
+        assert!(point_line_collision(LINE, Vec2::new(50., 4.), Some(5.)));
+        assert!(!point_line_collision(LINE, Vec2::new(50., 6.), Some(5.)));
+    }
+
+    #[test]
+    fn point_near_the_ends_collides_within_the_buffer() {
+        assert!(point_line_collision(LINE, Vec2::new(1., -4.), Some(5.)));
+        assert!(point_line_collision(LINE, Vec2::new(99., 4.), Some(5.)));
+        assert!(!point_line_collision(LINE, Vec2::new(1., 6.), Some(5.)));
+    }
+
+    #[test]
+    fn point_past_the_ends_is_measured_from_the_end() {
+        assert!(point_line_collision(LINE, Vec2::new(-3., 0.), Some(5.)));
+        assert!(!point_line_collision(LINE, Vec2::new(104., 4.), Some(5.)));
+        assert!(point_line_collision(
+            (Vec2::ONE, Vec2::ONE),
+            Vec2::new(2., 1.),
+            Some(2.)
+        ));
+    }
+
+    #[test]
+    fn variable_is_accepted_in_a_number_hole() {
+        let variable = Language::new()
+            .get_block("Variable")
+            .expect("The Variable block should exist");
+        assert!(HoleType::Number.accepts(&variable.value));
+        assert!(HoleType::String.accepts(&variable.value));
+        assert!(HoleType::Bool.accepts(&variable.value));
+    }
+
+    #[test]
+    fn variable_is_rejected_where_it_is_not_a_value() {
+        assert!(!HoleType::Comparitor.accepts(&HoleType::Variable));
+        assert!(!HoleType::Unit.accepts(&HoleType::Variable));
+        // INFO: A variable hole still needs a variable, a number can't be assigned to
+        assert!(!HoleType::Variable.accepts(&HoleType::Number));
+    }
+
+    const BLOCK: (Position, Size) = (Position(Vec2::new(100., 100.)), Size(Vec2::new(200., 100.)));
+
+    /// The direction of a 10 by 10 connector whose center is at the point
+    fn direction_at(point: Vec2) -> ConnectionDirection {
+        let connector = (Position(point - 5.), Size(Vec2::splat(10.)));
+        get_relative_direction((&BLOCK.0, &BLOCK.1), (&connector.0, &connector.1))
+    }
+
+    #[test]
+    fn connectors_on_the_edges_get_the_direction_of_the_edge() {
+        assert_eq!(
+            direction_at(Vec2::new(200., 100.)),
+            ConnectionDirection::Top
+        );
+        assert_eq!(
+            direction_at(Vec2::new(200., 200.)),
+            ConnectionDirection::Bottom
+        );
+        assert_eq!(
+            direction_at(Vec2::new(100., 150.)),
+            ConnectionDirection::Left
+        );
+        assert_eq!(
+            direction_at(Vec2::new(300., 150.)),
+            ConnectionDirection::Right
+        );
+    }
+
+    #[test]
+    fn connectors_on_the_corners_get_the_vertical_direction() {
+        assert_eq!(
+            direction_at(Vec2::new(100., 100.)),
+            ConnectionDirection::Top
+        );
+        assert_eq!(
+            direction_at(Vec2::new(300., 100.)),
+            ConnectionDirection::Top
+        );
+        assert_eq!(
+            direction_at(Vec2::new(100., 200.)),
+            ConnectionDirection::Bottom
+        );
+        assert_eq!(
+            direction_at(Vec2::new(300., 200.)),
+            ConnectionDirection::Bottom
+        );
+    }
+
+    #[test]
+    fn centered_connector_is_in_the_center() {
+        assert_eq!(
+            direction_at(Vec2::new(200., 150.)),
+            ConnectionDirection::Center
+        );
+    }
+
+    #[test]
+    fn aligns_the_left_edges() {
+        let boxes = [
+            (Vec2::new(10., 0.), Vec2::splat(20.)),
+            (Vec2::new(50., 40.), Vec2::splat(30.)),
+        ];
+        assert_eq!(
+            align_positions(&boxes, Alignment::Left),
+            vec![Vec2::new(10., 0.), Vec2::new(10., 40.)]
+        );
+        assert_eq!(
+            align_positions(&boxes, Alignment::Bottom),
+            vec![Vec2::new(10., 50.), Vec2::new(50., 40.)]
+        );
+    }
+
+    #[test]
+    fn distributes_the_gaps_evenly() {
+        let boxes = [
+            (Vec2::new(0., 0.), Vec2::splat(10.)),
+            (Vec2::new(100., 0.), Vec2::splat(10.)),
+            (Vec2::new(20., 0.), Vec2::splat(10.)),
+        ];
+        assert_eq!(
+            align_positions(&boxes, Alignment::DistributeHorizontal),
+            vec![Vec2::new(0., 0.), Vec2::new(100., 0.), Vec2::new(50., 0.)]
+        );
+    }
+
+    #[test]
+    fn free_position_is_the_start_when_nothing_is_there() {
+        let position = find_free_position(Vec2::new(100., 100.), Vec2::splat(40.), &[]);
+        assert_eq!(position, Vec2::new(100., 100.));
+    }
+
+    #[test]
+    fn free_position_moves_off_the_blocks_that_are_in_the_way() {
+        let size = Vec2::splat(40.);
+        let mut occupied: Vec<Aabb2d> = Vec::new();
+        for _ in 0..3 {
+            let position = find_free_position(Vec2::ZERO, size, &occupied);
+            let aabb = get_aabb2d(&Position(position), &Size(size));
+            assert!(!occupied.iter().any(|other| other.intersects(&aabb)));
+            occupied.push(aabb);
+        }
+    }
+
+    #[test]
+    fn recognises_number_literals() {
+        for number in [
+            "0", "42", "-7", "+3", "1.5", ".5", "-0.25", "1e10", "2.5E-3", "0x1F",
+        ] {
+            assert!(is_number_literal(number), "{number} should be a number");
+        }
+        for not_number in [
+            "", "-", "abc", "NaN", "inf", "1e", "1.2.3", "--1", "0x", "1 000",
+        ] {
+            assert!(
+                !is_number_literal(not_number),
+                "{not_number} isn't a number"
+            );
+        }
+    }
+
+    #[test]
+    fn escapes_the_quotes_and_new_lines_of_a_string() {
+        let language = Language::new();
+        assert_eq!(
+            language.escape_string("say \"hi\"\nbye"),
+            r#"say \"hi\"\nbye"#
+        );
+        assert_eq!(
+            language.format_list(&HoleType::String, "[a\"b]"),
+            r#"["a\"b"]"#
+        );
+    }
+
+    #[test]
+    fn accepts_an_identifier_and_rejects_a_reserved_word() {
+        let language = Language::new();
+        assert!(language.valid_input(&HoleType::Variable, "total_2"));
+        assert!(language.valid_input(&HoleType::Variable, "_count"));
+        assert!(!language.valid_input(&HoleType::Variable, "2total"));
+        assert!(!language.valid_input(&HoleType::Variable, "while"));
+        // INFO: Only the words of the language are reserved
+        assert!(Language::python().valid_input(&HoleType::Variable, "var"));
+    }
+
+    #[test]
+    fn accepts_every_comparison_operator() {
+        let language = Language::new();
+        for comparitor in language.get_comparitors() {
+            assert!(
+                language.valid_input(&HoleType::Comparitor, &comparitor),
+                "{comparitor} should be accepted"
+            );
+            assert_eq!(language.get_derived_type(&comparitor), HoleType::Comparitor);
+        }
+        assert!(language.valid_input(&HoleType::Comparitor, "==="));
+        assert!(!language.valid_input(&HoleType::Comparitor, "=>"));
+        assert!(!Language::python().valid_input(&HoleType::Comparitor, "==="));
     }
 }
 
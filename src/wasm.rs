@@ -1,4 +1,5 @@
 use std::{
+    collections::VecDeque,
     sync::{Arc, Mutex},
     time::Duration,
 };
@@ -6,13 +7,14 @@ use std::{
 use bevy::{prelude::*, time::common_conditions::on_timer};
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
-use web_sys::{MessageEvent, WebSocket};
+use web_sys::{Blob, HtmlAnchorElement, MessageEvent, Url, WebSocket};
 
 use crate::{
+    code_panel::ShowCode,
     focus::InteractionFocusBundle,
-    ui_box::{BlockBundle, SpawnUIBox},
-    utils::{Language, LanguageData},
-    ErrorEvent,
+    ui_box::{BlockBundle, SpawnUIBox, SwitchLanguage},
+    utils::{LanguageData, Languages},
+    ErrorEvent, ServerErrorEvent,
 };
 
 impl ErrorEvent {
@@ -24,11 +26,65 @@ impl ErrorEvent {
     }
 }
 
+/// Makes the browser download a file with the contents
+pub fn download_file(file_name: &str, contents: &str) -> Result<(), JsValue> {
+    let window = web_sys::window().ok_or("There is no window")?;
+    let document = window.document().ok_or("There is no document")?;
+
+    let parts = js_sys::Array::of1(&JsValue::from_str(contents));
+    let blob = Blob::new_with_str_sequence(&parts)?;
+    let url = Url::create_object_url_with_blob(&blob)?;
+
+    let anchor = document
+        .create_element("a")?
+        .dyn_into::<HtmlAnchorElement>()?;
+    anchor.set_href(&url);
+    anchor.set_download(file_name);
+    anchor.click();
+
+    Url::revoke_object_url(&url)
+}
+
+fn get_clipboard() -> Result<JsValue, JsValue> {
+    let navigator = web_sys::window().ok_or("There is no window")?.navigator();
+    js_sys::Reflect::get(&navigator, &"clipboard".into())
+}
+
+/// Puts the text on the clipboard of the browser
+pub fn write_clipboard(text: &str) -> Result<(), JsValue> {
+    let clipboard = get_clipboard()?;
+    let write_text =
+        js_sys::Reflect::get(&clipboard, &"writeText".into())?.dyn_into::<js_sys::Function>()?;
+    write_text.call1(&clipboard, &text.into())?;
+    Ok(())
+}
+
+/// Reads the text on the clipboard of the browser, it is put in `destination` once the browser
+/// gives it back
+pub fn read_clipboard(destination: Arc<Mutex<Option<String>>>) -> Result<(), JsValue> {
+    let clipboard = get_clipboard()?;
+    let read_text =
+        js_sys::Reflect::get(&clipboard, &"readText".into())?.dyn_into::<js_sys::Function>()?;
+    let promise = read_text.call0(&clipboard)?.dyn_into::<js_sys::Promise>()?;
+    wasm_bindgen_futures::spawn_local(async move {
+        match wasm_bindgen_futures::JsFuture::from(promise).await {
+            Ok(text) => {
+                if let (Some(text), Ok(mut destination)) = (text.as_string(), destination.lock()) {
+                    *destination = Some(text);
+                }
+            }
+            Err(error) => error!("{error:?}"),
+        }
+    });
+    Ok(())
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 #[serde(tag = "type", content = "content")]
 pub enum Command {
     SpawnBlock(String),
     RunCode,
+    SwitchLanguage(String),
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -41,22 +97,79 @@ pub enum Message {
     Command(Command),
 }
 
-pub struct WS(pub Option<WebSocket>);
+/// Where the websocket connects to. When there is no `url` it is derived from the address the
+/// page was loaded from
+#[derive(Debug, Resource, Clone)]
+pub struct WsConfig {
+    pub url: Option<String>,
+    /// The `type` query parameter that is sent to the server
+    pub connection_type: String,
+}
 
-impl Default for WS {
+impl Default for WsConfig {
     fn default() -> Self {
-        let ws = match WebSocket::new("ws://localhost:3000/websocket?type=server") {
-            Ok(ws) => ws,
-            Err(error) => {
-                error!("{error:?}");
-                return WS(None);
-            }
+        Self {
+            url: None,
+            connection_type: "server".into(),
+        }
+    }
+}
+
+impl WsConfig {
+    fn location_url() -> Option<String> {
+        if !cfg!(target_family = "wasm") {
+            return None;
+        }
+        let location = web_sys::window()?.location();
+        let protocol = match location.protocol().ok()?.as_str() {
+            "https:" => "wss:",
+            _ => "ws:",
         };
-        WS(Some(ws))
+        Some(format!("{protocol}//{}/websocket", location.host().ok()?))
+    }
+
+    pub fn get_url(&self) -> String {
+        let url = self
+            .url
+            .clone()
+            .or_else(Self::location_url)
+            .unwrap_or_else(|| "ws://localhost:3000/websocket".into());
+        format!("{url}?type={}", self.connection_type)
+    }
+}
+
+pub struct WS(pub Option<WebSocket>);
+
+impl FromWorld for WS {
+    fn from_world(world: &mut World) -> Self {
+        let config = world.get_resource_or_insert_with(WsConfig::default);
+        Self::connect(&config.get_url())
     }
 }
 
 impl WS {
+    fn connect(url: &str) -> Self {
+        match WebSocket::new(url) {
+            Ok(ws) => WS(Some(ws)),
+            Err(error) => {
+                error!("{error:?}");
+                WS(None)
+            }
+        }
+    }
+
+    fn is_open(&self) -> bool {
+        self.0
+            .as_ref()
+            .is_some_and(|ws| ws.ready_state() == WebSocket::OPEN)
+    }
+
+    fn is_closed(&self) -> bool {
+        self.0
+            .as_ref()
+            .map_or(true, |ws| ws.ready_state() == WebSocket::CLOSED)
+    }
+
     fn send<T: Serialize>(&self, value: &T) -> bool {
         let value = match serde_json::to_string(value) {
             Ok(val) => val,
@@ -96,14 +209,43 @@ pub struct WrappedMessage {
     message: Message,
 }
 
+impl WrappedMessage {
+    pub fn new(message: Message) -> Self {
+        Self {
+            sender: "server",
+            message,
+        }
+    }
+}
+
+/// How many failed requests are kept to be resent, the oldest ones are dropped first
+const MAX_FAILED_REQUESTS: usize = 100;
+
 #[derive(Debug, Resource, Default)]
 struct FailedRequest {
-    requests: Vec<Message>,
+    requests: VecDeque<Message>,
+}
+
+impl FailedRequest {
+    fn push(&mut self, message: Message) {
+        if self.requests.len() >= MAX_FAILED_REQUESTS {
+            self.requests.pop_front();
+        }
+        self.requests.push_back(message);
+    }
 }
 
 #[derive(Debug, Resource, Default)]
 pub struct SocketSender(pub Arc<Mutex<Vec<Message>>>);
 
+/// Whether the websocket is connected to the server
+#[derive(Debug, Resource, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Online,
+    #[default]
+    Offline,
+}
+
 // #[derive(Debug)]
 // pub struct SocketReciever(pub Receiver<Message>);
 
@@ -111,6 +253,10 @@ pub struct WASMPlugin;
 
 impl WASMPlugin {
     fn set_wasm_handles(ws: NonSend<WS>, resource: Res<SocketSender>) {
+        // INFO: The handles only need to be set when the socket is opened or reopened
+        if !ws.is_changed() {
+            return;
+        }
         let Some(ws) = ws.get_socket() else {
             info!("Websocket is not yet opened");
             return;
@@ -151,6 +297,32 @@ impl WASMPlugin {
         });
         ws.set_onopen(Some(onopen_callback.as_ref().unchecked_ref()));
         onopen_callback.forget();
+        let onclose_callback = Closure::<dyn Fn()>::new(move || {
+            info!("socket closed");
+        });
+        ws.set_onclose(Some(onclose_callback.as_ref().unchecked_ref()));
+        onclose_callback.forget();
+        let onerror_callback = Closure::<dyn Fn()>::new(move || {
+            error!("There was an error with the socket");
+        });
+        ws.set_onerror(Some(onerror_callback.as_ref().unchecked_ref()));
+        onerror_callback.forget();
+    }
+
+    fn update_connection_state(ws: NonSend<WS>, mut state: ResMut<ConnectionState>) {
+        let new_state = if ws.is_open() {
+            ConnectionState::Online
+        } else {
+            ConnectionState::Offline
+        };
+        state.set_if_neq(new_state);
+    }
+
+    fn reconnect_socket(mut ws: NonSendMut<WS>, config: Res<WsConfig>) {
+        if ws.is_closed() {
+            info!("Trying to reconnect to the server");
+            *ws = WS::connect(&config.get_url());
+        }
     }
     fn handle_wasm_request(
         ws: NonSend<WS>,
@@ -158,29 +330,28 @@ impl WASMPlugin {
         mut failed_request: ResMut<FailedRequest>,
     ) {
         for event in request_reader.read().map(ToOwned::to_owned) {
-            let message = WrappedMessage {
-                sender: "server",
-                message: event.0,
-            };
+            let message = WrappedMessage::new(event.0);
             if !ws.send(&message) {
                 info!("failed to send the message");
-                failed_request.requests.push(message.message)
+                failed_request.push(message.message);
             }
         }
     }
 
-    fn handle_channel(
+    pub(crate) fn handle_channel(
         reciever: Res<SocketSender>,
         mut event_writer: EventWriter<SpawnUIBox>,
         mut error_writer: EventWriter<ErrorEvent>,
-        language: Res<Language>,
+        mut server_error_writer: EventWriter<ServerErrorEvent>,
+        mut switch_language_writer: EventWriter<SwitchLanguage>,
+        mut code_writer: EventWriter<ShowCode>,
+        languages: Res<Languages>,
     ) {
         if let Ok(mut message) = reciever.0.lock() {
             for message in message.drain(..) {
-                #[allow(clippy::single_match)]
                 match message {
                     Message::Command(Command::SpawnBlock(block)) => {
-                        if let Some(block) = language.get_block(&block) {
+                        if let Some(block) = languages.active().get_block(&block) {
                             event_writer.send(SpawnUIBox {
                                 bundle: BlockBundle::new(
                                     0.,
@@ -191,11 +362,23 @@ impl WASMPlugin {
                                     block,
                                 ),
                                 marker: None,
+                                text: None,
                             });
                         } else {
                             error_writer.send(ErrorEvent(format!("Couldn't spawn block {block}")));
                         }
                     }
+                    Message::Command(Command::SwitchLanguage(language)) => {
+                        switch_language_writer.send(SwitchLanguage(language));
+                    }
+                    // INFO: Problems found while running the code are shown like the other errors,
+                    // they aren't sent back so that the server doesn't get its own errors
+                    Message::Diagnostics(error) | Message::Error(error) => {
+                        server_error_writer.send(ServerErrorEvent(error));
+                    }
+                    Message::Code(code) => {
+                        code_writer.send(ShowCode(code));
+                    }
                     _ => {}
                 }
             }
@@ -215,19 +398,29 @@ impl WASMPlugin {
 impl Plugin for WASMPlugin {
     fn build(&self, app: &mut App) {
         // let (sender, reciever) = channel::<Message>();
-        app.init_non_send_resource::<WS>()
+        app.init_resource::<WsConfig>()
+            .init_non_send_resource::<WS>()
             // .insert_non_send_resource(SocketReciever(reciever))
             .insert_resource(SocketSender::default())
             .init_resource::<FailedRequest>()
+            .init_resource::<ConnectionState>()
             .add_event::<WASMRequest>()
             .add_systems(Startup, Self::set_wasm_handles)
             .add_systems(
                 Update,
                 (
+                    Self::reconnect_socket.run_if(on_timer(Duration::from_secs(2))),
                     Self::set_wasm_handles,
+                    Self::update_connection_state,
                     apply_deferred,
                     Self::handle_wasm_request,
-                    Self::resend_failed_requests.run_if(on_timer(Duration::from_secs(1))),
+                    // INFO: The failed requests are flushed as soon as the socket reconnects
+                    Self::resend_failed_requests
+                        .run_if(resource_equals(ConnectionState::Online))
+                        .run_if(
+                            on_timer(Duration::from_secs(1))
+                                .or_else(resource_changed::<ConnectionState>),
+                        ),
                     Self::handle_channel,
                 )
                     .chain(),